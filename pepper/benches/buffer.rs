@@ -0,0 +1,201 @@
+// run with `cargo bench -p pepper --bench buffer`
+// each scenario prints the average time its edit took
+
+use std::time::{Duration, Instant};
+
+use pepper::{
+    buffer::{Buffer, BufferCollection, BufferHandle, BufferProperties},
+    buffer_position::{BufferPosition, BufferRange},
+    events::EditorEventQueue,
+    word_database::WordDatabase,
+};
+
+struct Bench {
+    buffers: BufferCollection,
+    buffer_handle: BufferHandle,
+    word_database: WordDatabase,
+    events: EditorEventQueue,
+}
+impl Bench {
+    fn new(text: &str) -> Self {
+        let mut buffers = BufferCollection::default();
+        let buffer = buffers.add_new();
+        buffer.properties = BufferProperties::text();
+        let buffer_handle = buffer.handle();
+
+        let mut bench = Self {
+            buffers,
+            buffer_handle,
+            word_database: WordDatabase::new(),
+            events: EditorEventQueue::default(),
+        };
+        bench.insert(BufferPosition::zero(), text);
+        bench.buffer().commit_edits();
+        bench.flush_events();
+        bench
+    }
+
+    fn buffer(&mut self) -> &mut Buffer {
+        self.buffers.get_mut(self.buffer_handle)
+    }
+
+    fn insert(&mut self, position: BufferPosition, text: &str) -> BufferRange {
+        let buffer = self.buffers.get_mut(self.buffer_handle);
+        let mut events = self
+            .events
+            .writer()
+            .buffer_text_inserts_mut_guard(self.buffer_handle);
+        buffer.insert_text(&mut self.word_database, position, text, &mut events)
+    }
+
+    fn delete(&mut self, range: BufferRange) {
+        let buffer = self.buffers.get_mut(self.buffer_handle);
+        let mut events = self
+            .events
+            .writer()
+            .buffer_range_deletes_mut_guard(self.buffer_handle);
+        buffer.delete_range(&mut self.word_database, range, &mut events);
+    }
+
+    fn flush_events(&mut self) {
+        self.events = EditorEventQueue::default();
+    }
+}
+
+fn report(name: &str, iterations: u32, elapsed: Duration) {
+    let average = elapsed / iterations;
+    println!(
+        "{:<40} {:>10.3?} per edit ({} edits)",
+        name, average, iterations
+    );
+}
+
+fn minified_line(len: usize) -> String {
+    let chunk = "function f(a,b){return a.value+b.value*2;}var x=[1,2,3];";
+    let mut text = String::with_capacity(len + chunk.len());
+    while text.len() < len {
+        text.push_str(chunk);
+    }
+    text
+}
+
+fn source_lines(line_count: usize) -> String {
+    let mut text = String::new();
+    for i in 0..line_count {
+        text.push_str("    let value_");
+        text.push_str(&i.to_string());
+        text.push_str(" = compute(previous, 42) + other_value;\n");
+    }
+    text
+}
+
+fn type_into_huge_line() {
+    const ITERATIONS: u32 = 1000;
+    let mut bench = Bench::new(&minified_line(16 * 1024 * 1024));
+
+    let mut position = BufferPosition::line_col(0, 8 * 1024 * 1024);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let range = bench.insert(position, "a");
+        position = range.to;
+        bench.flush_events();
+    }
+    report("type into a 16MB line", ITERATIONS, start.elapsed());
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let from = BufferPosition::line_col(0, position.column_byte_index - 1);
+        bench.delete(BufferRange::between(from, position));
+        position = from;
+        bench.flush_events();
+    }
+    report("backspace in a 16MB line", ITERATIONS, start.elapsed());
+
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let range = bench.insert(position, "\n");
+        let to = range.to;
+        bench.delete(range);
+        bench.flush_events();
+        assert_eq!(1, to.line_index);
+    }
+    report("break and join a 16MB line", ITERATIONS, start.elapsed());
+}
+
+fn type_into_many_lines() {
+    const ITERATIONS: u32 = 1000;
+    let mut bench = Bench::new(&source_lines(200_000));
+
+    let mut position = BufferPosition::line_col(100_000, 4);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let range = bench.insert(position, "a");
+        position = range.to;
+        bench.flush_events();
+    }
+    report("type into a 200k lines file", ITERATIONS, start.elapsed());
+
+    let start = Instant::now();
+    for i in 0..ITERATIONS {
+        let position = BufferPosition::line_col(100_000 + i, 0);
+        bench.insert(position, "\n");
+        bench.flush_events();
+    }
+    report(
+        "break lines in a 200k lines file",
+        ITERATIONS,
+        start.elapsed(),
+    );
+
+    let start = Instant::now();
+    for i in 0..ITERATIONS {
+        let line_index = 100_000 + ITERATIONS - i;
+        let from = BufferPosition::line_col(line_index - 1, 0);
+        let to = BufferPosition::line_col(line_index, 0);
+        bench.delete(BufferRange::between(from, to));
+        bench.flush_events();
+    }
+    report(
+        "join lines in a 200k lines file",
+        ITERATIONS,
+        start.elapsed(),
+    );
+}
+
+fn paste_and_delete_many_lines() {
+    const ITERATIONS: u32 = 10;
+    let mut bench = Bench::new(&source_lines(500_000));
+    let text = source_lines(100_000);
+
+    let position = BufferPosition::line_col(250_000, 4);
+    let mut ranges = Vec::new();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        ranges.push(bench.insert(position, &text));
+        bench.flush_events();
+    }
+    report(
+        "paste 100k lines into 500k lines",
+        ITERATIONS,
+        start.elapsed(),
+    );
+
+    let start = Instant::now();
+    for range in ranges.into_iter().rev() {
+        bench.delete(range);
+        bench.flush_events();
+    }
+    report(
+        "delete 100k lines from 500k lines",
+        ITERATIONS,
+        start.elapsed(),
+    );
+
+    bench.buffer().commit_edits();
+}
+
+fn main() {
+    type_into_huge_line();
+    type_into_many_lines();
+    paste_and_delete_many_lines();
+}
@@ -100,7 +100,7 @@ impl ServerApplication {
         for event in events {
             match event {
                 PlatformEvent::Idle => {
-                    self.ctx.editor.on_idle();
+                    self.ctx.editor.on_idle(&self.ctx.clients);
                     self.ctx.trigger_event_handlers();
                 }
                 PlatformEvent::ConnectionOpen { handle } => {
@@ -166,6 +166,15 @@ impl ServerApplication {
                                 handle,
                             )
                         }
+                        ProcessTag::VcsBase { buffer_handle, id } => {
+                            self.ctx.editor.buffers.on_vcs_base_process_spawned(
+                                &mut self.ctx.platform,
+                                buffer_handle,
+                                id,
+                                handle,
+                            )
+                        }
+                        ProcessTag::BufferRead { .. } => (),
                     }
                     self.ctx.trigger_event_handlers();
                 }
@@ -196,6 +205,16 @@ impl ServerApplication {
                                 bytes,
                             )
                         }
+                        ProcessTag::VcsBase { buffer_handle, id } => self
+                            .ctx
+                            .editor
+                            .buffers
+                            .on_vcs_base_process_output(buffer_handle, id, bytes),
+                        ProcessTag::BufferRead { buffer_handle, id } => self
+                            .ctx
+                            .editor
+                            .buffers
+                            .on_read_process_output(buffer_handle, id, bytes),
                     }
                     self.ctx.trigger_event_handlers();
                     self.ctx.platform.buf_pool.release(buf);
@@ -217,6 +236,15 @@ impl ServerApplication {
                         ProcessTag::Plugin { plugin_handle, id } => {
                             PluginCollection::on_process_exit(&mut self.ctx, plugin_handle, id)
                         }
+                        ProcessTag::VcsBase { buffer_handle, id } => self
+                            .ctx
+                            .editor
+                            .buffers
+                            .on_vcs_base_process_exit(buffer_handle, id),
+                        ProcessTag::BufferRead { buffer_handle, id } => self
+                            .ctx
+                            .editor
+                            .on_buffer_read_process_exit(buffer_handle, id),
                     }
                     self.ctx.trigger_event_handlers();
                 }
@@ -246,6 +274,12 @@ impl ServerApplication {
             }
         }
 
+        let editor = &mut self.ctx.editor;
+        editor
+            .buffers
+            .spawn_read_processes(&mut self.ctx.platform, &editor.current_directory);
+        self.ctx.trigger_event_handlers();
+
         self.ctx.editor.events.assert_empty();
         self.ctx.render();
     }
@@ -1,18 +1,22 @@
 use std::{
-    fmt,
-    fs::File,
+    env, fmt,
+    fs::{self, File},
     io,
-    ops::{Add, Range, RangeBounds, Sub},
+    ops::{Add, Bound, Index, Range, RangeBounds, Sub},
     path::{Component, Path, PathBuf},
     process::{Command, Stdio},
+    slice,
     str::CharIndices,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use crate::{
     buffer_history::{BufferHistory, Edit, EditKind},
     buffer_position::{BufferPosition, BufferPositionIndex, BufferRange},
+    config::{Config, ConfigOverrides, GlobConfigOverrides, SaveBackup},
     cursor::Cursor,
-    editor_utils::{find_delimiter_pair_at, ResidualStrBytes},
+    editor_utils::{continue_hash_bytes, find_delimiter_pair_at, hash_bytes, ResidualStrBytes},
+    editorconfig::EditorConfig,
     events::{
         BufferEditMutGuard, BufferRangeDeletesMutGuard, BufferTextInsertsMutGuard, EditorEvent,
         EditorEventTextInsert, EditorEventWriter,
@@ -21,14 +25,106 @@ use crate::{
     pattern::Pattern,
     platform::{Platform, PlatformProcessHandle, PlatformRequest, PooledBuf, ProcessTag},
     plugin::PluginHandle,
+    serialization::Serialize,
     syntax::{HighlightResult, HighlightedBuffer, SyntaxCollection, SyntaxHandle},
+    unicode_width,
+    vcs::BufferVcsDiff,
     word_database::{WordDatabase, WordIter, WordKind},
 };
 
-// TODO: parse unicode database and implement this
-// reference algorithm: https://www.cl.cam.ac.uk/~mgk25/ucs/wcwidth.c
-pub fn char_display_len(_: char) -> u8 {
-    1
+pub fn char_display_len(c: char) -> u8 {
+    if c.is_ascii() {
+        1
+    } else if char_to_raw_byte(c).is_some() {
+        4
+    } else if unicode_width::is_zero_width(c) {
+        0
+    } else if unicode_width::is_wide(c) {
+        2
+    } else {
+        1
+    }
+}
+
+// bytes that are not valid utf8 are kept in the buffer as these private use chars
+// so that they can be displayed as `\xNN` and written back to the file unchanged
+const RAW_BYTE_CHAR_BASE: u32 = 0x10fe00;
+const RAW_BYTE_FIRST_CHAR: char = '\u{10fe80}';
+const RAW_BYTE_LAST_CHAR: char = '\u{10feff}';
+
+pub fn raw_byte_to_char(byte: u8) -> char {
+    debug_assert!(!byte.is_ascii());
+    char::from_u32(RAW_BYTE_CHAR_BASE + byte as u32).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+pub fn char_to_raw_byte(c: char) -> Option<u8> {
+    if (RAW_BYTE_FIRST_CHAR..=RAW_BYTE_LAST_CHAR).contains(&c) {
+        Some((c as u32 - RAW_BYTE_CHAR_BASE) as _)
+    } else {
+        None
+    }
+}
+
+// all raw byte chars are encoded in utf8 starting with this byte
+const RAW_BYTE_CHAR_LEAD_BYTE: u8 = 0xf4;
+
+// decodes `bytes` as utf8 while mapping each invalid byte to its raw byte char.
+// valid encodings of the raw byte chars themselves are also mapped byte by byte
+// so that writing the text back always reproduces the original bytes
+pub fn push_bytes_lossless(text: &mut String, mut bytes: &[u8]) {
+    fn push_valid(text: &mut String, valid: &str) {
+        if !valid.as_bytes().contains(&RAW_BYTE_CHAR_LEAD_BYTE) {
+            text.push_str(valid);
+            return;
+        }
+        for c in valid.chars() {
+            if char_to_raw_byte(c).is_some() {
+                let mut buf = [0; 4];
+                for &b in c.encode_utf8(&mut buf).as_bytes() {
+                    text.push(raw_byte_to_char(b));
+                }
+            } else {
+                text.push(c);
+            }
+        }
+    }
+
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                push_valid(text, valid);
+                return;
+            }
+            Err(error) => {
+                let (valid, rest) = bytes.split_at(error.valid_up_to());
+                if let Ok(valid) = std::str::from_utf8(valid) {
+                    push_valid(text, valid);
+                }
+                let invalid_len = error.error_len().unwrap_or(rest.len());
+                for &b in &rest[..invalid_len] {
+                    text.push(raw_byte_to_char(b));
+                }
+                bytes = &rest[invalid_len..];
+            }
+        }
+    }
+}
+
+// the inverse of `push_bytes_lossless`
+pub fn write_str_lossless(write: &mut dyn io::Write, text: &str) -> io::Result<()> {
+    if !text.as_bytes().contains(&RAW_BYTE_CHAR_LEAD_BYTE) {
+        return write.write_all(text.as_bytes());
+    }
+
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        if let Some(byte) = char_to_raw_byte(c) {
+            write.write_all(&text.as_bytes()[start..i])?;
+            write.write_all(&[byte])?;
+            start = i + c.len_utf8();
+        }
+    }
+    write.write_all(&text.as_bytes()[start..])
 }
 
 #[derive(Clone, Copy)]
@@ -50,6 +146,14 @@ impl DisplayLen {
 }
 impl<'a> From<&'a str> for DisplayLen {
     fn from(s: &'a str) -> Self {
+        if s.is_ascii() {
+            let tab_count = s.bytes().filter(|&b| b == b'\t').count() as u32;
+            return Self {
+                len: s.len() as u32 - tab_count,
+                tab_count,
+            };
+        }
+
         let mut len = 0;
         let mut tab_count = 0;
         for c in s.chars() {
@@ -610,6 +714,24 @@ impl BufferLine {
         self.0.truncate(index);
     }
 
+    pub fn push_line(
+        &mut self,
+        self_display_len: &mut DisplayLen,
+        other: &BufferLine,
+        other_display_len: DisplayLen,
+        index: usize,
+    ) {
+        let text = &other.0[index..];
+        let display_len = if index < text.len() {
+            other_display_len - DisplayLen::from(&other.0[..index])
+        } else {
+            DisplayLen::from(text)
+        };
+
+        self.0.push_str(text);
+        *self_display_len = *self_display_len + display_len;
+    }
+
     pub fn insert_text(&mut self, display_len: &mut DisplayLen, index: usize, text: &str) {
         self.0.insert_str(index, text);
         *display_len = *display_len + DisplayLen::from(text);
@@ -633,301 +755,892 @@ impl BufferLine {
     }
 }
 
-pub struct TextRangeIter<'a> {
-    content: &'a BufferContent,
-    from: BufferPosition,
-    to: BufferPosition,
-}
-impl<'a> Iterator for TextRangeIter<'a> {
-    type Item = &'a str;
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.from == self.to {
-            return None;
-        }
-
-        let line = self.content.lines[self.from.line_index as usize].as_str();
+const BUFFER_LINE_CHUNK_MAX_LEN: usize = 1024;
 
-        if self.from.column_byte_index == line.len() as _ {
-            self.from.line_index += 1;
-            self.from.column_byte_index = 0;
-            Some("\n")
-        } else if self.from.line_index == self.to.line_index {
-            let text =
-                &line[self.from.column_byte_index as usize..self.to.column_byte_index as usize];
-            self.from = self.to;
-            Some(text)
-        } else {
-            let text = &line[self.from.column_byte_index as usize..];
-            self.from.column_byte_index = line.len() as _;
-            Some(text)
-        }
-    }
+struct BufferLineChunk {
+    lines: Vec<BufferLine>,
+    display_lens: Vec<DisplayLen>,
 }
 
-pub struct BufferContent {
-    lines: Vec<BufferLine>,
-    line_display_lens: Vec<DisplayLen>,
-    line_pool: BufferLinePool,
+// lines are stored in chunks of at most `BUFFER_LINE_CHUNK_MAX_LEN` lines so that inserting or
+// removing lines only moves the lines of the chunks being edited instead of every line after them
+pub struct BufferLines {
+    chunks: Vec<BufferLineChunk>,
+    // index of the first line of each chunk
+    chunk_starts: Vec<usize>,
+    len: usize,
 }
 
-impl BufferContent {
-    pub fn new() -> Self {
+impl BufferLines {
+    const fn new() -> Self {
         Self {
-            lines: vec![BufferLine::new()],
-            line_display_lens: vec![DisplayLen::zero()],
-            line_pool: BufferLinePool::new(),
+            chunks: Vec::new(),
+            chunk_starts: Vec::new(),
+            len: 0,
         }
     }
 
-    pub fn lines(&self) -> &[BufferLine] {
-        &self.lines
+    pub fn len(&self) -> usize {
+        self.len
     }
 
-    pub fn line_display_lens(&self) -> &[DisplayLen] {
-        &self.line_display_lens
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
 
-    pub fn end(&self) -> BufferPosition {
-        let last_line_index = self.lines.len() - 1;
-        BufferPosition::line_col(
-            last_line_index as _,
-            self.lines[last_line_index].as_str().len() as _,
-        )
+    pub fn get(&self, index: usize) -> Option<&BufferLine> {
+        if index < self.len {
+            Some(&self[index])
+        } else {
+            None
+        }
     }
 
-    pub fn read(&mut self, read: &mut dyn io::BufRead) -> io::Result<()> {
-        for line in self.lines.drain(..) {
-            self.line_pool.release(line);
-        }
-        self.line_display_lens.clear();
+    pub fn iter(&self) -> BufferLinesIter<'_, BufferLine> {
+        self.range(..)
+    }
 
-        let mut push_empty = true;
-        loop {
-            let mut line = self.line_pool.acquire();
-            match read.read_line(&mut line.0) {
-                Ok(0) => {
-                    if push_empty {
-                        self.lines.push(line);
-                        self.line_display_lens.push(DisplayLen::zero());
-                    } else {
-                        self.line_pool.release(line);
-                    }
-                    break;
-                }
-                Ok(_) => {
-                    push_empty = false;
+    pub fn range<R>(&self, range: R) -> BufferLinesIter<'_, BufferLine>
+    where
+        R: RangeBounds<usize>,
+    {
+        self.chunks_range(range, |chunk| &chunk.lines)
+    }
 
-                    if line.0.ends_with('\n') {
-                        push_empty = true;
-                        line.0.pop();
-                    }
-                    if line.0.ends_with('\r') {
-                        line.0.pop();
-                    }
-                    let display_len = DisplayLen::from(&line.0[..]);
+    pub fn display_len(&self, index: usize) -> DisplayLen {
+        let (chunk_index, index) = self.locate(index);
+        self.chunks[chunk_index].display_lens[index]
+    }
 
-                    self.lines.push(line);
-                    self.line_display_lens.push(display_len);
-                }
-                Err(e) => {
-                    for line in self.lines.drain(..) {
-                        self.line_pool.release(line);
-                    }
-                    self.lines.push(self.line_pool.acquire());
-                    self.line_display_lens.clear();
-                    self.line_display_lens.push(DisplayLen::zero());
-                    return Err(e);
-                }
-            }
+    pub fn display_lens<R>(&self, range: R) -> BufferLinesIter<'_, DisplayLen>
+    where
+        R: RangeBounds<usize>,
+    {
+        self.chunks_range(range, |chunk| &chunk.display_lens)
+    }
+
+    fn chunks_range<R, T>(
+        &self,
+        range: R,
+        items: fn(&BufferLineChunk) -> &[T],
+    ) -> BufferLinesIter<'_, T>
+    where
+        R: RangeBounds<usize>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(&index) => index,
+            Bound::Excluded(&index) => index + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&index) => index + 1,
+            Bound::Excluded(&index) => index,
+            Bound::Unbounded => self.len,
+        };
+        if start > end || end > self.len {
+            panic!(
+                "line range {}..{} out of bounds for {} lines",
+                start, end, self.len
+            );
         }
 
-        let byte_order_mark = b"\xef\xbb\xbf";
-        if self.lines[0]
-            .as_str()
-            .as_bytes()
-            .starts_with(byte_order_mark)
-        {
-            self.lines[0].delete_range(&mut self.line_display_lens[0], ..byte_order_mark.len());
+        let mut iter = BufferLinesIter {
+            chunks: [].iter(),
+            front: [].iter(),
+            back: [].iter(),
+            len: end - start,
+            items,
+        };
+        if start == end {
+            return iter;
         }
 
-        Ok(())
+        let (start_chunk_index, start_index) = self.locate(start);
+        let (end_chunk_index, end_index) = self.locate(end - 1);
+        if start_chunk_index == end_chunk_index {
+            let items = items(&self.chunks[start_chunk_index]);
+            iter.front = items[start_index..=end_index].iter();
+        } else {
+            iter.front = items(&self.chunks[start_chunk_index])[start_index..].iter();
+            iter.chunks = self.chunks[start_chunk_index + 1..end_chunk_index].iter();
+            iter.back = items(&self.chunks[end_chunk_index])[..=end_index].iter();
+        }
+        iter
     }
 
-    pub fn write(&self, write: &mut dyn io::Write) -> io::Result<()> {
-        let end_index = self.lines.len() - 1;
-        for line in &self.lines[..end_index] {
-            write!(write, "{}\n", line.as_str())?;
+    // returns the index of the chunk containing the line at `index` and its index inside it
+    fn locate(&self, index: usize) -> (usize, usize) {
+        if index >= self.len {
+            panic!("line index {} out of bounds for {} lines", index, self.len);
         }
-        write!(write, "{}", self.lines[end_index].as_str())?;
-        Ok(())
+        let chunk_index = self.chunk_starts.partition_point(|&start| start <= index) - 1;
+        (chunk_index, index - self.chunk_starts[chunk_index])
     }
 
-    pub fn saturate_position(&self, mut position: BufferPosition) -> BufferPosition {
-        position.line_index = position.line_index.min((self.lines.len() - 1) as _);
-        let line = self.lines[position.line_index as usize].as_str();
-        position.column_byte_index = position.column_byte_index.min(line.len() as _);
-        position
+    fn get_mut(&mut self, index: usize) -> (&mut BufferLine, &mut DisplayLen) {
+        let (chunk_index, index) = self.locate(index);
+        let chunk = &mut self.chunks[chunk_index];
+        (&mut chunk.lines[index], &mut chunk.display_lens[index])
     }
 
-    pub fn text_range(&self, range: BufferRange) -> TextRangeIter {
-        let from = self.saturate_position(range.from);
-        let to = self.saturate_position(range.to);
-        TextRangeIter {
-            content: self,
-            from,
-            to,
+    fn push(&mut self, line: BufferLine, display_len: DisplayLen) {
+        match self.chunks.last_mut() {
+            Some(chunk) if chunk.lines.len() < BUFFER_LINE_CHUNK_MAX_LEN => {
+                chunk.lines.push(line);
+                chunk.display_lens.push(display_len);
+            }
+            _ => {
+                self.chunk_starts.push(self.len);
+                self.chunks.push(BufferLineChunk {
+                    lines: vec![line],
+                    display_lens: vec![display_len],
+                });
+            }
         }
+        self.len += 1;
     }
 
-    pub fn find_search_ranges(&self, pattern: &Pattern, ranges: &mut Vec<BufferRange>) {
-        if pattern.is_empty() {
-            return;
-        }
-        let search_anchor = pattern.search_anchor();
-        for (line_index, line) in self.lines.iter().enumerate() {
-            let line = line.as_str();
-            for range in pattern.match_indices(line, search_anchor) {
-                let from = BufferPosition::line_col(line_index as _, range.start as _);
-                let to = BufferPosition::line_col(line_index as _, range.end as _);
-                ranges.push(BufferRange::between(from, to));
+    // inserts `lines` so that the first of them ends up at `index`. returns how many were inserted
+    fn insert<I>(&mut self, index: usize, lines: I) -> usize
+    where
+        I: Iterator<Item = (BufferLine, DisplayLen)>,
+    {
+        if index == self.len {
+            let previous_len = self.len;
+            for (line, display_len) in lines {
+                self.push(line, display_len);
             }
+            return self.len - previous_len;
         }
-    }
 
-    pub fn insert_text(&mut self, position: BufferPosition, text: &str) -> BufferRange {
-        if !text.contains(&['\n', '\r']) {
-            let line = &mut self.lines[position.line_index as usize];
-            let display_len = &mut self.line_display_lens[position.line_index as usize];
+        let (chunk_index, index) = self.locate(index);
+        let chunk = &mut self.chunks[chunk_index];
 
-            let previous_len = line.as_str().len();
-            line.insert_text(display_len, position.column_byte_index as _, text);
-            let len_diff = line.as_str().len() - previous_len;
+        // new lines are appended and then rotated into place so that inserting
+        // many lines only moves the lines after them once
+        let previous_len = chunk.lines.len();
+        for (line, display_len) in lines {
+            chunk.lines.push(line);
+            chunk.display_lens.push(display_len);
+        }
+        let count = chunk.lines.len() - previous_len;
+        chunk.lines[index..].rotate_right(count);
+        chunk.display_lens[index..].rotate_right(count);
+        self.len += count;
+
+        if chunk.lines.len() > BUFFER_LINE_CHUNK_MAX_LEN {
+            let mut new_chunks = Vec::new();
+            while chunk.lines.len() > BUFFER_LINE_CHUNK_MAX_LEN {
+                let split_index = chunk.lines.len() - BUFFER_LINE_CHUNK_MAX_LEN / 2;
+                new_chunks.push(BufferLineChunk {
+                    lines: chunk.lines.split_off(split_index),
+                    display_lens: chunk.display_lens.split_off(split_index),
+                });
+            }
+            chunk.lines.shrink_to(BUFFER_LINE_CHUNK_MAX_LEN);
+            chunk.display_lens.shrink_to(BUFFER_LINE_CHUNK_MAX_LEN);
 
-            let end_position = BufferPosition::line_col(
-                position.line_index,
-                position.column_byte_index + len_diff as BufferPositionIndex,
-            );
-            BufferRange::between(position, end_position)
-        } else {
-            let mut split_line = self.line_pool.acquire();
-            let mut split_display_len = DisplayLen::zero();
+            new_chunks.reverse();
+            let new_chunks_index = chunk_index + 1;
+            self.chunks
+                .splice(new_chunks_index..new_chunks_index, new_chunks);
+        }
 
-            let position_line = &mut self.lines[position.line_index as usize];
-            let position_display_len = &mut self.line_display_lens[position.line_index as usize];
+        self.update_chunk_starts(chunk_index);
+        count
+    }
 
-            position_line.split_off(
-                position_display_len,
-                &mut split_line,
-                &mut split_display_len,
-                position.column_byte_index as _,
-            );
+    fn remove_range(&mut self, range: Range<usize>, line_pool: &mut BufferLinePool) {
+        if range.start >= range.end {
+            return;
+        }
 
-            let mut line_count = 0 as BufferPositionIndex;
-            let mut lines = text.lines();
-            if let Some(line) = lines.next() {
-                position_line.push_text(position_display_len, line);
+        let (start_chunk_index, start_index) = self.locate(range.start);
+        let (end_chunk_index, end_index) = self.locate(range.end - 1);
+        if start_chunk_index == end_chunk_index {
+            let chunk = &mut self.chunks[start_chunk_index];
+            for line in chunk.lines.drain(start_index..=end_index) {
+                line_pool.release(line);
+            }
+            chunk.display_lens.drain(start_index..=end_index);
+        } else {
+            let chunk = &mut self.chunks[start_chunk_index];
+            for line in chunk.lines.drain(start_index..) {
+                line_pool.release(line);
             }
-            for line_text in lines {
-                line_count += 1;
+            chunk.display_lens.drain(start_index..);
 
-                let mut line = self.line_pool.acquire();
-                let mut display_len = DisplayLen::zero();
-                line.push_text(&mut display_len, line_text);
+            for chunk in self.chunks.drain(start_chunk_index + 1..end_chunk_index) {
+                for line in chunk.lines {
+                    line_pool.release(line);
+                }
+            }
 
-                let insert_index = (position.line_index + line_count) as _;
-                self.lines.insert(insert_index, line);
-                self.line_display_lens.insert(insert_index, display_len);
+            let chunk = &mut self.chunks[start_chunk_index + 1];
+            for line in chunk.lines.drain(..=end_index) {
+                line_pool.release(line);
             }
+            chunk.display_lens.drain(..=end_index);
+        }
+        self.len -= range.end - range.start;
 
-            let end_position = if text.ends_with('\n') {
-                line_count += 1;
+        // merge the chunks around the removed lines while they fit together
+        // so that removing lines never leaves many tiny chunks behind
+        let mut chunk_index = start_chunk_index;
+        if chunk_index + 1 < self.chunks.len() {
+            self.merge_with_next_chunk(chunk_index);
+        }
+        if chunk_index > 0 && self.merge_with_next_chunk(chunk_index - 1) {
+            chunk_index -= 1;
+        }
+        if self.chunks[chunk_index].lines.is_empty() {
+            self.chunks.remove(chunk_index);
+        }
 
-                let insert_index = (position.line_index + line_count) as _;
-                self.lines.insert(insert_index, split_line);
-                self.line_display_lens
-                    .insert(insert_index, split_display_len);
+        self.update_chunk_starts(chunk_index);
+    }
 
-                BufferPosition::line_col(position.line_index + line_count, 0)
-            } else {
-                let index = (position.line_index + line_count) as usize;
-                let line = &mut self.lines[index];
-                let display_len = &mut self.line_display_lens[index];
+    fn merge_with_next_chunk(&mut self, chunk_index: usize) -> bool {
+        let len = self.chunks[chunk_index].lines.len() + self.chunks[chunk_index + 1].lines.len();
+        if len > BUFFER_LINE_CHUNK_MAX_LEN {
+            return false;
+        }
 
-                let column_byte_index = line.as_str().len() as _;
-                line.push_text(display_len, split_line.as_str());
+        let next = self.chunks.remove(chunk_index + 1);
+        let chunk = &mut self.chunks[chunk_index];
+        chunk.lines.extend(next.lines);
+        chunk.display_lens.extend(next.display_lens);
+        true
+    }
 
-                self.line_pool.release(split_line);
-                BufferPosition::line_col(position.line_index + line_count, column_byte_index)
-            };
+    fn update_chunk_starts(&mut self, chunk_index: usize) {
+        self.chunk_starts.truncate(chunk_index);
+        let mut start = match chunk_index.checked_sub(1) {
+            Some(previous_index) => {
+                self.chunk_starts[previous_index] + self.chunks[previous_index].lines.len()
+            }
+            None => 0,
+        };
+        for chunk in &self.chunks[chunk_index..] {
+            self.chunk_starts.push(start);
+            start += chunk.lines.len();
+        }
+    }
 
-            BufferRange::between(position, end_position)
+    fn clear(&mut self, line_pool: &mut BufferLinePool) {
+        for chunk in self.chunks.drain(..) {
+            for line in chunk.lines {
+                line_pool.release(line);
+            }
         }
+        self.chunk_starts.clear();
+        self.len = 0;
     }
+}
 
-    pub fn delete_range(&mut self, range: BufferRange) {
-        let from = range.from;
-        let to = range.to;
+impl Index<usize> for BufferLines {
+    type Output = BufferLine;
+    fn index(&self, index: usize) -> &Self::Output {
+        let (chunk_index, index) = self.locate(index);
+        &self.chunks[chunk_index].lines[index]
+    }
+}
 
-        if from.line_index == to.line_index {
-            let line = &mut self.lines[from.line_index as usize];
-            let display_len = &mut self.line_display_lens[from.line_index as usize];
+impl<'a> IntoIterator for &'a BufferLines {
+    type Item = &'a BufferLine;
+    type IntoIter = BufferLinesIter<'a, BufferLine>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
 
-            line.delete_range(
-                display_len,
-                from.column_byte_index as usize..to.column_byte_index as usize,
-            );
-        } else {
-            let from_line = &mut self.lines[from.line_index as usize];
-            let from_display_len = &mut self.line_display_lens[from.line_index as usize];
-            from_line.delete_range(from_display_len, from.column_byte_index as usize..);
+pub struct BufferLinesIter<'a, T> {
+    chunks: slice::Iter<'a, BufferLineChunk>,
+    front: slice::Iter<'a, T>,
+    back: slice::Iter<'a, T>,
+    len: usize,
+    items: fn(&BufferLineChunk) -> &[T],
+}
+impl<'a, T> Iterator for BufferLinesIter<'a, T> {
+    type Item = &'a T;
 
-            let lines_range = (from.line_index as usize + 1)..to.line_index as usize;
-            if lines_range.start < lines_range.end {
-                for line in self.lines.drain(lines_range.clone()) {
-                    self.line_pool.release(line);
-                }
-                self.line_display_lens.drain(lines_range);
-            }
+    fn next(&mut self) -> Option<Self::Item> {
+        self.nth(0)
+    }
 
-            let to_line_index = from.line_index as usize + 1;
-            if to_line_index < self.lines.len() {
-                let to_line = self.lines.remove(to_line_index);
-                self.line_display_lens.remove(to_line_index);
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
 
-                let from_line = &mut self.lines[from.line_index as usize];
-                let from_display_len = &mut self.line_display_lens[from.line_index as usize];
+    // skips whole chunks so that skipping many lines is cheap
+    fn nth(&mut self, mut n: usize) -> Option<Self::Item> {
+        loop {
+            let front_len = self.front.len();
+            if n < front_len {
+                self.len -= n + 1;
+                return self.front.nth(n);
+            }
+            n -= front_len;
+            self.len -= front_len;
 
-                from_line.push_text(
-                    from_display_len,
-                    &to_line.as_str()[to.column_byte_index as usize..],
-                );
+            match self.chunks.next() {
+                Some(chunk) => self.front = (self.items)(chunk).iter(),
+                None => {
+                    self.front = [].iter();
+                    let item = self.back.nth(n);
+                    self.len = self.back.len();
+                    return item;
+                }
             }
         }
     }
+}
+impl<'a, T> DoubleEndedIterator for BufferLinesIter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.nth_back(0)
+    }
 
-    pub fn clear(&mut self) {
-        for line in self.lines.drain(..) {
-            self.line_pool.release(line);
+    fn nth_back(&mut self, mut n: usize) -> Option<Self::Item> {
+        loop {
+            let back_len = self.back.len();
+            if n < back_len {
+                self.len -= n + 1;
+                return self.back.nth_back(n);
+            }
+            n -= back_len;
+            self.len -= back_len;
+
+            match self.chunks.next_back() {
+                Some(chunk) => self.back = (self.items)(chunk).iter(),
+                None => {
+                    self.back = [].iter();
+                    let item = self.front.nth_back(n);
+                    self.len = self.front.len();
+                    return item;
+                }
+            }
         }
-        self.lines.push(self.line_pool.acquire());
-        self.line_display_lens.clear();
-        self.line_display_lens.push(DisplayLen::zero());
     }
+}
+impl<'a, T> ExactSizeIterator for BufferLinesIter<'a, T> {}
 
-    pub fn words_from(
-        &self,
-        position: BufferPosition,
-    ) -> (
-        WordRefWithPosition,
-        impl Iterator<Item = WordRefWithPosition>,
-        impl Iterator<Item = WordRefWithPosition>,
-    ) {
-        let position = self.saturate_position(position);
-        let line_index = position.line_index as _;
-        let column_byte_index = position.column_byte_index as _;
-
-        let (mid_word, left_words, right_words) =
-            self.lines[line_index as usize].words_from(column_byte_index);
+pub struct TextRangeIter<'a> {
+    content: &'a BufferContent,
+    from: BufferPosition,
+    to: BufferPosition,
+}
+impl<'a> Iterator for TextRangeIter<'a> {
+    type Item = &'a str;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.from == self.to {
+            return None;
+        }
+
+        let line = self.content.lines[self.from.line_index as usize].as_str();
+
+        if self.from.column_byte_index == line.len() as _ {
+            self.from.line_index += 1;
+            self.from.column_byte_index = 0;
+            Some("\n")
+        } else if self.from.line_index == self.to.line_index {
+            let text =
+                &line[self.from.column_byte_index as usize..self.to.column_byte_index as usize];
+            self.from = self.to;
+            Some(text)
+        } else {
+            let text = &line[self.from.column_byte_index as usize..];
+            self.from.column_byte_index = line.len() as _;
+            Some(text)
+        }
+    }
+}
+
+const BYTE_ORDER_MARK: &[u8] = b"\xef\xbb\xbf";
+
+// reads a buffer content from bytes received in chunks of any size
+// so that big files can be read a bit at a time
+struct BufferContentReader {
+    lf_count: usize,
+    crlf_count: usize,
+    push_empty: bool,
+    read_len: u64,
+    // bytes of a line whose end was not received yet
+    line_bytes: Vec<u8>,
+}
+impl BufferContentReader {
+    pub fn new(content: &mut BufferContent) -> Self {
+        content.lines.clear(&mut content.line_pool);
+
+        Self {
+            lf_count: 0,
+            crlf_count: 0,
+            push_empty: true,
+            read_len: 0,
+            line_bytes: Vec::new(),
+        }
+    }
+
+    pub fn read_len(&self) -> u64 {
+        self.read_len
+    }
+
+    pub fn read_bytes(&mut self, content: &mut BufferContent, mut bytes: &[u8]) {
+        self.read_len += bytes.len() as u64;
+
+        while let Some(index) = bytes.iter().position(|&b| b == b'\n') {
+            let (line, rest) = bytes.split_at(index + 1);
+            bytes = rest;
+
+            if self.line_bytes.is_empty() {
+                self.push_line(content, line);
+            } else {
+                let mut line_bytes = std::mem::take(&mut self.line_bytes);
+                line_bytes.extend_from_slice(line);
+                self.push_line(content, &line_bytes);
+                line_bytes.clear();
+                self.line_bytes = line_bytes;
+            }
+        }
+
+        self.line_bytes.extend_from_slice(bytes);
+    }
+
+    // returns the content's file format once all of it was read
+    pub fn finish(&mut self, content: &mut BufferContent) -> BufferFileFormat {
+        if !self.line_bytes.is_empty() {
+            let line_bytes = std::mem::take(&mut self.line_bytes);
+            self.push_line(content, &line_bytes);
+        }
+        if self.push_empty {
+            content
+                .lines
+                .push(content.line_pool.acquire(), DisplayLen::zero());
+        }
+
+        let mut format = BufferFileFormat::default();
+        if self.crlf_count > self.lf_count {
+            format.line_ending = Some(BufferLineEnding::CrLf);
+        } else if self.lf_count > 0 {
+            format.line_ending = Some(BufferLineEnding::Lf);
+        }
+
+        let (line, display_len) = content.lines.get_mut(0);
+        if line.as_str().as_bytes().starts_with(BYTE_ORDER_MARK) {
+            line.delete_range(display_len, ..BYTE_ORDER_MARK.len());
+            format.byte_order_mark = true;
+        }
+
+        format
+    }
+
+    fn push_line(&mut self, content: &mut BufferContent, mut bytes: &[u8]) {
+        self.push_empty = false;
+        if let [rest @ .., b'\n'] = bytes {
+            bytes = rest;
+            self.push_empty = true;
+            if rest.ends_with(b"\r") {
+                self.crlf_count += 1;
+            } else {
+                self.lf_count += 1;
+            }
+        }
+        if let [rest @ .., b'\r'] = bytes {
+            bytes = rest;
+        }
+
+        let mut line = content.line_pool.acquire();
+        push_bytes_lossless(&mut line.0, bytes);
+        let display_len = DisplayLen::from(&line.0[..]);
+        content.lines.push(line, display_len);
+    }
+}
+
+pub struct BufferContent {
+    lines: BufferLines,
+    line_pool: BufferLinePool,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BufferLineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+impl BufferLineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+        }
+    }
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct BufferFileFormat {
+    pub line_ending: Option<BufferLineEnding>,
+    pub byte_order_mark: bool,
+}
+
+impl BufferContent {
+    pub fn new() -> Self {
+        let mut lines = BufferLines::new();
+        lines.push(BufferLine::new(), DisplayLen::zero());
+        Self {
+            lines,
+            line_pool: BufferLinePool::new(),
+        }
+    }
+
+    pub fn lines(&self) -> &BufferLines {
+        &self.lines
+    }
+
+    pub fn end(&self) -> BufferPosition {
+        let last_line_index = self.lines.len() - 1;
+        BufferPosition::line_col(
+            last_line_index as _,
+            self.lines[last_line_index].as_str().len() as _,
+        )
+    }
+
+    pub fn read(&mut self, read: &mut dyn io::BufRead) -> io::Result<BufferFileFormat> {
+        let mut reader = BufferContentReader::new(self);
+        loop {
+            let bytes = match read.fill_buf() {
+                Ok([]) => return Ok(reader.finish(self)),
+                Ok(bytes) => bytes,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    self.clear();
+                    return Err(error);
+                }
+            };
+            reader.read_bytes(self, bytes);
+            let len = bytes.len();
+            read.consume(len);
+        }
+    }
+
+    pub fn write(
+        &self,
+        write: &mut dyn io::Write,
+        line_ending: BufferLineEnding,
+        byte_order_mark: bool,
+    ) -> io::Result<()> {
+        self.write_formatted(write, line_ending, byte_order_mark, false, false)
+    }
+
+    // writes the content as if its trailing whitespace was trimmed and a final newline inserted
+    // without actually editing it
+    pub fn write_formatted(
+        &self,
+        write: &mut dyn io::Write,
+        line_ending: BufferLineEnding,
+        byte_order_mark: bool,
+        trim_trailing_whitespace: bool,
+        insert_final_newline: bool,
+    ) -> io::Result<()> {
+        fn format_line(line: &BufferLine, trim_trailing_whitespace: bool) -> &str {
+            let line = line.as_str();
+            if trim_trailing_whitespace {
+                line.trim_end_matches([' ', '\t'])
+            } else {
+                line
+            }
+        }
+
+        if byte_order_mark {
+            write.write_all(BYTE_ORDER_MARK)?;
+        }
+        let line_ending = line_ending.as_str();
+        let end_index = self.lines.len() - 1;
+        for line in self.lines.range(..end_index) {
+            write_str_lossless(write, format_line(line, trim_trailing_whitespace))?;
+            write.write_all(line_ending.as_bytes())?;
+        }
+        let last_line = format_line(&self.lines[end_index], trim_trailing_whitespace);
+        write_str_lossless(write, last_line)?;
+        if insert_final_newline && !last_line.is_empty() {
+            write.write_all(line_ending.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn hash(&self) -> u64 {
+        let mut hash = hash_bytes(&[]);
+        for line in &self.lines {
+            hash = continue_hash_bytes(hash, line.as_str().as_bytes());
+            hash = continue_hash_bytes(hash, b"\n");
+        }
+        hash
+    }
+
+    // samples the leading whitespace of the first lines to infer whether they're indented with
+    // tabs or spaces and, in the latter case, by how many spaces each level is indented
+    pub fn detect_indentation(&self) -> Option<BufferDetectedIndentation> {
+        const MAX_SAMPLED_LINES: usize = 1000;
+        const MAX_TAB_SIZE: usize = 8;
+
+        let mut tab_indented_count = 0;
+        let mut space_indented_count = 0;
+        let mut indentation_deltas = [0; MAX_TAB_SIZE + 1];
+        let mut previous_indentation = 0;
+
+        for line in self.lines.iter().take(MAX_SAMPLED_LINES) {
+            let line = line.as_str();
+            let text = line.trim_start_matches([' ', '\t']);
+            // skip empty lines and continuations of block comments (` * ...`)
+            if text.is_empty() || text.starts_with('*') {
+                continue;
+            }
+
+            let indentation = &line[..line.len() - text.len()];
+            if indentation.starts_with('\t') {
+                tab_indented_count += 1;
+                previous_indentation = 0;
+                continue;
+            }
+            if indentation.contains('\t') {
+                continue;
+            }
+
+            let indentation = indentation.len();
+            if indentation > 0 {
+                space_indented_count += 1;
+            }
+            if indentation > previous_indentation {
+                let delta = indentation - previous_indentation;
+                if delta <= MAX_TAB_SIZE {
+                    indentation_deltas[delta] += 1;
+                }
+            }
+            previous_indentation = indentation;
+        }
+
+        if tab_indented_count == 0 && space_indented_count == 0 {
+            return None;
+        }
+
+        if tab_indented_count > space_indented_count {
+            return Some(BufferDetectedIndentation {
+                indent_with_tabs: true,
+                tab_size: None,
+            });
+        }
+
+        let mut tab_size = None;
+        let mut max_count = 0;
+        for (delta, &count) in indentation_deltas.iter().enumerate().skip(2) {
+            if count > max_count {
+                tab_size = Some(delta as _);
+                max_count = count;
+            }
+        }
+        if tab_size.is_none() && indentation_deltas[1] > 0 {
+            tab_size = Some(1);
+        }
+
+        Some(BufferDetectedIndentation {
+            indent_with_tabs: false,
+            tab_size,
+        })
+    }
+
+    pub fn saturate_position(&self, mut position: BufferPosition) -> BufferPosition {
+        position.line_index = position.line_index.min((self.lines.len() - 1) as _);
+        let line = self.lines[position.line_index as usize].as_str();
+        position.column_byte_index = position.column_byte_index.min(line.len() as _);
+        position
+    }
+
+    pub fn text_range(&self, range: BufferRange) -> TextRangeIter {
+        let from = self.saturate_position(range.from);
+        let to = self.saturate_position(range.to);
+        TextRangeIter {
+            content: self,
+            from,
+            to,
+        }
+    }
+
+    // extends the range to the word boundaries around it.
+    // since words are runs of chars of the same kind, an edit inside this range
+    // does not change any word outside of it
+    fn words_range(&self, range: BufferRange) -> BufferRange {
+        let mut from = range.from;
+        let from_line = self.lines[from.line_index as usize].as_str();
+        if let Some(word) = WordIter(&from_line[..from.column_byte_index as usize]).next_back() {
+            from.column_byte_index -= word.text.len() as BufferPositionIndex;
+        }
+
+        let mut to = range.to;
+        let to_line = self.lines[to.line_index as usize].as_str();
+        if let Some(word) = WordIter(&to_line[to.column_byte_index as usize..]).next() {
+            to.column_byte_index += word.text.len() as BufferPositionIndex;
+        }
+
+        BufferRange::between(from, to)
+    }
+
+    pub fn find_search_ranges(
+        &self,
+        pattern: &Pattern,
+        line_range: Range<usize>,
+        ranges: &mut Vec<BufferRange>,
+    ) {
+        if pattern.is_empty() {
+            return;
+        }
+        let search_anchor = pattern.search_anchor();
+        let line_index_offset = line_range.start;
+        for (line_index, line) in self.lines.range(line_range).enumerate() {
+            let line_index = line_index_offset + line_index;
+            let line = line.as_str();
+            for range in pattern.match_indices(line, search_anchor) {
+                let from = BufferPosition::line_col(line_index as _, range.start as _);
+                let to = BufferPosition::line_col(line_index as _, range.end as _);
+                ranges.push(BufferRange::between(from, to));
+            }
+        }
+    }
+
+    pub fn insert_text(&mut self, position: BufferPosition, text: &str) -> BufferRange {
+        if !text.contains(&['\n', '\r']) {
+            let (line, display_len) = self.lines.get_mut(position.line_index as _);
+
+            let previous_len = line.as_str().len();
+            line.insert_text(display_len, position.column_byte_index as _, text);
+            let len_diff = line.as_str().len() - previous_len;
+
+            let end_position = BufferPosition::line_col(
+                position.line_index,
+                position.column_byte_index + len_diff as BufferPositionIndex,
+            );
+            BufferRange::between(position, end_position)
+        } else {
+            let mut split_line = self.line_pool.acquire();
+            let mut split_display_len = DisplayLen::zero();
+
+            let (position_line, position_display_len) =
+                self.lines.get_mut(position.line_index as _);
+
+            position_line.split_off(
+                position_display_len,
+                &mut split_line,
+                &mut split_display_len,
+                position.column_byte_index as _,
+            );
+
+            let mut lines = text.lines();
+            if let Some(line) = lines.next() {
+                position_line.push_text(position_display_len, line);
+            }
+
+            // when the text ends with a line break, the split line goes after the new lines.
+            // otherwise it's appended to the last of them
+            let mut split = Some((split_line, split_display_len));
+            let split_after_lines = text.ends_with('\n');
+            let line_pool = &mut self.line_pool;
+            let new_lines = lines
+                .map(|line_text| {
+                    let mut line = line_pool.acquire();
+                    let mut display_len = DisplayLen::zero();
+                    line.push_text(&mut display_len, line_text);
+                    (line, display_len)
+                })
+                .chain(std::iter::from_fn(|| match split_after_lines {
+                    true => split.take(),
+                    false => None,
+                }));
+            let line_count = self
+                .lines
+                .insert(position.line_index as usize + 1, new_lines);
+
+            let end_column_byte_index = match split {
+                Some((split_line, _)) => {
+                    let (line, display_len) = self
+                        .lines
+                        .get_mut(position.line_index as usize + line_count);
+
+                    let column_byte_index = line.as_str().len() as _;
+                    line.push_text(display_len, split_line.as_str());
+
+                    self.line_pool.release(split_line);
+                    column_byte_index
+                }
+                None => 0,
+            };
+
+            let end_position = BufferPosition::line_col(
+                position.line_index + line_count as BufferPositionIndex,
+                end_column_byte_index,
+            );
+            BufferRange::between(position, end_position)
+        }
+    }
+
+    pub fn delete_range(&mut self, range: BufferRange) {
+        let from = range.from;
+        let to = range.to;
+
+        if from.line_index == to.line_index {
+            let (line, display_len) = self.lines.get_mut(from.line_index as _);
+            line.delete_range(
+                display_len,
+                from.column_byte_index as usize..to.column_byte_index as usize,
+            );
+        } else {
+            let (from_line, from_display_len) = self.lines.get_mut(from.line_index as _);
+            from_line.delete_range(from_display_len, from.column_byte_index as usize..);
+
+            let lines_start_index = from.line_index as usize + 1;
+            let to_line_index = to.line_index as usize;
+            if to_line_index < self.lines.len() {
+                // take the to line out so that what's left of it can be joined into the from line
+                let mut to_line = self.line_pool.acquire();
+                let (line, display_len) = self.lines.get_mut(to_line_index);
+                std::mem::swap(line, &mut to_line);
+                let to_display_len = *display_len;
+
+                self.lines
+                    .remove_range(lines_start_index..to_line_index + 1, &mut self.line_pool);
+
+                let (from_line, from_display_len) = self.lines.get_mut(from.line_index as _);
+                from_line.push_line(
+                    from_display_len,
+                    &to_line,
+                    to_display_len,
+                    to.column_byte_index as _,
+                );
+                self.line_pool.release(to_line);
+            } else {
+                let lines_end_index = self.lines.len();
+                self.lines
+                    .remove_range(lines_start_index..lines_end_index, &mut self.line_pool);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear(&mut self.line_pool);
+        self.lines
+            .push(self.line_pool.acquire(), DisplayLen::zero());
+    }
+
+    pub fn words_from(
+        &self,
+        position: BufferPosition,
+    ) -> (
+        WordRefWithPosition,
+        impl Iterator<Item = WordRefWithPosition>,
+        impl Iterator<Item = WordRefWithPosition>,
+    ) {
+        let position = self.saturate_position(position);
+        let line_index = position.line_index as _;
+        let column_byte_index = position.column_byte_index as _;
+
+        let (mid_word, left_words, right_words) =
+            self.lines[line_index as usize].words_from(column_byte_index);
 
         (
             mid_word.to_word_ref_with_position(line_index),
@@ -973,27 +1686,6 @@ impl BufferContent {
         left: char,
         right: char,
     ) -> Option<BufferRange> {
-        fn find<I>(iter: I, target: char, other: char, balance: &mut usize) -> Option<usize>
-        where
-            I: Iterator<Item = (usize, char)>,
-        {
-            let mut b = *balance;
-            for (i, c) in iter {
-                if c == target {
-                    if b == 0 {
-                        *balance = 0;
-                        return Some(i);
-                    } else {
-                        b -= 1;
-                    }
-                } else if c == other {
-                    b += 1;
-                }
-            }
-            *balance = b;
-            None
-        }
-
         let position = self.saturate_position(position);
         let line = self.lines[position.line_index as usize].as_str();
         let (before, after) = line.split_at(position.column_byte_index as _);
@@ -1014,7 +1706,7 @@ impl BufferContent {
 
         let right_position = match right_position {
             Some(column_index) => BufferPosition::line_col(position.line_index, column_index as _),
-            None => match find(after_chars, right, left, &mut balance) {
+            None => match find_balanced_char(after_chars, right, left, &mut balance) {
                 Some(column_byte_index) => {
                     let column_byte_index = position.column_byte_index as usize + column_byte_index;
                     BufferPosition::line_col(position.line_index, column_byte_index as _)
@@ -1024,7 +1716,7 @@ impl BufferContent {
                     for line_index in (position.line_index as usize + 1)..self.lines.len() {
                         let line = self.lines[line_index].as_str();
                         if let Some(column_byte_index) =
-                            find(line.char_indices(), right, left, &mut balance)
+                            find_balanced_char(line.char_indices(), right, left, &mut balance)
                         {
                             pos = Some(BufferPosition::line_col(
                                 line_index as _,
@@ -1042,17 +1734,90 @@ impl BufferContent {
 
         let left_position = match left_position {
             Some(column_index) => BufferPosition::line_col(position.line_index, column_index as _),
-            None => match find(before.char_indices().rev(), left, right, &mut balance) {
-                Some(column_byte_index) => {
-                    let column_byte_index = column_byte_index + left.len_utf8();
-                    BufferPosition::line_col(position.line_index, column_byte_index as _)
+            None => {
+                match find_balanced_char(before.char_indices().rev(), left, right, &mut balance) {
+                    Some(column_byte_index) => {
+                        let column_byte_index = column_byte_index + left.len_utf8();
+                        BufferPosition::line_col(position.line_index, column_byte_index as _)
+                    }
+                    None => {
+                        let mut pos = None;
+                        for line_index in (0..position.line_index).rev() {
+                            let line = self.lines[line_index as usize].as_str();
+                            if let Some(column_byte_index) = find_balanced_char(
+                                line.char_indices().rev(),
+                                left,
+                                right,
+                                &mut balance,
+                            ) {
+                                let column_byte_index = column_byte_index + left.len_utf8();
+                                pos = Some(BufferPosition::line_col(
+                                    line_index,
+                                    column_byte_index as _,
+                                ));
+                                break;
+                            }
+                        }
+                        pos?
+                    }
+                }
+            }
+        };
+
+        Some(BufferRange::between(left_position, right_position))
+    }
+
+    // like `find_balanced_chars_at` but the returned range (without `left` and `right`) contains `range`
+    pub fn find_balanced_chars_around(
+        &self,
+        range: BufferRange,
+        left: char,
+        right: char,
+    ) -> Option<BufferRange> {
+        let from = self.saturate_position(range.from);
+        let to = self.saturate_position(range.to);
+
+        let mut balance = 0;
+        let line = &self.lines[to.line_index as usize].as_str()[to.column_byte_index as usize..];
+        let right_position =
+            match find_balanced_char(line.char_indices(), right, left, &mut balance) {
+                Some(column_byte_index) => BufferPosition::line_col(
+                    to.line_index,
+                    (to.column_byte_index as usize + column_byte_index) as _,
+                ),
+                None => {
+                    let mut pos = None;
+                    for line_index in (to.line_index as usize + 1)..self.lines.len() {
+                        let line = self.lines[line_index].as_str();
+                        if let Some(column_byte_index) =
+                            find_balanced_char(line.char_indices(), right, left, &mut balance)
+                        {
+                            pos = Some(BufferPosition::line_col(
+                                line_index as _,
+                                column_byte_index as _,
+                            ));
+                            break;
+                        }
+                    }
+                    pos?
                 }
+            };
+
+        balance = 0;
+        let line =
+            &self.lines[from.line_index as usize].as_str()[..from.column_byte_index as usize];
+        let left_position =
+            match find_balanced_char(line.char_indices().rev(), left, right, &mut balance) {
+                Some(column_byte_index) => BufferPosition::line_col(
+                    from.line_index,
+                    (column_byte_index + left.len_utf8()) as _,
+                ),
                 None => {
                     let mut pos = None;
-                    for line_index in (0..position.line_index).rev() {
+                    for line_index in (0..from.line_index).rev() {
                         let line = self.lines[line_index as usize].as_str();
                         if let Some(column_byte_index) =
-                            find(line.char_indices().rev(), left, right, &mut balance)
+                            find_balanced_char(line.char_indices().rev(), left, right, &mut balance)
                         {
                             let column_byte_index = column_byte_index + left.len_utf8();
                             pos =
@@ -1062,17 +1827,37 @@ impl BufferContent {
                     }
                     pos?
                 }
-            },
-        };
+            };
 
         Some(BufferRange::between(left_position, right_position))
     }
 }
 
+fn find_balanced_char<I>(iter: I, target: char, other: char, balance: &mut usize) -> Option<usize>
+where
+    I: Iterator<Item = (usize, char)>,
+{
+    let mut b = *balance;
+    for (i, c) in iter {
+        if c == target {
+            if b == 0 {
+                *balance = 0;
+                return Some(i);
+            } else {
+                b -= 1;
+            }
+        } else if c == other {
+            b += 1;
+        }
+    }
+    *balance = b;
+    None
+}
+
 impl fmt::Display for BufferContent {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let end_index = self.lines.len() - 1;
-        for line in &self.lines[..end_index] {
+        for line in self.lines.range(..end_index) {
             f.write_str(line.as_str())?;
             f.write_str("\n")?;
         }
@@ -1106,12 +1891,26 @@ impl From<io::Error> for BufferReadError {
 
 pub enum BufferWriteError {
     SavingDisabled,
+    ReadOnly,
+    StillReading,
+    FileChangedOnDisk,
+    CouldNotWriteBackupFile,
+    CouldNotCreateTemporaryFile,
     CouldNotWriteToFile,
 }
 impl fmt::Display for BufferWriteError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Self::SavingDisabled => f.write_str("buffer has saving disabled"),
+            Self::ReadOnly => f.write_str("buffer is read-only"),
+            Self::StillReading => f.write_str("buffer is still being read from its file"),
+            Self::FileChangedOnDisk => f.write_str(
+                "file changed on disk since it was last read or saved (use `save!` to overwrite it)",
+            ),
+            Self::CouldNotWriteBackupFile => f.write_str("could not write backup file"),
+            Self::CouldNotCreateTemporaryFile => {
+                f.write_str("could not create a temporary file next to the file")
+            }
             Self::CouldNotWriteToFile => f.write_str("could not write to file"),
         }
     }
@@ -1128,6 +1927,10 @@ pub struct BufferProperties {
     pub saving_enabled: bool,
     pub file_backed_enabled: bool,
     pub word_database_enabled: bool,
+    pub line_ending: BufferLineEnding,
+    pub byte_order_mark_enabled: bool,
+    pub persistent_history_enabled: bool,
+    pub read_only: bool,
 }
 impl BufferProperties {
     pub fn text() -> Self {
@@ -1136,6 +1939,10 @@ impl BufferProperties {
             saving_enabled: true,
             file_backed_enabled: true,
             word_database_enabled: true,
+            line_ending: BufferLineEnding::Lf,
+            byte_order_mark_enabled: false,
+            persistent_history_enabled: false,
+            read_only: false,
         }
     }
 
@@ -1145,6 +1952,10 @@ impl BufferProperties {
             saving_enabled: false,
             file_backed_enabled: false,
             word_database_enabled: false,
+            line_ending: BufferLineEnding::Lf,
+            byte_order_mark_enabled: false,
+            persistent_history_enabled: false,
+            read_only: false,
         }
     }
 
@@ -1154,6 +1965,10 @@ impl BufferProperties {
             saving_enabled: false,
             file_backed_enabled: true,
             word_database_enabled: false,
+            line_ending: BufferLineEnding::Lf,
+            byte_order_mark_enabled: false,
+            persistent_history_enabled: false,
+            read_only: false,
         }
     }
 
@@ -1163,8 +1978,278 @@ impl BufferProperties {
             saving_enabled: false,
             file_backed_enabled: false,
             word_database_enabled: false,
+            line_ending: BufferLineEnding::Lf,
+            byte_order_mark_enabled: false,
+            persistent_history_enabled: false,
+            read_only: false,
+        }
+    }
+}
+
+const LARGE_FILE_READ_BUF_LEN: usize = 256 * 1024;
+const LARGE_FILE_HISTORY_GROUP_COUNT: usize = 100;
+const LARGE_FILE_SEARCH_LINE_COUNT: usize = 100_000;
+
+// large files are read from the output of a process spawned through the platform
+// so that reading them never blocks the editor
+struct BufferPendingRead {
+    content: BufferContent,
+    content_reader: BufferContentReader,
+    file_len: u64,
+    read_only: bool,
+    process_spawned: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct BufferFileStamp {
+    modified: SystemTime,
+    len: u64,
+}
+impl BufferFileStamp {
+    fn from_metadata(metadata: &fs::Metadata) -> Option<Self> {
+        Some(Self {
+            modified: metadata.modified().ok()?,
+            len: metadata.len(),
+        })
+    }
+
+    fn from_path(path: &Path) -> Option<Self> {
+        Self::from_metadata(&fs::metadata(path).ok()?)
+    }
+}
+
+fn remove_words_in_range(
+    content: &BufferContent,
+    word_database: &mut WordDatabase,
+    range: BufferRange,
+) {
+    for text in content.text_range(range) {
+        for word in WordIter(text).of_kind(WordKind::Identifier) {
+            word_database.remove(word);
+        }
+    }
+}
+
+fn add_words_in_range(
+    content: &BufferContent,
+    word_database: &mut WordDatabase,
+    range: BufferRange,
+) {
+    for text in content.text_range(range) {
+        for word in WordIter(text).of_kind(WordKind::Identifier) {
+            word_database.add(word);
+        }
+    }
+}
+
+fn sibling_path(path: &Path, prefix: &str, suffix: &str) -> Option<PathBuf> {
+    let file_name = path.file_name()?.to_str()?;
+    let mut sibling_file_name =
+        String::with_capacity(prefix.len() + file_name.len() + suffix.len());
+    sibling_file_name.push_str(prefix);
+    sibling_file_name.push_str(file_name);
+    sibling_file_name.push_str(suffix);
+    Some(path.with_file_name(sibling_file_name))
+}
+
+fn write_backup_file(path: &Path, backup: SaveBackup) -> io::Result<()> {
+    let backup_path = match backup {
+        SaveBackup::None => return Ok(()),
+        SaveBackup::Single => sibling_path(path, "", ".bak"),
+        SaveBackup::Timestamped => {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            sibling_path(path, "", &format!(".{}.bak", timestamp))
+        }
+    };
+    match backup_path {
+        Some(backup_path) => fs::copy(path, backup_path).map(|_| ()),
+        None => Err(io::Error::from(io::ErrorKind::InvalidInput)),
+    }
+}
+
+// checks write permission without opening the file (which would notify file watchers)
+#[cfg(unix)]
+fn is_file_writable(path: &Path, _metadata: Option<&fs::Metadata>) -> bool {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+    match CString::new(path.as_os_str().as_bytes()) {
+        Ok(path) => unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 },
+        Err(_) => true,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_file_writable(_path: &Path, metadata: Option<&fs::Metadata>) -> bool {
+    match metadata {
+        Some(metadata) => !metadata.permissions().readonly(),
+        None => true,
+    }
+}
+
+// creates a new sibling temporary file without ever touching an existing one
+fn create_temporary_file(path: &Path) -> Option<(PathBuf, File)> {
+    let process_id = std::process::id();
+    for attempt in 0..16 {
+        let suffix = format!(
+            ".{}-{}.{}-save",
+            process_id,
+            attempt,
+            env!("CARGO_PKG_NAME")
+        );
+        let temp_path = sibling_path(path, ".", &suffix)?;
+        match File::options()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(file) => return Some((temp_path, file)),
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(_) => return None,
         }
     }
+    None
+}
+
+// makes a rename inside this directory durable
+fn sync_parent_directory(path: &Path) -> io::Result<()> {
+    if cfg!(unix) {
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        File::open(parent)?.sync_all()
+    } else {
+        Ok(())
+    }
+}
+
+// writes to a sibling temporary file which is then renamed over the original one
+// so that a failed write never leaves a truncated file behind.
+// the written file keeps the original one's permissions unless `permissions` is set
+fn write_file_atomically(
+    path: &Path,
+    backup: SaveBackup,
+    permissions: Option<fs::Permissions>,
+    write: &dyn Fn(&mut dyn io::Write) -> io::Result<()>,
+) -> Result<(), BufferWriteError> {
+    let mut path = path;
+    let canonical_path;
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            canonical_path = fs::canonicalize(path)?;
+            path = &canonical_path;
+            Some(fs::metadata(path)?)
+        }
+        Ok(metadata) => Some(metadata),
+        Err(_) => None,
+    };
+
+    if metadata.is_some() && write_backup_file(path, backup).is_err() {
+        return Err(BufferWriteError::CouldNotWriteBackupFile);
+    }
+
+    let (temp_path, temp_file) = match create_temporary_file(path) {
+        Some(temp) => temp,
+        None => return Err(BufferWriteError::CouldNotCreateTemporaryFile),
+    };
+
+    let result = (|| {
+        // set before writing so the content is never readable with looser permissions
+        if let Some(permissions) = &permissions {
+            temp_file.set_permissions(permissions.clone())?;
+        }
+
+        let mut writer = io::BufWriter::new(temp_file);
+        write(&mut writer)?;
+        let temp_file = writer.into_inner().map_err(io::Error::from)?;
+        temp_file.sync_all()?;
+        drop(temp_file);
+
+        if let (None, Some(metadata)) = (&permissions, &metadata) {
+            fs::set_permissions(&temp_path, metadata.permissions())?;
+        }
+        fs::rename(&temp_path, path)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result?;
+    sync_parent_directory(path)?;
+    Ok(())
+}
+
+const HISTORY_FILE_VERSION: u32 = 1;
+const HISTORY_FILE_MAX_GROUP_COUNT: usize = 1000;
+
+fn history_file_path(path: &Path) -> Option<(PathBuf, PathBuf)> {
+    let mut file_path = if cfg!(windows) {
+        PathBuf::from(env::var_os("LOCALAPPDATA")?)
+    } else if let Some(cache_path) = env::var_os("XDG_CACHE_HOME") {
+        PathBuf::from(cache_path)
+    } else {
+        let mut home_path = PathBuf::from(env::var_os("HOME")?);
+        home_path.push(".cache");
+        home_path
+    };
+    file_path.push(env!("CARGO_PKG_NAME"));
+    file_path.push("history");
+
+    let path = fs::canonicalize(path).ok()?;
+    let hash = hash_bytes(path.to_str()?.as_bytes());
+    file_path.push(format!("{:x}", hash));
+    Some((path, file_path))
+}
+
+fn write_history_file(path: &Path, content: &BufferContent, history: &BufferHistory) -> Option<()> {
+    let (path, file_path) = history_file_path(path)?;
+
+    let mut history = history.clone();
+    history.limit_group_count(HISTORY_FILE_MAX_GROUP_COUNT);
+
+    let mut bytes = Vec::new();
+    HISTORY_FILE_VERSION.serialize(&mut bytes);
+    path.to_str()?.serialize(&mut bytes);
+    content.hash().serialize(&mut bytes);
+    history.serialize(&mut bytes);
+
+    // the history holds the file's text, so only its owner should be able to read it
+    #[cfg(unix)]
+    let permissions = {
+        use std::os::unix::fs::PermissionsExt;
+        Some(fs::Permissions::from_mode(0o600))
+    };
+    #[cfg(not(unix))]
+    let permissions = None;
+
+    fs::create_dir_all(file_path.parent()?).ok()?;
+    write_file_atomically(&file_path, SaveBackup::None, permissions, &|write| {
+        write.write_all(&bytes)
+    })
+    .ok()
+}
+
+fn read_history_file(path: &Path, content: &BufferContent) -> Option<BufferHistory> {
+    let (path, file_path) = history_file_path(path)?;
+    let bytes = fs::read(file_path).ok()?;
+    let mut bytes = &bytes[..];
+
+    if u32::deserialize(&mut bytes).ok()? != HISTORY_FILE_VERSION
+        || <&str>::deserialize(&mut bytes).ok()? != path.to_str()?
+        || u64::deserialize(&mut bytes).ok()? != content.hash()
+    {
+        return None;
+    }
+    BufferHistory::deserialize(&mut bytes).ok()
+}
+
+pub enum BufferFileChange {
+    None,
+    Reloaded,
+    ReloadFailed(BufferReadError),
+    Conflict,
 }
 
 #[derive(Clone, Copy)]
@@ -1173,19 +2258,49 @@ pub struct BufferIndentationConfig {
     pub tab_size: u8,
 }
 
+// indentation style inferred from a buffer's content when it's read from a file.
+// `tab_size` is `None` when it can't be inferred (like when indenting with tabs)
+#[derive(Clone, Copy)]
+pub struct BufferDetectedIndentation {
+    pub indent_with_tabs: bool,
+    pub tab_size: Option<u8>,
+}
+impl BufferDetectedIndentation {
+    pub fn apply_to(&self, config: &mut Config) {
+        config.indent_with_tabs = self.indent_with_tabs;
+        if let Some(tab_size) = self.tab_size {
+            config.tab_size = tab_size;
+        }
+    }
+}
+
 pub struct Buffer {
     alive: bool,
     handle: BufferHandle,
     pub path: PathBuf,
     content: BufferContent,
     syntax_handle: SyntaxHandle,
+    forced_syntax_handle: Option<SyntaxHandle>,
     highlighted: HighlightedBuffer,
     history: BufferHistory,
     pub lints: BufferLintCollection,
     breakpoints: BufferBreakpointCollection,
     search_ranges: Vec<BufferRange>,
     needs_save: bool,
+    file_stamp: Option<BufferFileStamp>,
+    conflicting_file_stamp: Option<BufferFileStamp>,
     pub properties: BufferProperties,
+    // set when the buffer was made read-only because its file is not writable or could not be
+    // fully read. it's lifted when the file is read again
+    forced_read_only: bool,
+    pub editorconfig: EditorConfig,
+    pub detected_indentation: Option<BufferDetectedIndentation>,
+    pub config_overrides: ConfigOverrides,
+    large_file: bool,
+    pending_read: Option<Box<BufferPendingRead>>,
+    // outputs from the read processes of previous reads are ignored
+    read_process_id: u32,
+    vcs_diff: BufferVcsDiff,
 }
 
 impl Buffer {
@@ -1196,13 +2311,24 @@ impl Buffer {
             path: PathBuf::new(),
             content: BufferContent::new(),
             syntax_handle: SyntaxHandle::default(),
+            forced_syntax_handle: None,
             highlighted: HighlightedBuffer::new(),
             history: BufferHistory::new(),
             lints: BufferLintCollection::default(),
             breakpoints: BufferBreakpointCollection::default(),
             search_ranges: Vec::new(),
             needs_save: false,
+            file_stamp: None,
+            conflicting_file_stamp: None,
             properties: BufferProperties::default(),
+            forced_read_only: false,
+            editorconfig: EditorConfig::default(),
+            detected_indentation: None,
+            config_overrides: ConfigOverrides::default(),
+            large_file: false,
+            pending_read: None,
+            read_process_id: 0,
+            vcs_diff: BufferVcsDiff::new(),
         }
     }
 
@@ -1214,16 +2340,30 @@ impl Buffer {
         self.alive = false;
         self.path.clear();
         self.syntax_handle = SyntaxHandle::default();
+        self.forced_syntax_handle = None;
         self.history.clear();
         self.lints.clear();
         self.breakpoints.clear();
         self.search_ranges.clear();
         self.needs_save = false;
+        self.file_stamp = None;
+        self.conflicting_file_stamp = None;
         self.properties = BufferProperties::default();
+        self.forced_read_only = false;
+        self.editorconfig = EditorConfig::default();
+        self.detected_indentation = None;
+        self.config_overrides = ConfigOverrides::default();
+        self.large_file = false;
+        self.pending_read = None;
+        self.vcs_diff.clear();
+    }
+
+    fn uses_word_database(&self) -> bool {
+        self.properties.word_database_enabled && !self.large_file
     }
 
     fn remove_all_words_from_database(&mut self, word_database: &mut WordDatabase) {
-        if self.properties.word_database_enabled {
+        if self.uses_word_database() {
             for line in &self.content.lines {
                 for word in WordIter(line.as_str()).of_kind(WordKind::Identifier) {
                     word_database.remove(word);
@@ -1246,6 +2386,33 @@ impl Buffer {
         }
     }
 
+    // layers, on top of the editor config, the overrides of globs matching this buffer's path,
+    // its detected indentation, its `.editorconfig` properties and then its own overrides
+    pub fn resolve_config(
+        &self,
+        config: &Config,
+        glob_config_overrides: &GlobConfigOverrides,
+    ) -> Config {
+        // layers: global, detected indentation, glob, editorconfig and then buffer overrides.
+        // globs are applied first only to know if they turn indentation detection off
+        let path = self.path.to_str();
+        let mut config = config.clone();
+        if let Some(path) = path {
+            glob_config_overrides.apply_to(&mut config, path);
+        }
+        if config.detect_indentation {
+            if let Some(detected_indentation) = &self.detected_indentation {
+                detected_indentation.apply_to(&mut config);
+                if let Some(path) = path {
+                    glob_config_overrides.apply_to(&mut config, path);
+                }
+            }
+        }
+        self.editorconfig.apply_to(&mut config);
+        self.config_overrides.apply_to(&mut config);
+        config
+    }
+
     pub fn content(&self) -> &BufferContent {
         &self.content
     }
@@ -1254,18 +2421,41 @@ impl Buffer {
         &self.highlighted
     }
 
+    pub fn syntax_handle(&self) -> SyntaxHandle {
+        self.syntax_handle
+    }
+
     pub fn update_highlighting(&mut self, syntaxes: &SyntaxCollection) -> HighlightResult {
+        if self.large_file {
+            return HighlightResult::Complete;
+        }
         self.highlighted
-            .highlight_dirty_lines(syntaxes.get(self.syntax_handle), &self.content)
+            .highlight_dirty_lines(syntaxes, self.syntax_handle, &self.content)
     }
 
-    pub fn refresh_syntax(&mut self, syntaxes: &SyntaxCollection) {
-        let path = self.path.to_str().unwrap_or("");
-        if path.is_empty() {
-            return;
-        }
+    // a forced syntax is kept until it's reset with `None` and the syntax is detected again
+    pub fn force_syntax(
+        &mut self,
+        syntax_handle: Option<SyntaxHandle>,
+        syntaxes: &SyntaxCollection,
+    ) {
+        self.forced_syntax_handle = syntax_handle;
+        self.refresh_syntax(syntaxes);
+    }
 
-        let syntax_handle = syntaxes.find_handle_by_path(path).unwrap_or_default();
+    pub fn refresh_syntax(&mut self, syntaxes: &SyntaxCollection) {
+        let syntax_handle = match self.forced_syntax_handle {
+            Some(handle) => handle,
+            None => {
+                let path = self.path.to_str().unwrap_or("");
+                if path.is_empty() {
+                    return;
+                }
+                syntaxes
+                    .find_handle(path, &self.content)
+                    .unwrap_or_default()
+            }
+        };
 
         if self.syntax_handle != syntax_handle {
             self.syntax_handle = syntax_handle;
@@ -1277,6 +2467,36 @@ impl Buffer {
         &self.breakpoints.breakpoints
     }
 
+    pub fn vcs_diff(&self) -> &BufferVcsDiff {
+        &self.vcs_diff
+    }
+
+    pub fn update_vcs_diff(&mut self) {
+        self.vcs_diff.update(&self.content);
+    }
+
+    // fetches the file's version at the vcs `HEAD` so the buffer can be diffed against it
+    pub(crate) fn refresh_vcs_diff(
+        &mut self,
+        platform: &mut Platform,
+        current_directory: &Path,
+        enabled: bool,
+    ) {
+        if !enabled
+            || self.large_file
+            || !self.properties.file_backed_enabled
+            || self.path.as_os_str().is_empty()
+            || self.path.starts_with(help::HELP_PREFIX)
+        {
+            self.vcs_diff.clear();
+            return;
+        }
+
+        let path = current_directory.join(&self.path);
+        self.vcs_diff
+            .spawn_base_process(platform, self.handle, &path);
+    }
+
     pub fn breakpoints_mut(&mut self) -> BufferBreakpointMutCollection {
         BufferBreakpointMutCollection {
             inner: &mut self.breakpoints,
@@ -1290,6 +2510,26 @@ impl Buffer {
         self.properties.saving_enabled && self.needs_save
     }
 
+    // replaces the whole content of a read-only buffer that lists things (like `list-buffer`)
+    pub fn set_read_only_content(
+        &mut self,
+        word_database: &mut WordDatabase,
+        text: &str,
+        events: &mut EditorEventWriter,
+    ) {
+        let mut events = BufferEditMutGuard::new(events, self.handle);
+        self.properties.read_only = false;
+        let range = BufferRange::between(BufferPosition::zero(), self.content.end());
+        self.delete_range(word_database, range, events.to_range_deletes());
+        self.insert_text(
+            word_database,
+            BufferPosition::zero(),
+            text,
+            events.to_text_inserts(),
+        );
+        self.properties.read_only = true;
+    }
+
     pub fn insert_text(
         &mut self,
         word_database: &mut WordDatabase,
@@ -1300,19 +2540,13 @@ impl Buffer {
         self.search_ranges.clear();
         let position = self.content.saturate_position(position);
 
-        if text.is_empty() {
+        if text.is_empty() || self.properties.read_only {
             return BufferRange::between(position, position);
         }
         self.needs_save = true;
 
-        let range = Self::insert_text_no_history(
-            &mut self.content,
-            self.properties
-                .word_database_enabled
-                .then_some(word_database),
-            position,
-            text,
-        );
+        let word_database = self.uses_word_database().then_some(word_database);
+        let range = Self::insert_text_no_history(&mut self.content, word_database, position, text);
 
         events.add(range, text);
 
@@ -1333,24 +2567,23 @@ impl Buffer {
         position: BufferPosition,
         text: &str,
     ) -> BufferRange {
+        let words_range = content.words_range(BufferRange::between(position, position));
         if let Some(word_database) = &mut word_database {
-            for word in WordIter(content.lines()[position.line_index as usize].as_str())
-                .of_kind(WordKind::Identifier)
-            {
-                word_database.remove(word);
-            }
+            remove_words_in_range(content, word_database, words_range);
         }
 
         let range = content.insert_text(position, text);
 
         if let Some(word_database) = &mut word_database {
-            for line in
-                &content.lines()[range.from.line_index as usize..=range.to.line_index as usize]
-            {
-                for word in WordIter(line.as_str()).of_kind(WordKind::Identifier) {
-                    word_database.add(word);
-                }
-            }
+            let words_range = BufferRange::between(
+                words_range.from,
+                BufferPosition::line_col(
+                    range.to.line_index,
+                    range.to.column_byte_index + words_range.to.column_byte_index
+                        - position.column_byte_index,
+                ),
+            );
+            add_words_in_range(content, word_database, words_range);
         }
 
         range
@@ -1366,7 +2599,7 @@ impl Buffer {
         range.from = self.content.saturate_position(range.from);
         range.to = self.content.saturate_position(range.to);
 
-        if range.from == range.to {
+        if range.from == range.to || self.properties.read_only {
             return;
         }
         self.needs_save = true;
@@ -1377,24 +2610,6 @@ impl Buffer {
         let to = range.to;
 
         if self.properties.history_enabled {
-            fn add_history_delete_line(buffer: &mut Buffer, from: BufferPosition) {
-                let line = buffer.content.lines()[from.line_index as usize].as_str();
-                let range = BufferRange::between(
-                    BufferPosition::line_col(from.line_index, line.len() as _),
-                    BufferPosition::line_col(from.line_index + 1, 0),
-                );
-                buffer.history.add_edit(Edit {
-                    kind: EditKind::Delete,
-                    range,
-                    text: "\n",
-                });
-                buffer.history.add_edit(Edit {
-                    kind: EditKind::Delete,
-                    range: BufferRange::between(from, range.from),
-                    text: &line[from.column_byte_index as usize..],
-                });
-            }
-
             if from.line_index == to.line_index {
                 let text = &self.content.lines()[from.line_index as usize].as_str()
                     [from.column_byte_index as usize..to.column_byte_index as usize];
@@ -1404,27 +2619,21 @@ impl Buffer {
                     text,
                 });
             } else {
-                let text = &self.content.lines()[to.line_index as usize].as_str()
-                    [..to.column_byte_index as usize];
+                // a single edit keeps the history from merging one edit per deleted line
+                let mut text = String::new();
+                for range_text in self.content.text_range(range) {
+                    text.push_str(range_text);
+                }
                 self.history.add_edit(Edit {
                     kind: EditKind::Delete,
-                    range: BufferRange::between(BufferPosition::line_col(to.line_index, 0), to),
-                    text,
+                    range,
+                    text: &text,
                 });
-                for line_index in ((from.line_index + 1)..to.line_index).rev() {
-                    add_history_delete_line(self, BufferPosition::line_col(line_index, 0));
-                }
-                add_history_delete_line(self, from);
             }
         }
 
-        Self::delete_range_no_history(
-            &mut self.content,
-            self.properties
-                .word_database_enabled
-                .then_some(word_database),
-            range,
-        );
+        let word_database = self.uses_word_database().then_some(word_database);
+        Self::delete_range_no_history(&mut self.content, word_database, range);
     }
 
     fn delete_range_no_history(
@@ -1432,39 +2641,41 @@ impl Buffer {
         mut word_database: Option<&mut WordDatabase>,
         range: BufferRange,
     ) {
+        let words_range = content.words_range(range);
         if let Some(word_database) = &mut word_database {
-            for line in
-                &content.lines()[range.from.line_index as usize..=range.to.line_index as usize]
-            {
-                for word in WordIter(line.as_str()).of_kind(WordKind::Identifier) {
-                    word_database.remove(word);
-                }
-            }
+            remove_words_in_range(content, word_database, words_range);
         }
 
         content.delete_range(range);
 
         if let Some(word_database) = &mut word_database {
-            for word in WordIter(content.lines()[range.from.line_index as usize].as_str())
-                .of_kind(WordKind::Identifier)
-            {
-                word_database.add(word);
-            }
+            let words_range = BufferRange::between(
+                words_range.from,
+                BufferPosition::line_col(
+                    range.from.line_index,
+                    range.from.column_byte_index + words_range.to.column_byte_index
+                        - range.to.column_byte_index,
+                ),
+            );
+            add_words_in_range(content, word_database, words_range);
         }
     }
 
     pub fn fix_line_indentation(
         &mut self,
         indentation_config: BufferIndentationConfig,
+        syntaxes: &SyntaxCollection,
         line_index: BufferPositionIndex,
         events: &mut BufferEditMutGuard,
     ) {
-        if indentation_config.tab_size == 0 {
+        if indentation_config.tab_size == 0 || self.properties.read_only {
             return;
         }
 
+        let syntax = syntaxes.get(self.syntax_handle);
+
         let mut previous_line_text = "";
-        for line in self.content.lines[..line_index as usize].iter().rev() {
+        for line in self.content.lines.range(..line_index as usize).rev() {
             previous_line_text = line.as_str();
             if !previous_line_text.is_empty() {
                 break;
@@ -1492,12 +2703,12 @@ impl Buffer {
             }
         }
 
-        if previous_line_text.ends_with(&['(', '[', '{', '<']) {
+        if previous_line_text.ends_with(&['(', '[', '{', '<']) || syntax.indents(previous_line_text)
+        {
             indentation += 1;
         }
 
-        let line = &mut self.content.lines[line_index as usize];
-        let display_lens = &mut self.content.line_display_lens[line_index as usize];
+        let (line, display_len) = self.content.lines.get_mut(line_index as _);
         let first_word = line.word_at(0);
         let delete_len = match first_word.kind {
             WordKind::Whitespace => first_word.text.len(),
@@ -1519,9 +2730,9 @@ impl Buffer {
             });
         }
 
-        line.delete_range(display_lens, ..delete_len);
+        line.delete_range(display_len, ..delete_len);
 
-        if line.0.trim_start().starts_with(&[')', ']', '}', '>']) {
+        if line.0.trim_start().starts_with(&[')', ']', '}', '>']) || syntax.dedents(&line.0) {
             indentation = indentation.saturating_sub(1);
         }
 
@@ -1549,8 +2760,7 @@ impl Buffer {
                 }
             };
 
-            let line_display_len = &mut self.content.line_display_lens[line_index as usize];
-            *line_display_len = *line_display_len + insert_display_len;
+            *display_len = *display_len + insert_display_len;
 
             insert_len
         };
@@ -1572,8 +2782,138 @@ impl Buffer {
         }
     }
 
+    // rewrites the leading whitespace of each line in `line_range` with the indentation style in
+    // `indentation_config` while keeping its display width. whitespace only lines are left as is
+    pub fn retab(
+        &mut self,
+        indentation_config: BufferIndentationConfig,
+        line_range: Range<BufferPositionIndex>,
+        events: &mut BufferEditMutGuard,
+    ) {
+        if indentation_config.tab_size == 0 || self.properties.read_only {
+            return;
+        }
+        let tab_size = indentation_config.tab_size as usize;
+
+        let mut indentation = String::new();
+        let end_line_index = (line_range.end as usize).min(self.content.lines.len());
+        for line_index in line_range.start as usize..end_line_index {
+            let (line, display_len) = self.content.lines.get_mut(line_index);
+            let text = line.as_str().trim_start_matches([' ', '\t']);
+            if text.is_empty() {
+                continue;
+            }
+            let delete_len = line.as_str().len() - text.len();
+
+            let mut width = 0;
+            for c in line.as_str()[..delete_len].chars() {
+                match c {
+                    '\t' => width += tab_size - width % tab_size,
+                    _ => width += 1,
+                }
+            }
+
+            indentation.clear();
+            let space_count = if indentation_config.indent_with_tabs {
+                for _ in 0..width / tab_size {
+                    indentation.push('\t');
+                }
+                width % tab_size
+            } else {
+                width
+            };
+            for _ in 0..space_count {
+                indentation.push(' ');
+            }
+            if indentation == line.as_str()[..delete_len] {
+                continue;
+            }
+
+            let delete_range = BufferRange::between(
+                BufferPosition::line_col(line_index as _, 0),
+                BufferPosition::line_col(line_index as _, delete_len as _),
+            );
+            events.to_range_deletes().add(delete_range);
+            if self.properties.history_enabled {
+                self.history.add_edit(Edit {
+                    kind: EditKind::Delete,
+                    range: delete_range,
+                    text: &line.as_str()[..delete_len],
+                });
+            }
+
+            line.delete_range(display_len, ..delete_len);
+            line.insert_text(display_len, 0, &indentation);
+
+            let insert_range = BufferRange::between(
+                BufferPosition::line_col(line_index as _, 0),
+                BufferPosition::line_col(line_index as _, indentation.len() as _),
+            );
+            events.to_text_inserts().add(insert_range, &indentation);
+            if self.properties.history_enabled {
+                self.history.add_edit(Edit {
+                    kind: EditKind::Insert,
+                    range: insert_range,
+                    text: &indentation,
+                });
+            }
+
+            self.search_ranges.clear();
+            self.needs_save = true;
+        }
+    }
+
+    fn trim_trailing_whitespace(&mut self, events: &mut BufferEditMutGuard) {
+        for line_index in 0..self.content.lines.len() {
+            let (line, display_len) = self.content.lines.get_mut(line_index);
+            let len = line.as_str().len();
+            let trimmed_len = line.as_str().trim_end_matches([' ', '\t']).len();
+            if trimmed_len == len {
+                continue;
+            }
+
+            let delete_range = BufferRange::between(
+                BufferPosition::line_col(line_index as _, trimmed_len as _),
+                BufferPosition::line_col(line_index as _, len as _),
+            );
+
+            events.to_range_deletes().add(delete_range);
+            if self.properties.history_enabled {
+                self.history.add_edit(Edit {
+                    kind: EditKind::Delete,
+                    range: delete_range,
+                    text: &line.as_str()[trimmed_len..],
+                });
+            }
+
+            line.delete_range(display_len, trimmed_len..);
+        }
+    }
+
+    fn insert_final_newline(&mut self, events: &mut BufferEditMutGuard) {
+        let end = self.content.end();
+        if end.column_byte_index == 0 {
+            return;
+        }
+
+        let text = "\n";
+        let insert_range = self.content.insert_text(end, text);
+        events.to_text_inserts().add(insert_range, text);
+        if self.properties.history_enabled {
+            self.history.add_edit(Edit {
+                kind: EditKind::Insert,
+                range: insert_range,
+                text,
+            });
+        }
+    }
+
     pub fn commit_edits(&mut self) {
         self.history.commit_edits();
+        if self.large_file {
+            self.history
+                .limit_group_count(LARGE_FILE_HISTORY_GROUP_COUNT);
+        }
     }
 
     pub fn undo(
@@ -1605,8 +2945,8 @@ impl Buffer {
         self.search_ranges.clear();
         self.needs_save = true;
 
+        let uses_word_database = self.uses_word_database();
         let content = &mut self.content;
-        let uses_word_database = self.properties.word_database_enabled;
 
         let edits = selector(&mut self.history);
 
@@ -1636,10 +2976,23 @@ impl Buffer {
         edits
     }
 
-    pub fn set_search(&mut self, pattern: &Pattern) {
+    // large files are only searched around `position`
+    pub fn set_search(&mut self, pattern: &Pattern, position: BufferPosition) {
         self.search_ranges.clear();
+        let line_range = if self.large_file {
+            let line_index = position.line_index as usize;
+            let start = line_index.saturating_sub(LARGE_FILE_SEARCH_LINE_COUNT);
+            let end = self
+                .content
+                .lines()
+                .len()
+                .min(line_index + LARGE_FILE_SEARCH_LINE_COUNT);
+            start..end
+        } else {
+            0..self.content.lines().len()
+        };
         self.content
-            .find_search_ranges(pattern, &mut self.search_ranges);
+            .find_search_ranges(pattern, line_range, &mut self.search_ranges);
     }
 
     pub fn search_ranges(&self) -> &[BufferRange] {
@@ -1649,21 +3002,49 @@ impl Buffer {
     pub fn read_from_file(
         &mut self,
         word_database: &mut WordDatabase,
+        current_directory: &Path,
+        large_file_size: u32,
         events: &mut EditorEventWriter,
+    ) -> Result<(), BufferReadError> {
+        let result =
+            self.read_from_file_no_event(word_database, current_directory, large_file_size);
+        // large files only notify they were read once they finish being read in chunks
+        if self.pending_read.is_none() {
+            events.enqueue(EditorEvent::BufferRead {
+                handle: self.handle,
+            });
+        }
+        result
+    }
+
+    fn read_from_file_no_event(
+        &mut self,
+        word_database: &mut WordDatabase,
+        current_directory: &Path,
+        large_file_size: u32,
     ) -> Result<(), BufferReadError> {
         fn clear_buffer(buffer: &mut Buffer, word_database: &mut WordDatabase) {
             buffer.remove_all_words_from_database(word_database);
             buffer.content.clear();
             buffer.highlighted.clear();
+            buffer.large_file = false;
+        }
+
+        if let Some(pending_read) = self.pending_read.take() {
+            self.properties.read_only = pending_read.read_only;
+        }
+        if self.forced_read_only {
+            self.properties.read_only = false;
+            self.forced_read_only = false;
         }
 
         self.needs_save = false;
         self.history.clear();
         self.search_ranges.clear();
-
-        events.enqueue(EditorEvent::BufferRead {
-            handle: self.handle,
-        });
+        self.file_stamp = None;
+        self.conflicting_file_stamp = None;
+        self.editorconfig = EditorConfig::default();
+        self.detected_indentation = None;
 
         let help_page_name = self.path.to_str().and_then(help::parse_help_page_name);
         if help_page_name.is_none() && !self.properties.file_backed_enabled {
@@ -1674,7 +3055,9 @@ impl Buffer {
 
         if let Some((name, mut reader)) = help_page {
             clear_buffer(self, word_database);
-            self.content.read(&mut reader)?;
+            let format = self.content.read(&mut reader)?;
+            self.set_file_format_from_read(format);
+            self.properties.read_only = true;
 
             let path = std::mem::take(&mut self.path);
             let mut path = path.into_os_string();
@@ -1685,11 +3068,56 @@ impl Buffer {
         } else if self.path.as_os_str().is_empty() {
             return Err(BufferReadError::FileNotFound);
         } else {
+            self.editorconfig = EditorConfig::find(current_directory, &self.path);
+            if let Some(line_ending) = self.editorconfig.line_ending {
+                self.properties.line_ending = line_ending;
+            }
+
             match File::open(&self.path) {
                 Ok(file) => {
-                    clear_buffer(self, word_database);
+                    let metadata = file.metadata().ok();
+                    self.file_stamp = metadata.as_ref().and_then(BufferFileStamp::from_metadata);
+                    let writable = is_file_writable(&self.path, metadata.as_ref());
+                    let file_len = metadata.map(|m| m.len()).unwrap_or(0);
+
+                    // files we can't write to can't be edited either
+                    if !writable && !self.properties.read_only {
+                        self.properties.read_only = true;
+                        self.forced_read_only = true;
+                    }
+
+                    // the previous content is kept (read-only) until the new one is fully read
+                    if large_file_size > 0 && file_len > large_file_size as u64 * 1024 * 1024 {
+                        self.remove_all_words_from_database(word_database);
+                        self.highlighted.clear();
+                        self.large_file = true;
+
+                        let mut content = BufferContent::new();
+                        let content_reader = BufferContentReader::new(&mut content);
+                        self.pending_read = Some(Box::new(BufferPendingRead {
+                            content,
+                            content_reader,
+                            file_len,
+                            read_only: self.properties.read_only,
+                            process_spawned: false,
+                        }));
+                        self.read_process_id = self.read_process_id.wrapping_add(1);
+                        self.properties.read_only = true;
+                        return Ok(());
+                    }
+
                     let mut reader = io::BufReader::new(file);
-                    self.content.read(&mut reader)?;
+                    clear_buffer(self, word_database);
+                    let format = self.content.read(&mut reader)?;
+                    self.set_file_format_from_read(format);
+                    self.detected_indentation = self.content.detect_indentation();
+
+                    if self.properties.history_enabled && self.properties.persistent_history_enabled
+                    {
+                        if let Some(history) = read_history_file(&self.path, &self.content) {
+                            self.history = history;
+                        }
+                    }
                 }
                 Err(error) => {
                     if self.properties.saving_enabled {
@@ -1701,7 +3129,7 @@ impl Buffer {
             }
         }
 
-        if self.properties.word_database_enabled {
+        if self.uses_word_database() {
             for line in &self.content.lines {
                 for word in WordIter(line.as_str()).of_kind(WordKind::Identifier) {
                     word_database.add(word);
@@ -1712,15 +3140,177 @@ impl Buffer {
         Ok(())
     }
 
+    pub(crate) fn spawn_read_process(&mut self, platform: &mut Platform, current_directory: &Path) {
+        let pending_read = match &mut self.pending_read {
+            Some(pending_read) if !pending_read.process_spawned => pending_read,
+            _ => return,
+        };
+        pending_read.process_spawned = true;
+
+        let path = current_directory.join(&self.path);
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.args(["/c", "type"]);
+            command
+        } else {
+            Command::new("cat")
+        };
+        command.arg(path);
+        command.stdin(Stdio::null());
+        command.stdout(Stdio::piped());
+        command.stderr(Stdio::null());
+
+        platform.requests.enqueue(PlatformRequest::SpawnProcess {
+            tag: ProcessTag::BufferRead {
+                buffer_handle: self.handle,
+                id: self.read_process_id,
+            },
+            command,
+            buf_len: LARGE_FILE_READ_BUF_LEN,
+        });
+    }
+
+    pub(crate) fn on_read_process_output(&mut self, id: u32, bytes: &[u8]) {
+        if id != self.read_process_id {
+            return;
+        }
+        if let Some(pending_read) = &mut self.pending_read {
+            let content = &mut pending_read.content;
+            pending_read.content_reader.read_bytes(content, bytes);
+        }
+    }
+
+    pub(crate) fn on_read_process_exit(
+        &mut self,
+        id: u32,
+        events: &mut EditorEventWriter,
+    ) -> Result<(), BufferReadError> {
+        if id != self.read_process_id {
+            return Ok(());
+        }
+        let mut pending_read = match self.pending_read.take() {
+            Some(pending_read) => pending_read,
+            None => return Ok(()),
+        };
+
+        events.enqueue(EditorEvent::BufferRead {
+            handle: self.handle,
+        });
+
+        // the process exit has no status so reading less than the file's length is the only way
+        // to tell it failed. then the previous content is kept read-only so it's never saved over
+        // the file
+        if pending_read.content_reader.read_len() < pending_read.file_len {
+            if !pending_read.read_only {
+                self.forced_read_only = true;
+            }
+            return Err(BufferReadError::Other);
+        }
+
+        self.properties.read_only = pending_read.read_only;
+
+        let format = pending_read
+            .content_reader
+            .finish(&mut pending_read.content);
+        std::mem::swap(&mut self.content, &mut pending_read.content);
+        self.set_file_format_from_read(format);
+        self.detected_indentation = self.content.detect_indentation();
+        Ok(())
+    }
+
+    pub fn is_large_file(&self) -> bool {
+        self.large_file
+    }
+
+    // percentage of a large file that was already read while it's still being read
+    pub fn read_progress(&self) -> Option<u8> {
+        let pending_read = self.pending_read.as_ref()?;
+        let read_len = pending_read.content_reader.read_len();
+        let progress = read_len * 100 / pending_read.file_len.max(1);
+        Some(progress.min(100) as _)
+    }
+
+    fn set_file_format_from_read(&mut self, format: BufferFileFormat) {
+        if let Some(line_ending) = self.editorconfig.line_ending.or(format.line_ending) {
+            self.properties.line_ending = line_ending;
+        }
+        self.properties.byte_order_mark_enabled = format.byte_order_mark;
+    }
+
+    pub fn set_file_format(
+        &mut self,
+        line_ending: BufferLineEnding,
+        byte_order_mark_enabled: bool,
+    ) {
+        if self.properties.line_ending != line_ending
+            || self.properties.byte_order_mark_enabled != byte_order_mark_enabled
+        {
+            self.properties.line_ending = line_ending;
+            self.properties.byte_order_mark_enabled = byte_order_mark_enabled;
+            self.needs_save = true;
+        }
+    }
+
+    fn changed_on_disk(&self) -> Option<BufferFileStamp> {
+        let stamp = BufferFileStamp::from_path(&self.path)?;
+        if self.file_stamp != Some(stamp) {
+            Some(stamp)
+        } else {
+            None
+        }
+    }
+
+    pub fn check_file_changed(
+        &mut self,
+        word_database: &mut WordDatabase,
+        current_directory: &Path,
+        large_file_size: u32,
+        events: &mut EditorEventWriter,
+    ) -> BufferFileChange {
+        if !self.properties.saving_enabled || !self.properties.file_backed_enabled {
+            return BufferFileChange::None;
+        }
+
+        let stamp = match self.changed_on_disk() {
+            Some(stamp) => stamp,
+            None => return BufferFileChange::None,
+        };
+
+        if self.needs_save {
+            if self.conflicting_file_stamp == Some(stamp) {
+                return BufferFileChange::None;
+            }
+            self.conflicting_file_stamp = Some(stamp);
+            return BufferFileChange::Conflict;
+        }
+
+        match self.read_from_file(word_database, current_directory, large_file_size, events) {
+            Ok(()) => BufferFileChange::Reloaded,
+            Err(error) => {
+                // only try again once the file changes again
+                self.file_stamp = Some(stamp);
+                BufferFileChange::ReloadFailed(error)
+            }
+        }
+    }
+
     pub fn write_to_file(
         &mut self,
         new_path: Option<&Path>,
+        force: bool,
+        backup: SaveBackup,
         events: &mut EditorEventWriter,
     ) -> Result<(), BufferWriteError> {
+        if self.pending_read.is_some() {
+            return Err(BufferWriteError::StillReading);
+        }
+
         let new_path = match new_path {
             Some(path) => {
                 self.properties.saving_enabled = true;
                 self.properties.file_backed_enabled = true;
+                self.properties.read_only = false;
+                self.forced_read_only = false;
                 self.set_path(path);
                 true
             }
@@ -1730,10 +3320,48 @@ impl Buffer {
         if !self.properties.saving_enabled {
             return Err(BufferWriteError::SavingDisabled);
         }
+        if self.properties.read_only {
+            return Err(BufferWriteError::ReadOnly);
+        }
 
         if self.properties.file_backed_enabled {
-            let file = File::create(&self.path)?;
-            self.content.write(&mut io::BufWriter::new(file))?;
+            if !new_path && !force && self.changed_on_disk().is_some() {
+                return Err(BufferWriteError::FileChangedOnDisk);
+            }
+
+            let trim_trailing_whitespace = self.editorconfig.trim_trailing_whitespace == Some(true);
+            let insert_final_newline = self.editorconfig.insert_final_newline == Some(true);
+
+            let content = &self.content;
+            let line_ending = self.properties.line_ending;
+            let byte_order_mark = self.properties.byte_order_mark_enabled;
+            write_file_atomically(&self.path, backup, None, &|write| {
+                content.write_formatted(
+                    write,
+                    line_ending,
+                    byte_order_mark,
+                    trim_trailing_whitespace,
+                    insert_final_newline,
+                )
+            })?;
+
+            // the buffer is only edited to match what was written once the write succeeded
+            if trim_trailing_whitespace || insert_final_newline {
+                let mut events = BufferEditMutGuard::new(events, self.handle);
+                if trim_trailing_whitespace {
+                    self.trim_trailing_whitespace(&mut events);
+                }
+                if insert_final_newline {
+                    self.insert_final_newline(&mut events);
+                }
+                self.commit_edits();
+            }
+            self.file_stamp = BufferFileStamp::from_path(&self.path);
+            self.conflicting_file_stamp = None;
+
+            if self.properties.history_enabled && self.properties.persistent_history_enabled {
+                write_history_file(&self.path, &self.content, &self.history);
+            }
         }
 
         self.needs_save = false;
@@ -1885,6 +3513,7 @@ impl BufferCollection {
             let range = insert.range;
             buffer.highlighted.insert_range(range);
             buffer.lints.insert_range(range);
+            buffer.vcs_diff.insert_range(range);
             if buffer.breakpoints.insert_range(range) {
                 breakpoints_changed = true;
             }
@@ -1919,6 +3548,7 @@ impl BufferCollection {
         for &range in deletes {
             buffer.highlighted.delete_range(range);
             buffer.lints.delete_range(range);
+            buffer.vcs_diff.delete_range(range);
             if buffer.breakpoints.delete_range(range) {
                 breakpoints_changed = true;
             }
@@ -2046,10 +3676,53 @@ impl BufferCollection {
         index: u32,
         events: &mut EditorEventWriter,
     ) {
-        self.on_process_output(word_database, index, &[], events);
-        let process = &mut self.insert_processes[index as usize];
-        process.alive = false;
-        process.handle = None;
+        self.on_process_output(word_database, index, &[], events);
+        let process = &mut self.insert_processes[index as usize];
+        process.alive = false;
+        process.handle = None;
+    }
+
+    pub(crate) fn on_vcs_base_process_spawned(
+        &mut self,
+        platform: &mut Platform,
+        handle: BufferHandle,
+        id: u32,
+        process_handle: PlatformProcessHandle,
+    ) {
+        let buffer = &mut self.buffers[handle.0 as usize];
+        buffer
+            .vcs_diff
+            .on_process_spawned(platform, id, process_handle);
+    }
+
+    pub(crate) fn on_vcs_base_process_output(
+        &mut self,
+        handle: BufferHandle,
+        id: u32,
+        bytes: &[u8],
+    ) {
+        let buffer = &mut self.buffers[handle.0 as usize];
+        buffer.vcs_diff.on_process_output(id, bytes);
+    }
+
+    pub(crate) fn on_vcs_base_process_exit(&mut self, handle: BufferHandle, id: u32) {
+        let buffer = &mut self.buffers[handle.0 as usize];
+        buffer.vcs_diff.on_process_exit(id, &buffer.content);
+    }
+
+    pub(crate) fn spawn_read_processes(
+        &mut self,
+        platform: &mut Platform,
+        current_directory: &Path,
+    ) {
+        for buffer in self.iter_mut() {
+            buffer.spawn_read_process(platform, current_directory);
+        }
+    }
+
+    pub(crate) fn on_read_process_output(&mut self, handle: BufferHandle, id: u32, bytes: &[u8]) {
+        let buffer = &mut self.buffers[handle.0 as usize];
+        buffer.on_read_process_output(id, bytes);
     }
 }
 
@@ -2058,6 +3731,27 @@ mod tests {
     use super::*;
     use crate::{buffer_position::BufferPosition, events::EditorEventQueue};
 
+    #[test]
+    fn char_display_widths() {
+        assert_eq!(1, char_display_len('a'));
+        assert_eq!(1, char_display_len('\t'));
+        assert_eq!(1, char_display_len('é'));
+        assert_eq!(1, char_display_len('\u{ad}'));
+        assert_eq!(1, char_display_len('ｱ'));
+        assert_eq!(1, char_display_len('λ'));
+        assert_eq!(0, char_display_len('\u{301}'));
+        assert_eq!(0, char_display_len('\u{200b}'));
+        assert_eq!(0, char_display_len('\u{fe0f}'));
+        assert_eq!(0, char_display_len('\u{1160}'));
+        assert_eq!(2, char_display_len('中'));
+        assert_eq!(2, char_display_len('한'));
+        assert_eq!(2, char_display_len('ア'));
+        assert_eq!(2, char_display_len('Ａ'));
+        assert_eq!(2, char_display_len('😀'));
+        assert_eq!(2, char_display_len('\u{20000}'));
+        assert_eq!(1, char_display_len('\u{10ffff}'));
+    }
+
     #[test]
     fn display_distance() {
         fn display_len(text: &str) -> usize {
@@ -2078,6 +3772,9 @@ mod tests {
         assert_eq!(6, display_len("xx\t"));
         assert_eq!(7, display_len("xxx\t"));
         assert_eq!(8, display_len("xxxx\t"));
+        assert_eq!(4, display_len("中文"));
+        assert_eq!(1, display_len("e\u{301}"));
+        assert_eq!(6, display_len("😀\t"));
     }
 
     fn buffer_from_str(text: &str) -> BufferContent {
@@ -2205,6 +3902,95 @@ mod tests {
         assert_eq!("this\nbuffines\nyes", buffer.to_string());
     }
 
+    #[test]
+    fn buffer_content_many_lines() {
+        fn assert_content(buffer: &BufferContent, expected: &[String]) {
+            assert_eq!(expected.join("\n"), buffer.to_string());
+            let lines = buffer.lines();
+            assert_eq!(expected.len(), lines.len());
+            assert_eq!(expected.len(), lines.iter().len());
+            for (line, display_len) in lines.iter().zip(lines.display_lens(..)) {
+                let expected_len = DisplayLen::from(line.as_str()).total_len(4);
+                assert_eq!(expected_len, display_len.total_len(4));
+            }
+            for (i, line) in lines.iter().enumerate().rev().step_by(97) {
+                assert_eq!(expected[i], line.as_str());
+            }
+            if expected.len() < 8 {
+                return;
+            }
+            let mid = expected.len() / 2;
+            let mut range = lines.range(mid / 2..mid + 3);
+            assert_eq!(
+                Some(expected[mid / 2 + 1].as_str()),
+                range.nth(1).map(|l| l.as_str())
+            );
+            assert_eq!(
+                Some(expected[mid].as_str()),
+                range.nth_back(2).map(|l| l.as_str())
+            );
+            assert_eq!(mid - mid / 2 - 2, range.len());
+        }
+
+        let mut expected: Vec<_> = (0..5000).map(|i| format!("line {}", i)).collect();
+        let mut buffer = buffer_from_str(&expected.join("\n"));
+        assert_content(&buffer, &expected);
+
+        let mut seed: usize = 7;
+        let mut next_random = |max: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % max
+        };
+
+        for i in 0..100 {
+            let line_index = next_random(expected.len());
+            let column_index = expected[line_index].len().min(2);
+            if i % 2 == 0 {
+                let count = next_random(3000);
+                let mut text = String::new();
+                for j in 0..count {
+                    text.push_str("\tnew ");
+                    text.push_str(&j.to_string());
+                    text.push('\n');
+                }
+                text.push_str("end");
+
+                let position = BufferPosition::line_col(line_index as _, column_index as _);
+                buffer.insert_text(position, &text);
+
+                let split = expected[line_index].split_off(column_index);
+                let mut new_lines: Vec<_> = text.split('\n').map(String::from).collect();
+                expected[line_index].push_str(&new_lines.remove(0));
+                if let Some(last) = new_lines.last_mut() {
+                    last.push_str(&split);
+                } else {
+                    expected[line_index].push_str(&split);
+                }
+                expected.splice(line_index + 1..line_index + 1, new_lines);
+            } else {
+                let to_line_index = (line_index + 1 + next_random(4000)).min(expected.len() - 1);
+                let to_column_index = expected[to_line_index].len().min(3);
+                buffer.delete_range(BufferRange::between(
+                    BufferPosition::line_col(line_index as _, column_index as _),
+                    BufferPosition::line_col(to_line_index as _, to_column_index as _),
+                ));
+
+                let to_line = expected[to_line_index][to_column_index..].to_string();
+                expected.drain(line_index + 1..to_line_index + 1);
+                expected[line_index].truncate(column_index);
+                expected[line_index].push_str(&to_line);
+            }
+            assert_content(&buffer, &expected);
+        }
+
+        buffer.delete_range(BufferRange::between(BufferPosition::zero(), buffer.end()));
+        expected.clear();
+        expected.push(String::new());
+        assert_content(&buffer, &expected);
+    }
+
     #[test]
     fn buffer_content_delete_lines() {
         let mut buffer = buffer_from_str("first line\nsecond line\nthird line");
@@ -2397,6 +4183,213 @@ mod tests {
         assert_eq!(None, text_range.next());
     }
 
+    #[test]
+    fn buffer_content_read_write_file_format() {
+        fn round_trip(text: &str) -> (BufferFileFormat, String) {
+            let mut buffer = BufferContent::new();
+            let format = buffer.read(&mut text.as_bytes()).unwrap();
+
+            let mut written = Vec::new();
+            buffer
+                .write(
+                    &mut written,
+                    format.line_ending.unwrap_or_default(),
+                    format.byte_order_mark,
+                )
+                .unwrap();
+            (format, String::from_utf8(written).unwrap())
+        }
+
+        let (format, written) = round_trip("");
+        assert_eq!(None, format.line_ending);
+        assert!(!format.byte_order_mark);
+        assert_eq!("", written);
+
+        let (format, written) = round_trip("first\nsecond\n");
+        assert_eq!(Some(BufferLineEnding::Lf), format.line_ending);
+        assert!(!format.byte_order_mark);
+        assert_eq!("first\nsecond\n", written);
+
+        let (format, written) = round_trip("first\r\nsecond\r\n");
+        assert_eq!(Some(BufferLineEnding::CrLf), format.line_ending);
+        assert!(!format.byte_order_mark);
+        assert_eq!("first\r\nsecond\r\n", written);
+
+        let (format, written) = round_trip("\u{feff}first\r\nsecond");
+        assert_eq!(Some(BufferLineEnding::CrLf), format.line_ending);
+        assert!(format.byte_order_mark);
+        assert_eq!("\u{feff}first\r\nsecond", written);
+
+        let mut buffer = BufferContent::new();
+        buffer.read(&mut "\u{feff}first\r\n".as_bytes()).unwrap();
+        assert_eq!("first", buffer.lines()[0].as_str());
+        assert_eq!("", buffer.lines()[1].as_str());
+    }
+
+    #[test]
+    fn buffer_content_read_write_invalid_utf8() {
+        let bytes = b"a\xffb\xe2\x82\n\xf4\x8f\xba\x80\xc3\xa9\n\x80";
+        let mut buffer = BufferContent::new();
+        let format = buffer.read(&mut &bytes[..]).unwrap();
+
+        assert_eq!(3, buffer.lines().len());
+        let first_line = buffer.lines()[0].as_str();
+        let mut chars = first_line.chars();
+        assert_eq!(Some('a'), chars.next());
+        assert_eq!(Some(0xff), chars.next().and_then(char_to_raw_byte));
+        assert_eq!(Some('b'), chars.next());
+        assert_eq!(Some(0xe2), chars.next().and_then(char_to_raw_byte));
+        assert_eq!(Some(0x82), chars.next().and_then(char_to_raw_byte));
+        assert_eq!(None, chars.next());
+        assert_eq!(1 + 4 + 1 + 4 + 4, DisplayLen::from(first_line).len);
+
+        let second_line = buffer.lines()[1].as_str();
+        assert_eq!(5, second_line.chars().count());
+        assert!(second_line.ends_with('é'));
+
+        let mut written = Vec::new();
+        buffer
+            .write(
+                &mut written,
+                format.line_ending.unwrap_or_default(),
+                format.byte_order_mark,
+            )
+            .unwrap();
+        assert_eq!(&bytes[..], &written[..]);
+    }
+
+    #[test]
+    fn buffer_content_read_in_chunks() {
+        let text = "first\r\nsecond\r\nthird\r\n";
+        let mut content = BufferContent::new();
+        let mut reader = BufferContentReader::new(&mut content);
+
+        reader.read_bytes(&mut content, &text.as_bytes()[..3]);
+        assert_eq!(3, reader.read_len());
+        assert_eq!(0, content.lines().len());
+
+        reader.read_bytes(&mut content, &text.as_bytes()[3..10]);
+        assert_eq!(10, reader.read_len());
+        assert_eq!(1, content.lines().len());
+        assert_eq!("first", content.lines()[0].as_str());
+
+        reader.read_bytes(&mut content, &text.as_bytes()[10..]);
+        let format = reader.finish(&mut content);
+        assert_eq!(Some(BufferLineEnding::CrLf), format.line_ending);
+        assert_eq!(text.len() as u64, reader.read_len());
+        assert_eq!(4, content.lines().len());
+        assert_eq!("second", content.lines()[1].as_str());
+        assert_eq!("third", content.lines()[2].as_str());
+        assert_eq!("", content.lines()[3].as_str());
+
+        let mut content = BufferContent::new();
+        let mut reader = BufferContentReader::new(&mut content);
+        reader.read_bytes(&mut content, b"\xef\xbb\xbfno");
+        reader.read_bytes(&mut content, b" line ending");
+        let format = reader.finish(&mut content);
+        assert_eq!(None, format.line_ending);
+        assert!(format.byte_order_mark);
+        assert_eq!("no line ending", content.to_string());
+    }
+
+    #[test]
+    fn buffer_read_large_file_from_process_output() {
+        let path = temp_test_path("read-large-file.txt");
+        let mut text = String::new();
+        while text.len() <= 1024 * 1024 {
+            text.push_str("some line of a large file\n");
+        }
+        fs::write(&path, &text).unwrap();
+
+        let mut word_database = WordDatabase::new();
+        let mut events = EditorEventQueue::default();
+        let mut buffer = Buffer::new(BufferHandle(0));
+        buffer.properties = BufferProperties::text();
+        buffer.set_path(&path);
+        buffer.insert_text(
+            &mut word_database,
+            BufferPosition::zero(),
+            "previous",
+            &mut events
+                .writer()
+                .buffer_text_inserts_mut_guard(buffer.handle()),
+        );
+
+        assert!(buffer
+            .read_from_file(&mut word_database, Path::new(""), 1, &mut events.writer())
+            .is_ok());
+        assert!(buffer.is_large_file());
+        assert!(buffer.properties.read_only);
+        assert_eq!("previous", buffer.content().to_string());
+
+        // outputs from the process of a previous read are ignored
+        let id = buffer.read_process_id;
+        buffer.on_read_process_output(id.wrapping_sub(1), b"stale\n");
+        let (first_half, second_half) = text.as_bytes().split_at(text.len() / 2);
+        for chunk in first_half.chunks(1000) {
+            buffer.on_read_process_output(id, chunk);
+        }
+        assert_eq!(Some(50), buffer.read_progress());
+        assert_eq!("previous", buffer.content().to_string());
+        for chunk in second_half.chunks(1000) {
+            buffer.on_read_process_output(id, chunk);
+        }
+        assert!(buffer
+            .on_read_process_exit(id, &mut events.writer())
+            .is_ok());
+
+        assert!(buffer.read_progress().is_none());
+        assert!(!buffer.properties.read_only);
+        assert_eq!(text, buffer.content().to_string());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn buffer_read_large_file_keeps_previous_content_when_truncated() {
+        let path = temp_test_path("read-truncated-large-file.txt");
+        let mut text = String::new();
+        while text.len() <= 1024 * 1024 {
+            text.push_str("some line of a large file\n");
+        }
+        fs::write(&path, &text).unwrap();
+
+        let mut word_database = WordDatabase::new();
+        let mut events = EditorEventQueue::default();
+        let mut buffer = Buffer::new(BufferHandle(0));
+        buffer.properties = BufferProperties::text();
+        buffer.set_path(&path);
+        buffer.insert_text(
+            &mut word_database,
+            BufferPosition::zero(),
+            "previous",
+            &mut events
+                .writer()
+                .buffer_text_inserts_mut_guard(buffer.handle()),
+        );
+
+        assert!(buffer
+            .read_from_file(&mut word_database, Path::new(""), 1, &mut events.writer())
+            .is_ok());
+        let id = buffer.read_process_id;
+        buffer.on_read_process_output(id, &text.as_bytes()[..text.len() / 2]);
+        assert!(matches!(
+            buffer.on_read_process_exit(id, &mut events.writer()),
+            Err(BufferReadError::Other)
+        ));
+        assert!(buffer.read_progress().is_none());
+        assert!(buffer.properties.read_only);
+        assert_eq!("previous", buffer.content().to_string());
+
+        assert!(buffer
+            .read_from_file(&mut word_database, Path::new(""), 0, &mut events.writer())
+            .is_ok());
+        assert!(!buffer.properties.read_only);
+        assert_eq!(text, buffer.content().to_string());
+
+        fs::remove_file(&path).unwrap();
+    }
+
     #[test]
     fn buffer_content_word_at() {
         fn col(column: usize) -> BufferPosition {
@@ -2509,10 +4502,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn buffer_find_balanced_chars_around() {
+        let buffer = buffer_from_str("(\n(a)\n(b c)\n)");
+
+        let range = |from: (u32, u32), to: (u32, u32)| {
+            BufferRange::between(
+                BufferPosition::line_col(from.0 as _, from.1 as _),
+                BufferPosition::line_col(to.0 as _, to.1 as _),
+            )
+        };
+
+        assert_eq!(
+            Some(range((2, 1), (2, 4))),
+            buffer.find_balanced_chars_around(range((2, 3), (2, 4)), '(', ')')
+        );
+        assert_eq!(
+            Some(range((2, 1), (2, 4))),
+            buffer.find_balanced_chars_around(range((2, 1), (2, 4)), '(', ')')
+        );
+        assert_eq!(
+            Some(range((0, 1), (3, 0))),
+            buffer.find_balanced_chars_around(range((2, 0), (2, 5)), '(', ')')
+        );
+        assert_eq!(
+            Some(range((0, 1), (3, 0))),
+            buffer.find_balanced_chars_around(range((1, 0), (2, 5)), '(', ')')
+        );
+        assert_eq!(
+            None,
+            buffer.find_balanced_chars_around(range((0, 0), (3, 1)), '(', ')')
+        );
+    }
+
     #[test]
     fn buffer_display_len() {
         fn len(buffer: &BufferContent, line: usize) -> usize {
-            buffer.line_display_lens()[line].total_len(4)
+            buffer.lines().display_len(line).total_len(4)
         }
 
         let mut buffer = buffer_from_str("abc\tdef");
@@ -2544,6 +4570,9 @@ mod tests {
         assert_eq!(0, len(&buffer, 0));
         assert_eq!(6, len(&buffer, 1));
         assert_eq!(3, len(&buffer, 2));
+        buffer.insert_text(BufferPosition::line_col(2, 0), "日本e\u{301}");
+
+        assert_eq!(8, len(&buffer, 2));
     }
 
     #[test]
@@ -2563,6 +4592,7 @@ mod tests {
             buffer
         }
 
+        let mut syntaxes = SyntaxCollection::new();
         let mut events = EditorEventQueue::default();
         let mut events = BufferEditMutGuard::new(events.writer(), BufferHandle(0));
 
@@ -2572,91 +4602,91 @@ mod tests {
         };
 
         let mut buffer = new_buffer("");
-        buffer.fix_line_indentation(indentation_config, 0, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 0, &mut events);
         assert_eq!("", buffer.content().lines()[0].as_str());
 
         let mut buffer = new_buffer("first\nsecond");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("second", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("\tfirst\nsecond");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("\tsecond", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("\tfirst\n    second");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("\tsecond", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("\t\tfirst\n \tsecond");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("\t\tsecond", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("\t\tfirst\n\t second");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("\t\tsecond", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("\t\tfirst }\n second");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("\t\tsecond", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("\t\tfirst } {}\n second");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("\t\tsecond", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("\t\tfirst {}\n second");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("\t\tsecond", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("\t\tfirst { ( }\n second");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("\t\tsecond", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("\t\tfirst {\n second");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("\t\t\tsecond", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("\t\t{\n second");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("\t\t\tsecond", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("{\n second");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("\tsecond", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("{}()[\n second");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("\tsecond", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("{}()[]>\n second");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("second", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("\t\n\t");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("\t\n    ");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("{\n}");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("}", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("\t{}\n}");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("}", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("first\n}");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("}", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("{{\n}");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("}", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("\tfirst\n\nsecond");
-        buffer.fix_line_indentation(indentation_config, 2, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 2, &mut events);
         assert_eq!("\tsecond", buffer.content().lines()[2].as_str());
 
         let indentation_config = BufferIndentationConfig {
@@ -2665,19 +4695,456 @@ mod tests {
         };
 
         let mut buffer = new_buffer("\tfirst\n second");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("    second", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("    first\n second");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("    second", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("        first\n second");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("        second", buffer.content().lines()[1].as_str());
 
         let mut buffer = new_buffer("     first\n second");
-        buffer.fix_line_indentation(indentation_config, 1, &mut events);
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
         assert_eq!("        second", buffer.content().lines()[1].as_str());
+
+        syntaxes.get_last().set_indent("{!(then$)!(do$).}").unwrap();
+        syntaxes.get_last().set_dedent("end%b|else%b").unwrap();
+
+        let mut buffer = new_buffer("if a then\nb");
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
+        assert_eq!("    b", buffer.content().lines()[1].as_str());
+
+        let mut buffer = new_buffer("  then_b()\nc");
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
+        assert_eq!("    c", buffer.content().lines()[1].as_str());
+
+        let mut buffer = new_buffer("    b\nend");
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
+        assert_eq!("end", buffer.content().lines()[1].as_str());
+
+        let mut buffer = new_buffer("    b\n    ending()");
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
+        assert_eq!("    ending()", buffer.content().lines()[1].as_str());
+
+        let mut buffer = new_buffer("if a then\nelse");
+        buffer.fix_line_indentation(indentation_config, &syntaxes, 1, &mut events);
+        assert_eq!("else", buffer.content().lines()[1].as_str());
+    }
+
+    fn temp_test_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("pepper-test-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn buffer_check_file_changed() {
+        let path = temp_test_path("check-file-changed.txt");
+        fs::write(&path, "first").unwrap();
+
+        let mut word_database = WordDatabase::new();
+        let mut events = EditorEventQueue::default();
+
+        let mut buffer = Buffer::new(BufferHandle(0));
+        buffer.properties = BufferProperties::text();
+        buffer.set_path(&path);
+        assert!(buffer
+            .read_from_file(&mut word_database, Path::new(""), 0, &mut events.writer())
+            .is_ok());
+        assert!(buffer.changed_on_disk().is_none());
+
+        let mut check = |buffer: &mut Buffer| {
+            buffer.check_file_changed(&mut word_database, Path::new(""), 0, &mut events.writer())
+        };
+        assert!(matches!(check(&mut buffer), BufferFileChange::None));
+
+        fs::write(&path, "second text").unwrap();
+        assert!(buffer.changed_on_disk().is_some());
+        assert!(matches!(check(&mut buffer), BufferFileChange::Reloaded));
+        assert_eq!("second text", buffer.content().to_string());
+        assert!(matches!(check(&mut buffer), BufferFileChange::None));
+
+        buffer.needs_save = true;
+        fs::write(&path, "third").unwrap();
+        assert!(matches!(check(&mut buffer), BufferFileChange::Conflict));
+        assert!(matches!(check(&mut buffer), BufferFileChange::None));
+        assert_eq!("second text", buffer.content().to_string());
+
+        buffer.needs_save = false;
+        fs::remove_file(&path).unwrap();
+        fs::create_dir(&path).unwrap();
+        assert!(matches!(
+            check(&mut buffer),
+            BufferFileChange::ReloadFailed(_)
+        ));
+        assert!(matches!(check(&mut buffer), BufferFileChange::None));
+
+        fs::remove_dir(&path).unwrap();
+        assert!(matches!(check(&mut buffer), BufferFileChange::None));
+    }
+
+    #[test]
+    fn buffer_write_to_file_formats_content_only_when_written() {
+        let mut word_database = WordDatabase::new();
+        let mut events = EditorEventQueue::default();
+        let mut buffer = Buffer::new(BufferHandle(0));
+        buffer.properties = BufferProperties::text();
+        buffer.editorconfig.trim_trailing_whitespace = Some(true);
+        buffer.editorconfig.insert_final_newline = Some(true);
+        buffer.insert_text(
+            &mut word_database,
+            BufferPosition::zero(),
+            "a  \nb\t",
+            &mut events
+                .writer()
+                .buffer_text_inserts_mut_guard(buffer.handle()),
+        );
+        buffer.commit_edits();
+
+        buffer.set_path(&temp_test_path("missing").join("file.txt"));
+        assert!(buffer
+            .write_to_file(None, true, SaveBackup::None, &mut events.writer())
+            .is_err());
+        assert_eq!("a  \nb\t", buffer.content().to_string());
+
+        let path = temp_test_path("write-formatted.txt");
+        buffer.set_path(&path);
+        assert!(buffer
+            .write_to_file(None, true, SaveBackup::None, &mut events.writer())
+            .is_ok());
+        assert_eq!("a\nb\n", fs::read_to_string(&path).unwrap());
+        assert_eq!("a\nb\n", buffer.content().to_string());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn buffer_write_file_atomically() {
+        fn write_text(path: &Path, backup: SaveBackup, text: &str) -> Result<(), BufferWriteError> {
+            write_file_atomically(path, backup, None, &|write| {
+                write.write_all(text.as_bytes())
+            })
+        }
+
+        fn file_names(directory: &Path) -> Vec<String> {
+            let mut names: Vec<_> = fs::read_dir(directory)
+                .unwrap()
+                .map(|e| e.unwrap().file_name().into_string().unwrap())
+                .collect();
+            names.sort_unstable();
+            names
+        }
+
+        let directory = temp_test_path("write-file-atomically");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("file.txt");
+
+        assert!(write_text(&path, SaveBackup::Single, "first").is_ok());
+        assert_eq!("first", fs::read_to_string(&path).unwrap());
+        assert_eq!(["file.txt"], &file_names(&directory)[..]);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+
+        let unrelated_file_name = format!(".file.txt.{}-0.pepper-save", std::process::id());
+        fs::write(directory.join(&unrelated_file_name), "unrelated").unwrap();
+
+        assert!(write_text(&path, SaveBackup::Single, "second").is_ok());
+        assert_eq!("second", fs::read_to_string(&path).unwrap());
+        assert_eq!(
+            "first",
+            fs::read_to_string(directory.join("file.txt.bak")).unwrap()
+        );
+        assert_eq!(
+            "unrelated",
+            fs::read_to_string(directory.join(&unrelated_file_name)).unwrap()
+        );
+        assert_eq!(
+            [unrelated_file_name.as_str(), "file.txt", "file.txt.bak"],
+            &file_names(&directory)[..]
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(0o640, mode & 0o777);
+        }
+
+        let result = write_file_atomically(&path, SaveBackup::None, None, &|write| {
+            write.write_all(b"partial")?;
+            Err(io::Error::from(io::ErrorKind::Other))
+        });
+        assert!(matches!(result, Err(BufferWriteError::CouldNotWriteToFile)));
+        assert_eq!("second", fs::read_to_string(&path).unwrap());
+        assert_eq!(3, file_names(&directory).len());
+
+        fs::remove_file(directory.join(&unrelated_file_name)).unwrap();
+        fs::remove_file(directory.join("file.txt.bak")).unwrap();
+        assert!(write_text(&path, SaveBackup::Timestamped, "third").is_ok());
+        assert_eq!("third", fs::read_to_string(&path).unwrap());
+        let file_names = file_names(&directory);
+        assert_eq!(2, file_names.len());
+        let backup_file_name = &file_names[1];
+        let timestamp = backup_file_name
+            .strip_prefix("file.txt.")
+            .and_then(|n| n.strip_suffix(".bak"))
+            .unwrap();
+        assert!(timestamp.chars().all(|c| c.is_ascii_digit()));
+        assert_eq!(
+            "second",
+            fs::read_to_string(directory.join(backup_file_name)).unwrap()
+        );
+
+        let missing_directory_path = directory.join("missing").join("file.txt");
+        assert!(matches!(
+            write_text(&missing_directory_path, SaveBackup::Single, "text"),
+            Err(BufferWriteError::CouldNotCreateTemporaryFile)
+        ));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let private_path = directory.join("private.txt");
+            let permissions = fs::Permissions::from_mode(0o600);
+            for text in ["first", "second"] {
+                let result = write_file_atomically(
+                    &private_path,
+                    SaveBackup::None,
+                    Some(permissions.clone()),
+                    &|write| write.write_all(text.as_bytes()),
+                );
+                assert!(result.is_ok());
+                let mode = fs::metadata(&private_path).unwrap().permissions().mode();
+                assert_eq!(0o600, mode & 0o777);
+            }
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn buffer_content_detect_indentation() {
+        fn detect(text: &str) -> Option<(bool, Option<u8>)> {
+            buffer_from_str(text)
+                .detect_indentation()
+                .map(|i| (i.indent_with_tabs, i.tab_size))
+        }
+
+        assert_eq!(None, detect(""));
+        assert_eq!(None, detect("a\nb\n\n  \nc"));
+        assert_eq!(Some((true, None)), detect("a {\n\tb {\n\t\tc\n\t}\n}"));
+        assert_eq!(Some((false, Some(2))), detect("a {\n  b {\n    c\n  }\n}"));
+        assert_eq!(
+            Some((false, Some(4))),
+            detect("a {\n    b {\n        c\n    }\n    d(\n        e)\n}"),
+        );
+        assert_eq!(
+            Some((false, Some(4))),
+            detect("/**\n * doc\n */\na {\n    b\n}"),
+        );
+        assert_eq!(Some((true, None)), detect("a\n\tb\n\tc\n  d\n\te"),);
+        assert_eq!(Some((false, Some(1))), detect("a\n b\n  c"));
+    }
+
+    #[test]
+    fn buffer_resolve_config_layers() {
+        let mut buffer = Buffer::new(BufferHandle(0));
+        buffer.path = PathBuf::from("src/main.c");
+        buffer.detected_indentation = Some(BufferDetectedIndentation {
+            indent_with_tabs: true,
+            tab_size: Some(2),
+        });
+
+        let mut glob_overrides = GlobConfigOverrides::default();
+        let config = buffer.resolve_config(&Config::default(), &glob_overrides);
+        assert!(config.indent_with_tabs);
+        assert_eq!(2, config.tab_size);
+
+        let overrides = glob_overrides.get_or_add("**/*.c").unwrap();
+        assert!(overrides.parse_config("tab_size", "8").is_ok());
+        let config = buffer.resolve_config(&Config::default(), &glob_overrides);
+        assert!(config.indent_with_tabs);
+        assert_eq!(8, config.tab_size);
+
+        let overrides = glob_overrides.get_or_add("**/*.c").unwrap();
+        assert!(overrides
+            .parse_config("detect_indentation", "false")
+            .is_ok());
+        let config = buffer.resolve_config(&Config::default(), &glob_overrides);
+        assert!(!config.indent_with_tabs);
+        assert_eq!(8, config.tab_size);
+
+        buffer.config_overrides.tab_size = Some(3);
+        let config = buffer.resolve_config(&Config::default(), &glob_overrides);
+        assert_eq!(3, config.tab_size);
+    }
+
+    #[test]
+    fn buffer_retab() {
+        let handle = BufferHandle(0);
+        let mut buffer = Buffer::new(handle);
+        let mut word_database = WordDatabase::new();
+        let mut events = EditorEventQueue::default();
+        let mut events = BufferEditMutGuard::new(events.writer(), handle);
+        buffer.insert_text(
+            &mut word_database,
+            BufferPosition::zero(),
+            "a\n    b\n\t  c\n  \t d\n   \n e",
+            events.to_text_inserts(),
+        );
+
+        let line_count = buffer.content().lines().len() as _;
+        let indentation_config = BufferIndentationConfig {
+            tab_size: 4,
+            indent_with_tabs: true,
+        };
+        buffer.retab(indentation_config, 0..line_count, &mut events);
+        let lines: Vec<_> = buffer
+            .content()
+            .lines()
+            .iter()
+            .map(|l| l.as_str())
+            .collect();
+        assert_eq!(["a", "\tb", "\t  c", "\t d", "   ", " e"], &lines[..]);
+
+        let indentation_config = BufferIndentationConfig {
+            tab_size: 2,
+            indent_with_tabs: false,
+        };
+        buffer.retab(indentation_config, 1..3, &mut events);
+        let lines: Vec<_> = buffer
+            .content()
+            .lines()
+            .iter()
+            .map(|l| l.as_str())
+            .collect();
+        assert_eq!(["a", "  b", "    c", "\t d", "   ", " e"], &lines[..]);
+        assert_eq!(5, buffer.content().lines().display_len(2).total_len(4));
+    }
+
+    #[test]
+    fn buffer_read_only() {
+        let handle = BufferHandle(0);
+        let mut buffer = Buffer::new(handle);
+        let mut word_database = WordDatabase::new();
+        let mut events = EditorEventQueue::default();
+        let mut events = BufferEditMutGuard::new(events.writer(), handle);
+        buffer.insert_text(
+            &mut word_database,
+            BufferPosition::zero(),
+            "first\n\tsecond",
+            events.to_text_inserts(),
+        );
+        buffer.properties.read_only = true;
+
+        let range = buffer.insert_text(
+            &mut word_database,
+            BufferPosition::zero(),
+            "text",
+            events.to_text_inserts(),
+        );
+        assert_eq!(BufferRange::zero(), range);
+        buffer.delete_range(
+            &mut word_database,
+            BufferRange::between(BufferPosition::zero(), BufferPosition::line_col(1, 0)),
+            events.to_range_deletes(),
+        );
+        let indentation_config = BufferIndentationConfig {
+            tab_size: 4,
+            indent_with_tabs: false,
+        };
+        buffer.retab(indentation_config, 0..2, &mut events);
+        buffer.fix_line_indentation(indentation_config, &SyntaxCollection::new(), 1, &mut events);
+
+        let lines: Vec<_> = buffer
+            .content()
+            .lines()
+            .iter()
+            .map(|l| l.as_str())
+            .collect();
+        assert_eq!(["first", "\tsecond"], &lines[..]);
+
+        let mut events = EditorEventQueue::default();
+        buffer.set_read_only_content(&mut word_database, "listed\nthings", events.writer());
+        assert!(buffer.properties.read_only);
+        assert_eq!("listed\nthings", buffer.content().to_string());
+    }
+
+    #[test]
+    fn buffer_read_only_from_file_permissions() {
+        let path = temp_test_path("read-only-from-file-permissions.txt");
+        fs::write(&path, "text").unwrap();
+
+        let mut word_database = WordDatabase::new();
+        let mut events = EditorEventQueue::default();
+        let mut buffer = Buffer::new(BufferHandle(0));
+        buffer.properties = BufferProperties::text();
+        buffer.set_path(&path);
+
+        // a buffer that was read-only because its file was not writable is editable once it is
+        buffer.properties.read_only = true;
+        buffer.forced_read_only = true;
+        assert!(buffer
+            .read_from_file(&mut word_database, Path::new(""), 0, &mut events.writer())
+            .is_ok());
+        assert!(!buffer.properties.read_only);
+
+        buffer.properties.read_only = true;
+        assert!(buffer
+            .read_from_file(&mut word_database, Path::new(""), 0, &mut events.writer())
+            .is_ok());
+        assert!(buffer.properties.read_only);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn buffer_word_database_edits() {
+        let handle = BufferHandle(0);
+        let mut buffer = Buffer::new(handle);
+        buffer.properties = BufferProperties::text();
+        let mut word_database = WordDatabase::new();
+        let mut events = EditorEventQueue::default();
+        let mut events = BufferEditMutGuard::new(events.writer(), handle);
+
+        let mut insert = |buffer: &mut Buffer, line, col, text| {
+            let position = BufferPosition::line_col(line, col);
+            buffer.insert_text(&mut word_database, position, text, events.to_text_inserts());
+        };
+        insert(&mut buffer, 0, 0, "first line\nsecond(word)");
+        insert(&mut buffer, 0, 3, "x");
+        insert(&mut buffer, 0, 6, " ");
+        insert(&mut buffer, 1, 7, "other\nlines\n");
+        insert(&mut buffer, 2, 5, "_");
+        let mut delete = |buffer: &mut Buffer, from_line, from_col, to_line, to_col| {
+            let range = BufferRange::between(
+                BufferPosition::line_col(from_line, from_col),
+                BufferPosition::line_col(to_line, to_col),
+            );
+            buffer.delete_range(&mut word_database, range, events.to_range_deletes());
+        };
+        delete(&mut buffer, 0, 6, 0, 7);
+        delete(&mut buffer, 1, 3, 2, 2);
+
+        let lines: Vec<_> = buffer
+            .content()
+            .lines()
+            .iter()
+            .map(|l| l.as_str())
+            .collect();
+        assert_eq!(["firxst line", "secnes_", "word)"], &lines[..]);
+
+        let mut words: Vec<_> = word_database.word_indices().map(|(_, w)| w).collect();
+        words.sort_unstable();
+        assert_eq!(["firxst", "line", "secnes_", "word"], &words[..]);
+
+        buffer.remove_all_words_from_database(&mut word_database);
+        assert_eq!(0, word_database.word_indices().count());
     }
 }
@@ -1,6 +1,9 @@
 use std::ops::Range;
 
-use crate::buffer_position::{BufferPosition, BufferRange};
+use crate::{
+    buffer_position::{BufferPosition, BufferRange},
+    serialization::{DeserializeError, Deserializer, Serialize, Serializer},
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EditKind {
@@ -15,7 +18,7 @@ pub struct Edit<'a> {
     pub text: &'a str,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct EditInternal {
     pub kind: EditKind,
     pub buffer_range: BufferRange,
@@ -36,11 +39,13 @@ impl EditInternal {
     }
 }
 
+#[derive(Clone)]
 enum HistoryState {
     IterIndex { group_index: usize },
     InsertGroup { edit_index: usize },
 }
 
+#[derive(Clone)]
 pub(crate) struct BufferHistory {
     texts: String,
     edits: Vec<EditInternal>,
@@ -373,6 +378,39 @@ impl BufferHistory {
         }
     }
 
+    // forgets the oldest committed groups so that at most `max_group_count` of them can be undone
+    pub fn limit_group_count(&mut self, max_group_count: usize) {
+        let group_index = match self.state {
+            HistoryState::IterIndex { group_index } => group_index,
+            HistoryState::InsertGroup { .. } => return,
+        };
+        if group_index <= max_group_count {
+            return;
+        }
+
+        let removed_group_count = group_index - max_group_count;
+        let removed_edit_count = self.group_ranges[removed_group_count].start;
+        let removed_text_len = match self.edits.get(removed_edit_count) {
+            Some(edit) => edit.text_range.start,
+            None => self.texts.len() as _,
+        };
+
+        self.texts.drain(..removed_text_len as usize);
+        self.edits.drain(..removed_edit_count);
+        for edit in &mut self.edits {
+            edit.text_range.start -= removed_text_len;
+            edit.text_range.end -= removed_text_len;
+        }
+        self.group_ranges.drain(..removed_group_count);
+        for range in &mut self.group_ranges {
+            range.start -= removed_edit_count;
+            range.end -= removed_edit_count;
+        }
+        self.state = HistoryState::IterIndex {
+            group_index: max_group_count,
+        };
+    }
+
     pub fn undo_edits(
         &mut self,
     ) -> impl Clone + ExactSizeIterator<Item = Edit> + DoubleEndedIterator<Item = Edit> {
@@ -424,6 +462,111 @@ impl BufferHistory {
     }
 }
 
+impl<'de> Serialize<'de> for BufferHistory {
+    fn serialize(&self, serializer: &mut dyn Serializer) {
+        fn serialize_position(position: BufferPosition, serializer: &mut dyn Serializer) {
+            position.line_index.serialize(serializer);
+            position.column_byte_index.serialize(serializer);
+        }
+
+        // an uncommitted group is serialized as if it was committed
+        let (pending_group, group_index) = match self.state {
+            HistoryState::IterIndex { group_index } => (None, group_index),
+            HistoryState::InsertGroup { edit_index } => (
+                Some(edit_index..self.edits.len()),
+                self.group_ranges.len() + 1,
+            ),
+        };
+
+        self.texts.as_str().serialize(serializer);
+
+        (self.edits.len() as u32).serialize(serializer);
+        for edit in &self.edits {
+            let kind: u8 = match edit.kind {
+                EditKind::Insert => 0,
+                EditKind::Delete => 1,
+            };
+            kind.serialize(serializer);
+            serialize_position(edit.buffer_range.from, serializer);
+            serialize_position(edit.buffer_range.to, serializer);
+            edit.text_range.start.serialize(serializer);
+            edit.text_range.end.serialize(serializer);
+        }
+
+        let group_count = self.group_ranges.len() + pending_group.is_some() as usize;
+        (group_count as u32).serialize(serializer);
+        for range in self.group_ranges.iter().chain(pending_group.iter()) {
+            (range.start as u32).serialize(serializer);
+            (range.end as u32).serialize(serializer);
+        }
+
+        (group_index as u32).serialize(serializer);
+    }
+
+    fn deserialize(deserializer: &mut dyn Deserializer<'de>) -> Result<Self, DeserializeError> {
+        fn deserialize_position<'de>(
+            deserializer: &mut dyn Deserializer<'de>,
+        ) -> Result<BufferPosition, DeserializeError> {
+            let line_index = Serialize::deserialize(deserializer)?;
+            let column_byte_index = Serialize::deserialize(deserializer)?;
+            Ok(BufferPosition::line_col(line_index, column_byte_index))
+        }
+
+        let texts = <&str>::deserialize(deserializer)?;
+
+        let edit_count = u32::deserialize(deserializer)?;
+        let mut edits = Vec::new();
+        for _ in 0..edit_count {
+            let kind = match u8::deserialize(deserializer)? {
+                0 => EditKind::Insert,
+                1 => EditKind::Delete,
+                _ => return Err(DeserializeError::InvalidData),
+            };
+            let from = deserialize_position(deserializer)?;
+            let to = deserialize_position(deserializer)?;
+            let text_start = u32::deserialize(deserializer)?;
+            let text_end = u32::deserialize(deserializer)?;
+
+            if text_start > text_end
+                || text_end as usize > texts.len()
+                || !texts.is_char_boundary(text_start as _)
+                || !texts.is_char_boundary(text_end as _)
+            {
+                return Err(DeserializeError::InvalidData);
+            }
+
+            edits.push(EditInternal {
+                kind,
+                buffer_range: BufferRange::between(from, to),
+                text_range: text_start..text_end,
+            });
+        }
+
+        let group_count = u32::deserialize(deserializer)?;
+        let mut group_ranges = Vec::new();
+        for _ in 0..group_count {
+            let start = u32::deserialize(deserializer)? as usize;
+            let end = u32::deserialize(deserializer)? as usize;
+            if start > end || end > edits.len() {
+                return Err(DeserializeError::InvalidData);
+            }
+            group_ranges.push(start..end);
+        }
+
+        let group_index = u32::deserialize(deserializer)? as usize;
+        if group_index > group_ranges.len() {
+            return Err(DeserializeError::InvalidData);
+        }
+
+        Ok(Self {
+            texts: texts.into(),
+            edits,
+            group_ranges,
+            state: HistoryState::IterIndex { group_index },
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -517,6 +660,30 @@ mod tests {
         assert_eq!(0, history.undo_edits().count());
     }
 
+    #[test]
+    fn limit_group_count() {
+        let mut history = BufferHistory::new();
+        for text in ["a", "b", "c", "d"] {
+            history.add_edit(Edit {
+                kind: EditKind::Insert,
+                range: buffer_range((0, 0), (0, 1)),
+                text,
+            });
+            history.commit_edits();
+        }
+        assert_eq!("d", history.undo_edits().next().unwrap().text);
+
+        history.limit_group_count(2);
+        assert_eq!("c", history.undo_edits().next().unwrap().text);
+        assert_eq!("b", history.undo_edits().next().unwrap().text);
+        assert_eq!(0, history.undo_edits().count());
+
+        assert_eq!("b", history.redo_edits().next().unwrap().text);
+        assert_eq!("c", history.redo_edits().next().unwrap().text);
+        assert_eq!("d", history.redo_edits().next().unwrap().text);
+        assert_eq!(0, history.redo_edits().count());
+    }
+
     #[test]
     fn compress_insert_insert_edits() {
         let mut history = BufferHistory::new();
@@ -1287,4 +1454,54 @@ mod tests {
             assert!(edits.next().is_none());
         }
     }
+
+    #[test]
+    fn serialize_deserialize() {
+        let range01to02 = buffer_range((0, 1), (0, 2));
+        let range00to03 = buffer_range((0, 0), (0, 3));
+        let mut history = BufferHistory::new();
+
+        history.add_edit(Edit {
+            kind: EditKind::Insert,
+            range: range00to03,
+            text: "abc",
+        });
+        history.commit_edits();
+        history.add_edit(Edit {
+            kind: EditKind::Delete,
+            range: range01to02,
+            text: "b",
+        });
+
+        let mut bytes = Vec::new();
+        history.serialize(&mut bytes);
+        let mut bytes = &bytes[..];
+        let mut history = match BufferHistory::deserialize(&mut bytes) {
+            Ok(history) => history,
+            Err(_) => panic!("could not deserialize history"),
+        };
+        assert!(bytes.is_empty());
+
+        assert!(history.redo_edits().next().is_none());
+        {
+            let mut edits = history.undo_edits();
+            let edit = edits.next().unwrap();
+            assert_eq!(EditKind::Insert, edit.kind);
+            assert_eq!("b", edit.text);
+            assert_eq!(range01to02, edit.range);
+            assert!(edits.next().is_none());
+        }
+        {
+            let mut edits = history.undo_edits();
+            let edit = edits.next().unwrap();
+            assert_eq!(EditKind::Delete, edit.kind);
+            assert_eq!("abc", edit.text);
+            assert_eq!(range00to03, edit.range);
+            assert!(edits.next().is_none());
+        }
+        assert!(history.undo_edits().next().is_none());
+
+        let mut bytes: &[u8] = &[1, 0, 0, 0];
+        assert!(BufferHistory::deserialize(&mut bytes).is_err());
+    }
 }
@@ -0,0 +1,323 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use crate::{
+    buffer::{push_bytes_lossless, Buffer, BufferCollection, BufferHandle, BufferLineEnding},
+    editor_utils::hash_bytes,
+};
+
+struct RecoveryEntry {
+    buffer_handle: BufferHandle,
+    changed: bool,
+    file_path: Option<PathBuf>,
+}
+
+// keeps the content of buffers with unsaved changes inside a per-session directory next to
+// the log file so that it can be recovered if the server dies before they're saved.
+// a recovery file starts with the buffer's absolute path on its first line followed by its content.
+#[derive(Default)]
+pub struct BufferRecovery {
+    entries: Vec<RecoveryEntry>,
+}
+impl BufferRecovery {
+    fn entry_mut(&mut self, buffer_handle: BufferHandle) -> &mut RecoveryEntry {
+        match self
+            .entries
+            .iter()
+            .position(|e| e.buffer_handle == buffer_handle)
+        {
+            Some(index) => &mut self.entries[index],
+            None => {
+                self.entries.push(RecoveryEntry {
+                    buffer_handle,
+                    changed: false,
+                    file_path: None,
+                });
+                let last_index = self.entries.len() - 1;
+                &mut self.entries[last_index]
+            }
+        }
+    }
+
+    pub(crate) fn on_buffer_changed(&mut self, buffer_handle: BufferHandle) {
+        self.entry_mut(buffer_handle).changed = true;
+    }
+
+    // returns true if there's a recovery file for this buffer that was left by a previous session
+    pub(crate) fn on_buffer_read(
+        &mut self,
+        buffer: &Buffer,
+        current_directory: &Path,
+        log_file_path: Option<&str>,
+    ) -> bool {
+        let entry = self.entry_mut(buffer.handle());
+        entry.changed = false;
+        if entry.file_path.is_some() {
+            return false;
+        }
+
+        match recovery_file_path(buffer, current_directory, log_file_path) {
+            Some((_, file_path)) => file_path.exists(),
+            None => false,
+        }
+    }
+
+    pub(crate) fn on_buffer_write(
+        &mut self,
+        buffer: &Buffer,
+        current_directory: &Path,
+        log_file_path: Option<&str>,
+    ) {
+        let entry = self.entry_mut(buffer.handle());
+        if let Some(file_path) = entry.file_path.take() {
+            let _ = fs::remove_file(file_path);
+        }
+        if let Some((_, file_path)) = recovery_file_path(buffer, current_directory, log_file_path) {
+            let _ = fs::remove_file(file_path);
+        }
+    }
+
+    pub(crate) fn on_buffer_close(&mut self, buffer_handle: BufferHandle) {
+        if let Some(index) = self
+            .entries
+            .iter()
+            .position(|e| e.buffer_handle == buffer_handle)
+        {
+            let entry = self.entries.swap_remove(index);
+            if let Some(file_path) = entry.file_path {
+                let _ = fs::remove_file(file_path);
+            }
+        }
+    }
+
+    pub(crate) fn on_idle(
+        &mut self,
+        buffers: &BufferCollection,
+        current_directory: &Path,
+        log_file_path: Option<&str>,
+    ) {
+        for entry in &mut self.entries {
+            let buffer = buffers.get(entry.buffer_handle);
+            if !buffer.needs_save() {
+                if let Some(file_path) = entry.file_path.take() {
+                    let _ = fs::remove_file(file_path);
+                }
+                continue;
+            }
+            if !entry.changed {
+                continue;
+            }
+
+            let (buffer_path, file_path) =
+                match recovery_file_path(buffer, current_directory, log_file_path) {
+                    Some(paths) => paths,
+                    None => continue,
+                };
+            if write_recovery_file(buffer, &buffer_path, &file_path).is_ok() {
+                entry.changed = false;
+                entry.file_path = Some(file_path);
+            }
+        }
+    }
+
+    pub fn read_recovered_text(
+        &self,
+        buffer: &Buffer,
+        current_directory: &Path,
+        log_file_path: Option<&str>,
+        text: &mut String,
+    ) -> bool {
+        let (buffer_path, file_path) =
+            match recovery_file_path(buffer, current_directory, log_file_path) {
+                Some(paths) => paths,
+                None => return false,
+            };
+        let content = match fs::read(file_path) {
+            Ok(content) => content,
+            Err(_) => return false,
+        };
+        let (path, content) = match content.iter().position(|&b| b == b'\n') {
+            Some(i) => (&content[..i], &content[i + 1..]),
+            None => return false,
+        };
+        if path != buffer_path.as_bytes() {
+            return false;
+        }
+        push_bytes_lossless(text, content);
+        true
+    }
+}
+impl Drop for BufferRecovery {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            return;
+        }
+        for entry in &self.entries {
+            if let Some(file_path) = &entry.file_path {
+                let _ = fs::remove_file(file_path);
+            }
+        }
+    }
+}
+
+fn recovery_file_path(
+    buffer: &Buffer,
+    current_directory: &Path,
+    log_file_path: Option<&str>,
+) -> Option<(String, PathBuf)> {
+    if !buffer.properties.saving_enabled
+        || !buffer.properties.file_backed_enabled
+        || buffer.path.as_os_str().is_empty()
+    {
+        return None;
+    }
+
+    let buffer_path = current_directory.join(&buffer.path);
+    let buffer_path = buffer_path.to_str()?;
+
+    let mut file_path = Path::new(log_file_path?).with_extension("recovery");
+    file_path.push(format!("{:x}", hash_bytes(buffer_path.as_bytes())));
+    Some((buffer_path.into(), file_path))
+}
+
+fn write_recovery_file(buffer: &Buffer, buffer_path: &str, file_path: &Path) -> io::Result<()> {
+    use io::Write;
+
+    if let Some(directory) = file_path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+
+    let mut writer = io::BufWriter::new(fs::File::create(file_path)?);
+    writer.write_all(buffer_path.as_bytes())?;
+    writer.write_all(b"\n")?;
+    buffer
+        .content()
+        .write(&mut writer, BufferLineEnding::Lf, false)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    use crate::{
+        buffer::BufferProperties, buffer_position::BufferPosition, config::SaveBackup,
+        events::EditorEventQueue, word_database::WordDatabase,
+    };
+
+    #[test]
+    fn write_detect_restore_and_delete_recovery_file() {
+        let directory = env::temp_dir().join(format!(
+            "pepper-test-{}-buffer-recovery",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let log_file_path = directory.join("session.txt");
+        let log_file_path = log_file_path.to_str();
+        let buffer_path = directory.join("file.txt");
+        let current_directory = Path::new("");
+
+        let mut word_database = WordDatabase::new();
+        let mut events = EditorEventQueue::default();
+        let mut buffers = BufferCollection::default();
+        let buffer = buffers.add_new();
+        let buffer_handle = buffer.handle();
+        buffer.properties = BufferProperties::text();
+        buffer.set_path(&buffer_path);
+
+        let mut recovery = BufferRecovery::default();
+        assert!(!recovery.on_buffer_read(buffer, current_directory, log_file_path));
+
+        buffer.insert_text(
+            &mut word_database,
+            BufferPosition::zero(),
+            "unsaved\ntext",
+            &mut events.writer().buffer_text_inserts_mut_guard(buffer_handle),
+        );
+        recovery.on_buffer_changed(buffer_handle);
+        recovery.on_idle(&buffers, current_directory, log_file_path);
+
+        let buffer = buffers.get(buffer_handle);
+        let (_, file_path) = recovery_file_path(buffer, current_directory, log_file_path).unwrap();
+        let file_content = fs::read_to_string(&file_path).unwrap();
+        let expected_file_content = format!("{}\nunsaved\ntext", buffer_path.to_str().unwrap());
+        assert_eq!(expected_file_content, file_content);
+
+        // a crashed session leaves its recovery files behind
+        std::mem::forget(recovery);
+
+        let mut recovery = BufferRecovery::default();
+        assert!(recovery.on_buffer_read(buffer, current_directory, log_file_path));
+        let mut text = String::new();
+        assert!(recovery.read_recovered_text(buffer, current_directory, log_file_path, &mut text));
+        assert_eq!("unsaved\ntext", text);
+
+        let buffer = buffers.get_mut(buffer_handle);
+        assert!(buffer
+            .write_to_file(None, true, SaveBackup::None, &mut events.writer())
+            .is_ok());
+        recovery.on_buffer_write(buffer, current_directory, log_file_path);
+        assert!(!file_path.exists());
+        assert!(!recovery.read_recovered_text(buffer, current_directory, log_file_path, &mut text));
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn delete_recovery_file_when_saved_or_closed() {
+        let directory = env::temp_dir().join(format!(
+            "pepper-test-{}-buffer-recovery-delete",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let log_file_path = directory.join("session.txt");
+        let log_file_path = log_file_path.to_str();
+        let current_directory = Path::new("");
+
+        let mut word_database = WordDatabase::new();
+        let mut events = EditorEventQueue::default();
+        let mut buffers = BufferCollection::default();
+        let buffer = buffers.add_new();
+        let buffer_handle = buffer.handle();
+        buffer.properties = BufferProperties::text();
+        buffer.set_path(&directory.join("file.txt"));
+
+        let mut recovery = BufferRecovery::default();
+        let mut change = |buffers: &mut BufferCollection, recovery: &mut BufferRecovery| {
+            buffers.get_mut(buffer_handle).insert_text(
+                &mut word_database,
+                BufferPosition::zero(),
+                "text",
+                &mut events.writer().buffer_text_inserts_mut_guard(buffer_handle),
+            );
+            recovery.on_buffer_changed(buffer_handle);
+            recovery.on_idle(buffers, current_directory, log_file_path);
+        };
+
+        change(&mut buffers, &mut recovery);
+        let buffer = buffers.get(buffer_handle);
+        let (_, file_path) = recovery_file_path(buffer, current_directory, log_file_path).unwrap();
+        assert!(file_path.exists());
+
+        let mut events = EditorEventQueue::default();
+        let buffer = buffers.get_mut(buffer_handle);
+        assert!(buffer
+            .write_to_file(None, true, SaveBackup::None, &mut events.writer())
+            .is_ok());
+        recovery.on_idle(&buffers, current_directory, log_file_path);
+        assert!(!file_path.exists());
+
+        change(&mut buffers, &mut recovery);
+        assert!(file_path.exists());
+        recovery.on_buffer_close(buffer_handle);
+        assert!(!file_path.exists());
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
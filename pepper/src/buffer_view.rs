@@ -7,6 +7,7 @@ use crate::{
     client::ClientHandle,
     cursor::{Cursor, CursorCollection},
     events::{BufferEditMutGuard, EditorEventTextInsert, EditorEventWriter},
+    syntax::SyntaxCollection,
     word_database::{WordDatabase, WordIter, WordKind},
 };
 
@@ -38,6 +39,7 @@ pub struct BufferView {
     pub buffer_handle: BufferHandle,
     pub cursors: CursorCollection,
     pub(crate) scroll: BufferPositionIndex,
+    pub(crate) horizontal_scroll: BufferPositionIndex,
 }
 
 impl BufferView {
@@ -436,6 +438,7 @@ impl BufferView {
     pub fn fix_indentation_in_cursor_ranges(
         &self,
         indentation_config: BufferIndentationConfig,
+        syntaxes: &SyntaxCollection,
         buffers: &mut BufferCollection,
         events: &mut EditorEventWriter,
     ) {
@@ -452,7 +455,12 @@ impl BufferView {
             previous_fix_line_index = to_line_index;
 
             for line_index in from_line_index..=to_line_index {
-                buffer.fix_line_indentation(indentation_config, line_index as _, &mut events);
+                buffer.fix_line_indentation(
+                    indentation_config,
+                    syntaxes,
+                    line_index as _,
+                    &mut events,
+                );
             }
         }
     }
@@ -506,9 +514,11 @@ impl BufferView {
         word_database: &mut WordDatabase,
         events: &mut EditorEventWriter,
     ) {
-        let edits = buffers
-            .get_mut(self.buffer_handle)
-            .undo(word_database, events);
+        let buffer = buffers.get_mut(self.buffer_handle);
+        if buffer.properties.read_only {
+            return;
+        }
+        let edits = buffer.undo(word_database, events);
 
         let mut fix_cursors = events.fix_cursors_mut_guard(self.handle);
 
@@ -536,9 +546,11 @@ impl BufferView {
         word_database: &mut WordDatabase,
         events: &mut EditorEventWriter,
     ) {
-        let edits = buffers
-            .get_mut(self.buffer_handle)
-            .redo(word_database, events);
+        let buffer = buffers.get_mut(self.buffer_handle);
+        if buffer.properties.read_only {
+            return;
+        }
+        let edits = buffer.redo(word_database, events);
 
         let mut fix_cursors = events.fix_cursors_mut_guard(self.handle);
 
@@ -600,6 +612,7 @@ impl BufferViewCollection {
             buffer_handle,
             cursors: CursorCollection::new(),
             scroll: 0,
+            horizontal_scroll: 0,
         });
         handle
     }
@@ -4,10 +4,12 @@ use crate::{
     buffer::{BufferCollection, BufferHandle, BufferProperties, CharDisplayDistances},
     buffer_position::BufferPositionIndex,
     buffer_view::{BufferView, BufferViewCollection, BufferViewHandle},
+    config::{Config, WrapMode},
     editor::Editor,
     editor_utils::ResidualStrBytes,
     navigation_history::{NavigationHistory, NavigationMovement},
     serialization::{DeserializeError, Deserializer, Serialize, Serializer},
+    ui::LineLayout,
 };
 
 #[derive(Clone, Copy, Eq, PartialEq)]
@@ -112,12 +114,11 @@ impl Client {
                 ViewAnchor::Bottom => height.saturating_sub(1),
             };
 
+            let config = editor.buffer_view_config(Some(buffer_view_handle));
             let buffer_view = editor.buffer_views.get_mut(buffer_view_handle);
-            let main_cursor_padding_top = self.find_main_cursor_padding_top(
-                buffer_view,
-                &editor.buffers,
-                editor.config.tab_size,
-            );
+            let layout = self.line_layout(buffer_view, &editor.buffers, &config);
+            let main_cursor_padding_top =
+                find_main_cursor_padding_top(buffer_view, &editor.buffers, &layout);
             buffer_view.scroll = main_cursor_padding_top.saturating_sub(height_offset) as _;
         }
     }
@@ -126,11 +127,11 @@ impl Client {
         &self,
         buffer_views: &mut BufferViewCollection,
         buffers: &BufferCollection,
-        tab_size: u8,
+        config: &Config,
         margin_bottom: usize,
-    ) -> BufferPositionIndex {
+    ) -> (BufferPositionIndex, BufferPositionIndex) {
         if !self.has_ui() {
-            return 0;
+            return (0, 0);
         }
 
         let height = self.viewport_size.1.saturating_sub(1) as usize;
@@ -140,8 +141,9 @@ impl Client {
         match self.buffer_view_handle {
             Some(buffer_view_handle) => {
                 let buffer_view = buffer_views.get_mut(buffer_view_handle);
+                let layout = self.line_layout(buffer_view, buffers, config);
                 let main_cursor_padding_top =
-                    self.find_main_cursor_padding_top(buffer_view, buffers, tab_size);
+                    find_main_cursor_padding_top(buffer_view, buffers, &layout);
 
                 let mut scroll = buffer_view.scroll as usize;
                 if main_cursor_padding_top < scroll.saturating_sub(half_height) {
@@ -155,9 +157,32 @@ impl Client {
                 }
                 let scroll = scroll as _;
                 buffer_view.scroll = scroll;
-                scroll
+
+                let mut horizontal_scroll = 0;
+                if layout.wrap == WrapMode::None {
+                    let width = layout.text_width;
+                    let half_width = width / 2;
+                    let (column, column_len) =
+                        find_main_cursor_padding_left(buffer_view, buffers, &layout);
+                    let column_end = column + column_len;
+
+                    horizontal_scroll = buffer_view.horizontal_scroll as usize;
+                    if column < horizontal_scroll.saturating_sub(half_width) {
+                        horizontal_scroll = column.saturating_sub(half_width);
+                    } else if column < horizontal_scroll {
+                        horizontal_scroll = column;
+                    } else if column_end > horizontal_scroll + width + half_width {
+                        horizontal_scroll = column_end.saturating_sub(half_width);
+                    } else if column_end > horizontal_scroll + width {
+                        horizontal_scroll = column_end - width;
+                    }
+                }
+                let horizontal_scroll = horizontal_scroll as _;
+                buffer_view.horizontal_scroll = horizontal_scroll;
+
+                (scroll, horizontal_scroll)
             }
-            None => 0,
+            None => (0, 0),
         }
     }
 
@@ -218,30 +243,74 @@ impl Client {
         }
     }
 
-    fn find_main_cursor_padding_top(
+    fn line_layout(
         &self,
         buffer_view: &BufferView,
         buffers: &BufferCollection,
-        tab_size: u8,
-    ) -> usize {
-        let width = self.viewport_size.0 as usize;
+        config: &Config,
+    ) -> LineLayout {
+        let line_count = buffers
+            .get(buffer_view.buffer_handle)
+            .content()
+            .lines()
+            .len();
+        LineLayout::new(config, line_count, self.viewport_size.0 as _)
+    }
+}
 
-        let buffer = buffers.get(buffer_view.buffer_handle).content();
-        let position = buffer_view.cursors.main_cursor().position;
+fn find_main_cursor_padding_top(
+    buffer_view: &BufferView,
+    buffers: &BufferCollection,
+    layout: &LineLayout,
+) -> usize {
+    let buffer = buffers.get(buffer_view.buffer_handle).content();
+    let position = buffer_view.cursors.main_cursor().position;
+    let line_index = position.line_index as usize;
+
+    let mut height = 0;
+    let lines = buffer.lines();
+    for (line, display_len) in lines
+        .range(..line_index)
+        .zip(lines.display_lens(..line_index))
+    {
+        height += layout.line_height(line.as_str(), *display_len);
+    }
 
-        let mut height = position.line_index as usize;
-        for display_len in &buffer.line_display_lens()[..position.line_index as usize] {
-            height += display_len.total_len(tab_size) / width;
-        }
+    let cursor_line = lines[line_index].as_str();
+    let cursor_display_len = lines.display_len(line_index);
+    if layout.line_height(cursor_line, cursor_display_len) > 1 {
+        let column_byte_index = position.column_byte_index as usize;
+        let row_count = layout
+            .rows(cursor_line)
+            .take_while(|r| r.start <= column_byte_index)
+            .count();
+        height += row_count.saturating_sub(1);
+    }
 
-        let cursor_line = buffer.lines()[position.line_index as usize].as_str();
-        let cursor_line = &cursor_line[..position.column_byte_index as usize];
-        if let Some(d) = CharDisplayDistances::new(cursor_line, tab_size).last() {
-            height += d.distance as usize / width;
-        }
+    height
+}
 
-        height
-    }
+// returns the main cursor display column and how many columns it takes
+fn find_main_cursor_padding_left(
+    buffer_view: &BufferView,
+    buffers: &BufferCollection,
+    layout: &LineLayout,
+) -> (usize, usize) {
+    let buffer = buffers.get(buffer_view.buffer_handle).content();
+    let position = buffer_view.cursors.main_cursor().position;
+
+    let cursor_line = buffer.lines()[position.line_index as usize].as_str();
+    let (before, after) = cursor_line.split_at(position.column_byte_index as usize);
+    let column = match CharDisplayDistances::new(before, layout.tab_size).last() {
+        Some(d) => d.distance as usize,
+        None => 0,
+    };
+    let column_len = match CharDisplayDistances::new(after, layout.tab_size).next() {
+        Some(d) => d.distance as usize,
+        None => 1,
+    };
+
+    (column, column_len)
 }
 
 #[derive(Default)]
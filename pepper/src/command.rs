@@ -34,6 +34,7 @@ pub enum CommandError {
     BufferWriteError(BufferWriteError),
     InvalidBufferPath,
     NoSuchBufferProperty,
+    BufferReadOnly,
     NoSuchBreakpointSubcommand,
     ConfigError(ParseConfigError),
     NoSuchColor,
@@ -43,11 +44,13 @@ pub enum CommandError {
     KeyParseError(KeyParseAllError),
     InvalidRegisterKey,
     InvalidTokenKind,
+    NoSuchSyntax,
     PatternError(PatternError),
     InvalidEnvironmentVariable,
     InvalidProcessCommand,
     InvalidIfOp,
     InvalidGlob(InvalidGlobError),
+    InvalidIndentationStyle,
     OtherStatic(&'static str),
     OtherOwned(String),
 }
@@ -68,6 +71,7 @@ impl fmt::Display for CommandError {
             Self::BufferWriteError(error) => write!(f, "buffer write error: {}", error),
             Self::InvalidBufferPath => f.write_str("invalid buffer path"),
             Self::NoSuchBufferProperty => f.write_str("no such buffer property"),
+            Self::BufferReadOnly => f.write_str("buffer is read-only"),
             Self::NoSuchBreakpointSubcommand => f.write_str("no such breakpoint subcommand"),
             Self::ConfigError(error) => write!(f, "config error: {}", error),
             Self::NoSuchColor => f.write_str("no such color"),
@@ -77,11 +81,13 @@ impl fmt::Display for CommandError {
             Self::KeyParseError(error) => write!(f, "key parse error: {}", error),
             Self::InvalidRegisterKey => f.write_str("invalid register key"),
             Self::InvalidTokenKind => f.write_str("invalid token kind"),
+            Self::NoSuchSyntax => f.write_str("no such syntax"),
             Self::PatternError(error) => write!(f, "pattern error: {}", error),
             Self::InvalidEnvironmentVariable => f.write_str("invalid environment variable"),
             Self::InvalidProcessCommand => f.write_str("invalid process command"),
             Self::InvalidIfOp => f.write_str("invalid if comparison operator"),
             Self::InvalidGlob(error) => write!(f, "glob error: {}", error),
+            Self::InvalidIndentationStyle => f.write_str("invalid indentation style"),
             Self::OtherStatic(error) => f.write_str(error),
             Self::OtherOwned(error) => f.write_str(&error),
         }
@@ -209,6 +215,18 @@ impl<'a> CommandIO<'a> {
             Err(CommandError::UnsavedChanges)
         }
     }
+
+    pub fn assert_can_edit_buffer(
+        &self,
+        ctx: &EditorContext,
+        handle: BufferHandle,
+    ) -> Result<(), CommandError> {
+        if ctx.editor.buffers.get(handle).properties.read_only {
+            Err(CommandError::BufferReadOnly)
+        } else {
+            Ok(())
+        }
+    }
 }
 
 pub struct CommandIter<'a>(pub &'a str);
@@ -1,11 +1,15 @@
-use std::{env, path::Path, process::Stdio};
+use std::{env, fs::File, io, path::Path, process::Stdio};
 
 use crate::{
-    buffer::{BufferProperties, BufferReadError, BufferWriteError},
+    buffer::{
+        BufferContent, BufferIndentationConfig, BufferLineEnding, BufferProperties,
+        BufferReadError, BufferWriteError,
+    },
     buffer_position::{BufferPosition, BufferPositionIndex, BufferRange},
     command::{CommandError, CommandIO, CommandManager, CompletionSource},
     config::{ParseConfigError, CONFIG_NAMES},
     cursor::Cursor,
+    diff,
     editor::{EditorContext, EditorFlow},
     editor_utils::{
         parse_path_and_ranges, parse_process_command, validate_process_command, LogKind,
@@ -14,9 +18,11 @@ use crate::{
     events::BufferEditMutGuard,
     help,
     mode::{picker, readline, ModeKind},
+    pattern::{MatchResult, PatternCaptures},
     platform::{PlatformRequest, ProcessTag},
-    syntax::TokenKind,
+    syntax::{HighlightResult, TokenKind},
     theme::{Color, THEME_COLOR_NAMES},
+    vcs::BufferVcsDiff,
     word_database::{WordIndicesIter, WordKind},
 };
 
@@ -40,6 +46,10 @@ pub fn register_commands(commands: &mut CommandManager) {
             saving_enabled: false,
             file_backed_enabled: true,
             word_database_enabled: false,
+            line_ending: BufferLineEnding::Lf,
+            byte_order_mark_enabled: false,
+            persistent_history_enabled: false,
+            read_only: true,
         };
 
         let result = ctx.editor.buffer_view_handle_from_path(
@@ -103,7 +113,12 @@ pub fn register_commands(commands: &mut CommandManager) {
             Some(buffer_handle) => {
                 let buffer = ctx.editor.buffers.get_mut(buffer_handle);
                 buffer
-                    .read_from_file(&mut ctx.editor.word_database, ctx.editor.events.writer())
+                    .read_from_file(
+                        &mut ctx.editor.word_database,
+                        &ctx.editor.current_directory,
+                        ctx.editor.config.large_file_size,
+                        ctx.editor.events.writer(),
+                    )
                     .map_err(CommandError::BufferReadError)?;
                 ctx.editor
                     .buffer_views
@@ -162,6 +177,14 @@ pub fn register_commands(commands: &mut CommandManager) {
                 "file-backed-disabled" => properties.file_backed_enabled = false,
                 "word-database-enabled" => properties.word_database_enabled = true,
                 "word-database-disabled" => properties.word_database_enabled = false,
+                "line-ending-lf" => properties.line_ending = BufferLineEnding::Lf,
+                "line-ending-crlf" => properties.line_ending = BufferLineEnding::CrLf,
+                "byte-order-mark-enabled" => properties.byte_order_mark_enabled = true,
+                "byte-order-mark-disabled" => properties.byte_order_mark_enabled = false,
+                "persistent-history-enabled" => properties.persistent_history_enabled = true,
+                "persistent-history-disabled" => properties.persistent_history_enabled = false,
+                "read-only-enabled" => properties.read_only = true,
+                "read-only-disabled" => properties.read_only = false,
                 _ => return Err(CommandError::NoSuchBufferProperty),
             }
             path = arg;
@@ -200,15 +223,49 @@ pub fn register_commands(commands: &mut CommandManager) {
         Ok(())
     });
 
+    static BUFFER_FORMAT_COMPLETIONS: &[CompletionSource] = &[CompletionSource::Custom(&[
+        "line-ending-lf",
+        "line-ending-crlf",
+        "byte-order-mark-enabled",
+        "byte-order-mark-disabled",
+    ])];
+    r("set-buffer-format", BUFFER_FORMAT_COMPLETIONS, |ctx, io| {
+        let buffer_handle = io.current_buffer_handle(ctx)?;
+        io.assert_can_edit_buffer(ctx, buffer_handle)?;
+        let buffer = ctx.editor.buffers.get_mut(buffer_handle);
+
+        let mut line_ending = buffer.properties.line_ending;
+        let mut byte_order_mark_enabled = buffer.properties.byte_order_mark_enabled;
+
+        let mut format = io.args.next()?;
+        loop {
+            match format {
+                "line-ending-lf" => line_ending = BufferLineEnding::Lf,
+                "line-ending-crlf" => line_ending = BufferLineEnding::CrLf,
+                "byte-order-mark-enabled" => byte_order_mark_enabled = true,
+                "byte-order-mark-disabled" => byte_order_mark_enabled = false,
+                _ => return Err(CommandError::NoSuchBufferProperty),
+            }
+            match io.args.try_next() {
+                Some(arg) => format = arg,
+                None => break,
+            }
+        }
+
+        buffer.set_file_format(line_ending, byte_order_mark_enabled);
+        Ok(())
+    });
+
     r("save", &[CompletionSource::Files], |ctx, io| {
         let path = io.args.try_next().map(|p| Path::new(p));
         io.args.assert_empty()?;
 
         let buffer_handle = io.current_buffer_handle(ctx)?;
+        let save_backup = ctx.editor.buffer_config(buffer_handle).save_backup;
         let buffer = ctx.editor.buffers.get_mut(buffer_handle);
 
         buffer
-            .write_to_file(path, ctx.editor.events.writer())
+            .write_to_file(path, io.bang, save_backup, ctx.editor.events.writer())
             .map_err(CommandError::BufferWriteError)?;
 
         ctx.editor
@@ -224,9 +281,12 @@ pub fn register_commands(commands: &mut CommandManager) {
         let mut count = 0;
         let mut maybe_error = None;
         for buffer in ctx.editor.buffers.iter_mut() {
-            match buffer.write_to_file(None, ctx.editor.events.writer()) {
+            let save_backup = buffer
+                .resolve_config(&ctx.editor.config, &ctx.editor.glob_config_overrides)
+                .save_backup;
+            match buffer.write_to_file(None, io.bang, save_backup, ctx.editor.events.writer()) {
                 Ok(()) => count += 1,
-                Err(BufferWriteError::SavingDisabled) => (),
+                Err(BufferWriteError::SavingDisabled | BufferWriteError::ReadOnly) => (),
                 Err(error) => maybe_error = Some(CommandError::BufferWriteError(error)),
             }
         }
@@ -250,7 +310,12 @@ pub fn register_commands(commands: &mut CommandManager) {
         let buffer = ctx.editor.buffers.get_mut(buffer_handle);
 
         buffer
-            .read_from_file(&mut ctx.editor.word_database, ctx.editor.events.writer())
+            .read_from_file(
+                &mut ctx.editor.word_database,
+                &ctx.editor.current_directory,
+                ctx.editor.config.large_file_size,
+                ctx.editor.events.writer(),
+            )
             .map_err(CommandError::BufferReadError)?;
 
         ctx.editor
@@ -268,7 +333,12 @@ pub fn register_commands(commands: &mut CommandManager) {
         let mut all_files_found = true;
         let mut maybe_error = None;
         for buffer in ctx.editor.buffers.iter_mut() {
-            match buffer.read_from_file(&mut ctx.editor.word_database, ctx.editor.events.writer()) {
+            match buffer.read_from_file(
+                &mut ctx.editor.word_database,
+                &ctx.editor.current_directory,
+                ctx.editor.config.large_file_size,
+                ctx.editor.events.writer(),
+            ) {
                 Ok(()) => count += 1,
                 Err(BufferReadError::FileNotFound) => all_files_found = true,
                 Err(error) => maybe_error = Some(CommandError::BufferReadError(error)),
@@ -289,6 +359,284 @@ pub fn register_commands(commands: &mut CommandManager) {
         Ok(())
     });
 
+    r("recover", &[], |ctx, io| {
+        io.args.assert_empty()?;
+
+        let buffer_handle = io.current_buffer_handle(ctx)?;
+        io.assert_can_edit_buffer(ctx, buffer_handle)?;
+        let buffer = ctx.editor.buffers.get_mut(buffer_handle);
+
+        let mut text = ctx.editor.string_pool.acquire();
+        if !ctx.editor.buffer_recovery.read_recovered_text(
+            buffer,
+            &ctx.editor.current_directory,
+            ctx.editor.logger.log_file_path(),
+            &mut text,
+        ) {
+            ctx.editor.string_pool.release(text);
+            return Err(CommandError::OtherStatic(
+                "buffer has no unsaved changes to recover",
+            ));
+        }
+
+        let range = BufferRange::between(BufferPosition::zero(), buffer.content().end());
+        buffer.delete_range(
+            &mut ctx.editor.word_database,
+            range,
+            &mut ctx
+                .editor
+                .events
+                .writer()
+                .buffer_range_deletes_mut_guard(buffer_handle),
+        );
+        buffer.insert_text(
+            &mut ctx.editor.word_database,
+            BufferPosition::zero(),
+            &text,
+            &mut ctx
+                .editor
+                .events
+                .writer()
+                .buffer_text_inserts_mut_guard(buffer_handle),
+        );
+        buffer.commit_edits();
+
+        ctx.editor.string_pool.release(text);
+
+        ctx.editor.logger.write(LogKind::Status).fmt(format_args!(
+            "recovered unsaved changes of {:?}",
+            &buffer.path
+        ));
+        Ok(())
+    });
+
+    r("diff-buffer", &[], |ctx, io| {
+        io.args.assert_empty()?;
+
+        let client_handle = io.client_handle()?;
+        let buffer_handle = io.current_buffer_handle(ctx)?;
+        let buffer = ctx.editor.buffers.get(buffer_handle);
+        if !buffer.properties.file_backed_enabled {
+            return Err(CommandError::OtherStatic("buffer is not file backed"));
+        }
+        let buffer_path = match buffer.path.to_str() {
+            Some(path) if !path.is_empty() => path,
+            _ => return Err(CommandError::InvalidBufferPath),
+        };
+
+        // a file that does not exist yet is diffed as if it were empty
+        let mut file_content = BufferContent::new();
+        match File::open(&buffer.path) {
+            Ok(file) => {
+                if let Err(error) = file_content.read(&mut io::BufReader::new(file)) {
+                    return Err(CommandError::BufferReadError(error.into()));
+                }
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+            Err(error) => return Err(CommandError::BufferReadError(error.into())),
+        }
+
+        let file_lines: Vec<_> = file_content.lines().iter().map(|l| l.as_str()).collect();
+        let buffer_lines: Vec<_> = buffer
+            .content()
+            .lines()
+            .iter()
+            .map(|l| l.as_str())
+            .collect();
+        let mut hunks = Vec::new();
+        diff::diff(&file_lines, &buffer_lines, &mut hunks);
+        if hunks.is_empty() {
+            ctx.editor
+                .logger
+                .write(LogKind::Status)
+                .str("buffer has no changes");
+            return Ok(());
+        }
+
+        let mut content = ctx.editor.string_pool.acquire();
+        let _ = diff::write_unified_diff(
+            &mut content,
+            buffer_path,
+            buffer_path,
+            &file_content,
+            buffer.content(),
+            &hunks,
+        );
+        if content.ends_with('\n') {
+            content.pop();
+        }
+
+        let mut diff_path = ctx.editor.string_pool.acquire_with(buffer_path);
+        diff_path.push_str(".diff");
+        let buffer_view_handle = ctx.editor.buffer_view_handle_from_path(
+            client_handle,
+            Path::new(&diff_path),
+            BufferProperties::scratch(),
+            true,
+        );
+        ctx.editor.string_pool.release(diff_path);
+        let buffer_view_handle = match buffer_view_handle {
+            Ok(handle) => handle,
+            Err(error) => {
+                ctx.editor.string_pool.release(content);
+                return Err(CommandError::BufferReadError(error));
+            }
+        };
+
+        let buffer_handle = ctx
+            .editor
+            .buffer_views
+            .get(buffer_view_handle)
+            .buffer_handle;
+        let buffer = ctx.editor.buffers.get_mut(buffer_handle);
+        buffer.set_read_only_content(
+            &mut ctx.editor.word_database,
+            &content,
+            ctx.editor.events.writer(),
+        );
+
+        ctx.editor.string_pool.release(content);
+
+        let client = ctx.clients.get_mut(client_handle);
+        client.set_buffer_view_handle(Some(buffer_view_handle), &ctx.editor.buffer_views);
+        Ok(())
+    });
+
+    r("next-hunk", &[], |ctx, io| {
+        io.args.assert_empty()?;
+
+        let buffer_view_handle = io.current_buffer_view_handle(ctx)?;
+        let buffer_view = ctx.editor.buffer_views.get_mut(buffer_view_handle);
+        let buffer = ctx.editor.buffers.get_mut(buffer_view.buffer_handle);
+        buffer.update_vcs_diff();
+
+        let line_index = buffer_view.cursors.main_cursor().position.line_index as usize;
+        let hunks = buffer.vcs_diff().hunks();
+        let lines = hunks
+            .iter()
+            .map(BufferVcsDiff::hunk_marker_lines)
+            .find(|l| l.start > line_index)
+            .or_else(|| hunks.first().map(BufferVcsDiff::hunk_marker_lines));
+        let lines = match lines {
+            Some(lines) => lines,
+            None => return Err(CommandError::OtherStatic("buffer has no vcs changes")),
+        };
+
+        let position = BufferPosition::line_col(lines.start as _, 0);
+        let mut cursors = buffer_view.cursors.mut_guard();
+        cursors.clear();
+        cursors.add(Cursor {
+            anchor: position,
+            position,
+        });
+        Ok(())
+    });
+
+    r("previous-hunk", &[], |ctx, io| {
+        io.args.assert_empty()?;
+
+        let buffer_view_handle = io.current_buffer_view_handle(ctx)?;
+        let buffer_view = ctx.editor.buffer_views.get_mut(buffer_view_handle);
+        let buffer = ctx.editor.buffers.get_mut(buffer_view.buffer_handle);
+        buffer.update_vcs_diff();
+
+        let line_index = buffer_view.cursors.main_cursor().position.line_index as usize;
+        let hunks = buffer.vcs_diff().hunks();
+        let lines = hunks
+            .iter()
+            .rev()
+            .map(BufferVcsDiff::hunk_marker_lines)
+            .find(|l| l.start < line_index)
+            .or_else(|| hunks.last().map(BufferVcsDiff::hunk_marker_lines));
+        let lines = match lines {
+            Some(lines) => lines,
+            None => return Err(CommandError::OtherStatic("buffer has no vcs changes")),
+        };
+
+        let position = BufferPosition::line_col(lines.start as _, 0);
+        let mut cursors = buffer_view.cursors.mut_guard();
+        cursors.clear();
+        cursors.add(Cursor {
+            anchor: position,
+            position,
+        });
+        Ok(())
+    });
+
+    r("revert-hunk", &[], |ctx, io| {
+        io.args.assert_empty()?;
+
+        let buffer_view_handle = io.current_buffer_view_handle(ctx)?;
+        let buffer_view = ctx.editor.buffer_views.get(buffer_view_handle);
+        let buffer_handle = buffer_view.buffer_handle;
+        io.assert_can_edit_buffer(ctx, buffer_handle)?;
+
+        let line_index = buffer_view.cursors.main_cursor().position.line_index as usize;
+        let buffer = ctx.editor.buffers.get_mut(buffer_handle);
+        buffer.update_vcs_diff();
+        let hunk = match buffer.vcs_diff().hunk_at(line_index) {
+            Some(hunk) => hunk.clone(),
+            None => return Err(CommandError::OtherStatic("no vcs change at cursor")),
+        };
+
+        // the hunk's lines are replaced by their `HEAD` version
+        let content = buffer.content();
+        let base_lines = buffer.vcs_diff().base().lines().range(hunk.old_range.clone());
+        let mut text = ctx.editor.string_pool.acquire();
+        let range = if hunk.new_range.end < content.lines().len() {
+            for line in base_lines {
+                text.push_str(line.as_str());
+                text.push('\n');
+            }
+            BufferRange::between(
+                BufferPosition::line_col(hunk.new_range.start as _, 0),
+                BufferPosition::line_col(hunk.new_range.end as _, 0),
+            )
+        } else if hunk.new_range.start > 0 {
+            for line in base_lines {
+                text.push('\n');
+                text.push_str(line.as_str());
+            }
+            let line_index = hunk.new_range.start - 1;
+            let line_len = content.lines()[line_index].as_str().len();
+            BufferRange::between(
+                BufferPosition::line_col(line_index as _, line_len as _),
+                content.end(),
+            )
+        } else {
+            for line in base_lines {
+                text.push_str(line.as_str());
+                text.push('\n');
+            }
+            text.pop();
+            BufferRange::between(BufferPosition::zero(), content.end())
+        };
+
+        buffer.delete_range(
+            &mut ctx.editor.word_database,
+            range,
+            &mut ctx
+                .editor
+                .events
+                .writer()
+                .buffer_range_deletes_mut_guard(buffer_handle),
+        );
+        buffer.insert_text(
+            &mut ctx.editor.word_database,
+            range.from,
+            &text,
+            &mut ctx
+                .editor
+                .events
+                .writer()
+                .buffer_text_inserts_mut_guard(buffer_handle),
+        );
+        buffer.commit_edits();
+
+        ctx.editor.string_pool.release(text);
+        Ok(())
+    });
+
     r("close", &[], |ctx, io| {
         io.args.assert_empty()?;
 
@@ -349,29 +697,102 @@ pub fn register_commands(commands: &mut CommandManager) {
         }
     });
 
-    static COLOR_COMPLETIONS: &[CompletionSource] = &[CompletionSource::Custom(THEME_COLOR_NAMES)];
-    r("color", COLOR_COMPLETIONS, |ctx, io| {
+    r("config-buffer", CONFIG_COMPLETIONS, |ctx, io| {
         let key = io.args.next()?;
         let value = io.args.try_next();
         io.args.assert_empty()?;
 
-        let color = ctx
+        let buffer_handle = io.current_buffer_handle(ctx)?;
+        match value {
+            Some(value) => {
+                let buffer = ctx.editor.buffers.get_mut(buffer_handle);
+                match buffer.config_overrides.parse_config(key, value) {
+                    Ok(()) => Ok(()),
+                    Err(error) => Err(CommandError::ConfigError(error)),
+                }
+            }
+            None => {
+                let config = ctx.editor.buffer_config(buffer_handle);
+                match config.display_config(key) {
+                    Some(display) => {
+                        ctx.editor
+                            .logger
+                            .write(LogKind::Status)
+                            .fmt(format_args!("{}", display));
+                        Ok(())
+                    }
+                    None => Err(CommandError::ConfigError(ParseConfigError::NoSuchConfig)),
+                }
+            }
+        }
+    });
+
+    static CONFIG_GLOB_COMPLETIONS: &[CompletionSource] = &[
+        CompletionSource::Custom(&[]),
+        CompletionSource::Custom(CONFIG_NAMES),
+    ];
+    r("config-glob", CONFIG_GLOB_COMPLETIONS, |ctx, io| {
+        let glob = io.args.next()?;
+        let key = io.args.next()?;
+        let value = io.args.next()?;
+        io.args.assert_empty()?;
+
+        let overrides = ctx
             .editor
-            .theme
-            .color_from_name(key)
-            .ok_or(CommandError::NoSuchColor)?;
+            .glob_config_overrides
+            .get_or_add(glob)
+            .map_err(CommandError::InvalidGlob)?;
+        match overrides.parse_config(key, value) {
+            Ok(()) => Ok(()),
+            Err(error) => Err(CommandError::ConfigError(error)),
+        }
+    });
 
-        match value {
+    static COLOR_COMPLETIONS: &[CompletionSource] = &[CompletionSource::Custom(THEME_COLOR_NAMES)];
+    r("color", COLOR_COMPLETIONS, |ctx, io| {
+        let key = io.args.next()?;
+        let value = io.args.try_next();
+        io.args.assert_empty()?;
+
+        let value = match value {
             Some(value) => {
                 let encoded =
                     u32::from_str_radix(value, 16).map_err(|_| CommandError::InvalidColorValue)?;
-                *color = Color::from_u32(encoded);
+                Some(Color::from_u32(encoded))
             }
-            None => ctx
-                .editor
+            None => None,
+        };
+
+        let theme = &mut ctx.editor.theme;
+        let color = match key.strip_prefix("token_") {
+            Some(name) if !THEME_COLOR_NAMES.contains(&key) => {
+                let syntaxes = &mut ctx.editor.syntaxes;
+                let kind = match value {
+                    Some(_) => syntaxes.add_token_kind(name),
+                    None => syntaxes.find_token_kind(name),
+                };
+                let kind = kind.ok_or(CommandError::NoSuchColor)?;
+                if let Some(value) = value {
+                    theme.set_custom_token_color(kind, value);
+                }
+                theme.token_color(kind)
+            }
+            _ => {
+                let color = theme
+                    .color_from_name(key)
+                    .ok_or(CommandError::NoSuchColor)?;
+                if let Some(value) = value {
+                    *color = value;
+                }
+                *color
+            }
+        };
+
+        if value.is_none() {
+            ctx.editor
                 .logger
                 .write(LogKind::Status)
-                .fmt(format_args!("0x{:0<6x}", color.into_u32())),
+                .fmt(format_args!("0x{:0<6x}", color.into_u32()));
         }
 
         Ok(())
@@ -402,10 +823,39 @@ pub fn register_commands(commands: &mut CommandManager) {
     });
 
     static SYNTAX_COMPLETIONS: &[CompletionSource] = &[CompletionSource::Custom(&[
-        "keywords", "types", "symbols", "literals", "strings", "comments", "texts",
+        "kind",
+        "name",
+        "first-line",
+        "indent",
+        "dedent",
+        "region",
+        "keywords",
+        "types",
+        "symbols",
+        "literals",
+        "strings",
+        "comments",
+        "texts",
     ])];
     r("syntax", SYNTAX_COMPLETIONS, |ctx, io| {
         let arg = io.args.next()?;
+        if arg == "region" {
+            let syntax_name = io.args.next()?;
+            let start = io.args.next()?;
+            let end = io.args.next()?;
+            io.args.assert_empty()?;
+
+            return match ctx
+                .editor
+                .syntaxes
+                .get_last()
+                .add_region(syntax_name, start, end)
+            {
+                Ok(()) => Ok(()),
+                Err(error) => Err(CommandError::PatternError(error)),
+            };
+        }
+
         let pattern = io.args.try_next();
         io.args.assert_empty()?;
 
@@ -418,6 +868,44 @@ pub fn register_commands(commands: &mut CommandManager) {
         };
 
         let token_kind = match arg {
+            "kind" => {
+                let theme_color_name = ["token_", pattern].concat();
+                // names already taken by other `syntax` arguments or builtin colors
+                if matches!(
+                    pattern,
+                    "kind" | "name" | "first-line" | "indent" | "dedent" | "region"
+                ) || pattern.parse::<TokenKind>().is_ok()
+                    || THEME_COLOR_NAMES.contains(&theme_color_name.as_str())
+                {
+                    return Err(CommandError::InvalidTokenKind);
+                }
+                return match ctx.editor.syntaxes.add_token_kind(pattern) {
+                    Some(_) => Ok(()),
+                    None => Err(CommandError::InvalidTokenKind),
+                };
+            }
+            "name" => {
+                ctx.editor.syntaxes.get_last().set_name(pattern);
+                return Ok(());
+            }
+            "first-line" => {
+                return match ctx.editor.syntaxes.get_last().set_first_line(pattern) {
+                    Ok(()) => Ok(()),
+                    Err(error) => Err(CommandError::PatternError(error)),
+                };
+            }
+            "indent" => {
+                return match ctx.editor.syntaxes.get_last().set_indent(pattern) {
+                    Ok(()) => Ok(()),
+                    Err(error) => Err(CommandError::PatternError(error)),
+                };
+            }
+            "dedent" => {
+                return match ctx.editor.syntaxes.get_last().set_dedent(pattern) {
+                    Ok(()) => Ok(()),
+                    Err(error) => Err(CommandError::PatternError(error)),
+                };
+            }
             "keywords" => TokenKind::Keyword,
             "types" => TokenKind::Type,
             "symbols" => TokenKind::Symbol,
@@ -425,7 +913,11 @@ pub fn register_commands(commands: &mut CommandManager) {
             "strings" => TokenKind::String,
             "comments" => TokenKind::Comment,
             "texts" => TokenKind::Text,
-            _ => return Err(CommandError::InvalidTokenKind),
+            _ => ctx
+                .editor
+                .syntaxes
+                .find_token_kind(arg)
+                .ok_or(CommandError::InvalidTokenKind)?,
         };
 
         match ctx
@@ -439,6 +931,148 @@ pub fn register_commands(commands: &mut CommandManager) {
         }
     });
 
+    r("set-syntax", &[], |ctx, io| {
+        let name = io.args.try_next();
+        io.args.assert_empty()?;
+
+        let buffer_view_handle = io.current_buffer_view_handle(ctx)?;
+        let buffer_handle = ctx
+            .editor
+            .buffer_views
+            .get(buffer_view_handle)
+            .buffer_handle;
+
+        let syntax_handle = match name {
+            Some(name) => match ctx.editor.syntaxes.find_handle_by_name(name) {
+                Some(handle) => Some(handle),
+                None => return Err(CommandError::NoSuchSyntax),
+            },
+            None => None,
+        };
+
+        ctx.editor
+            .buffers
+            .get_mut(buffer_handle)
+            .force_syntax(syntax_handle, &ctx.editor.syntaxes);
+        Ok(())
+    });
+
+    r("syntax-inspect", &[], |ctx, io| {
+        io.args.assert_empty()?;
+        let client_handle = io.client_handle()?;
+
+        let buffer_view_handle = io.current_buffer_view_handle(ctx)?;
+        let buffer_view = ctx.editor.buffer_views.get(buffer_view_handle);
+        let range = buffer_view.cursors.main_cursor().to_range();
+        let buffer = ctx.editor.buffers.get_mut(buffer_view.buffer_handle);
+        while let HighlightResult::Pending = buffer.update_highlighting(&ctx.editor.syntaxes) {}
+
+        let syntax_name = ctx.editor.syntaxes.get(buffer.syntax_handle()).name();
+        let mut content = ctx.editor.string_pool.acquire();
+        content.push_str("syntax ");
+        content.push_str(syntax_name);
+        content.push('\n');
+
+        let lines = buffer.content().lines();
+        for line_index in range.from.line_index..=range.to.line_index {
+            let line_index = line_index as usize;
+            buffer.highlighted().write_line_inspection(
+                &ctx.editor.syntaxes,
+                line_index,
+                lines[line_index].as_str(),
+                &mut content,
+            );
+        }
+        content.pop();
+
+        let buffer_view_handle = match ctx.editor.buffer_view_handle_from_path(
+            client_handle,
+            Path::new("syntax-inspect.tokens"),
+            BufferProperties::scratch(),
+            true,
+        ) {
+            Ok(handle) => handle,
+            Err(error) => {
+                ctx.editor.string_pool.release(content);
+                return Err(CommandError::BufferReadError(error));
+            }
+        };
+
+        let buffer_handle = ctx
+            .editor
+            .buffer_views
+            .get(buffer_view_handle)
+            .buffer_handle;
+        let buffer = ctx.editor.buffers.get_mut(buffer_handle);
+        buffer.set_read_only_content(
+            &mut ctx.editor.word_database,
+            &content,
+            ctx.editor.events.writer(),
+        );
+
+        ctx.editor.string_pool.release(content);
+
+        let client = ctx.clients.get_mut(client_handle);
+        client.set_buffer_view_handle(Some(buffer_view_handle), &ctx.editor.buffer_views);
+        Ok(())
+    });
+
+    r("expand-selection", &[], |ctx, io| {
+        io.args.assert_empty()?;
+
+        let buffer_view_handle = io.current_buffer_view_handle(ctx)?;
+        let buffer_view = ctx.editor.buffer_views.get_mut(buffer_view_handle);
+        let buffer = ctx.editor.buffers.get(buffer_view.buffer_handle);
+        let highlighted = buffer.highlighted();
+        let buffer = buffer.content();
+
+        // each cursor grows to the smallest of these ranges that contains its selection:
+        // its word, its token, the inside of its enclosing brackets and then also the brackets
+        for cursor in &mut buffer_view.cursors.mut_guard()[..] {
+            let range = cursor.to_range();
+            let mut expanded: Option<BufferRange> = None;
+            let mut grow = |candidate: BufferRange| {
+                if candidate == range || range.from < candidate.from || candidate.to < range.to {
+                    return;
+                }
+                match expanded {
+                    Some(e) if candidate.from < e.from || e.to < candidate.to => (),
+                    _ => expanded = Some(candidate),
+                }
+            };
+
+            let word = buffer.word_at(range.from);
+            if word.kind != WordKind::Whitespace {
+                grow(BufferRange::between(word.position, word.end_position()));
+            }
+            if let Some((_, token_range)) = highlighted.token_range_at(buffer, range.from) {
+                grow(token_range);
+            }
+            for (left, right) in [('(', ')'), ('[', ']'), ('{', '}')] {
+                if let Some(inside) = buffer.find_balanced_chars_around(range, left, right) {
+                    grow(inside);
+                    grow(BufferRange::between(
+                        BufferPosition::line_col(
+                            inside.from.line_index,
+                            inside.from.column_byte_index - left.len_utf8() as BufferPositionIndex,
+                        ),
+                        BufferPosition::line_col(
+                            inside.to.line_index,
+                            inside.to.column_byte_index + right.len_utf8() as BufferPositionIndex,
+                        ),
+                    ));
+                }
+            }
+
+            if let Some(expanded) = expanded {
+                cursor.anchor = expanded.from;
+                cursor.position = expanded.to;
+            }
+        }
+
+        Ok(())
+    });
+
     r("list-buffer", &[], |ctx, io| {
         io.args.assert_empty()?;
         let client_handle = io.client_handle()?;
@@ -460,6 +1094,10 @@ pub fn register_commands(commands: &mut CommandManager) {
                 || !props.saving_enabled
                 || !props.file_backed_enabled
                 || !props.word_database_enabled
+                || props.line_ending != BufferLineEnding::Lf
+                || props.byte_order_mark_enabled
+                || props.persistent_history_enabled
+                || props.read_only
             {
                 content.push_str(" (");
                 if !props.history_enabled {
@@ -474,15 +1112,39 @@ pub fn register_commands(commands: &mut CommandManager) {
                 if !props.word_database_enabled {
                     content.push_str("word-database-disabled, ");
                 }
+                if props.line_ending != BufferLineEnding::Lf {
+                    content.push_str("line-ending-crlf, ");
+                }
+                if props.byte_order_mark_enabled {
+                    content.push_str("byte-order-mark-enabled, ");
+                }
+                if props.persistent_history_enabled {
+                    content.push_str("persistent-history-enabled, ");
+                }
+                if props.read_only {
+                    content.push_str("read-only-enabled, ");
+                }
                 content.truncate(content.len() - 2);
                 content.push(')');
             }
             if buffer.needs_save() {
                 content.push_str(" (needs save)");
             }
+            if let Some(indentation) = &buffer.detected_indentation {
+                match indentation.tab_size {
+                    _ if indentation.indent_with_tabs => content.push_str(" (indent with tabs)"),
+                    Some(tab_size) => {
+                        let _ = write!(content, " (indent with {} spaces)", tab_size);
+                    }
+                    None => content.push_str(" (indent with spaces)"),
+                }
+            }
             if !buffer.lints.all().is_empty() {
                 let _ = write!(content, " ({} lints)", buffer.lints.all().len());
             }
+            if buffer.is_large_file() {
+                content.push_str(" (large file)");
+            }
             content.push('\n');
         }
 
@@ -505,25 +1167,10 @@ pub fn register_commands(commands: &mut CommandManager) {
             .get(buffer_view_handle)
             .buffer_handle;
         let buffer = ctx.editor.buffers.get_mut(buffer_handle);
-        let range = BufferRange::between(BufferPosition::zero(), buffer.content().end());
-        buffer.delete_range(
+        buffer.set_read_only_content(
             &mut ctx.editor.word_database,
-            range,
-            &mut ctx
-                .editor
-                .events
-                .writer()
-                .buffer_range_deletes_mut_guard(buffer_handle),
-        );
-        buffer.insert_text(
-            &mut ctx.editor.word_database,
-            BufferPosition::zero(),
             &content,
-            &mut ctx
-                .editor
-                .events
-                .writer()
-                .buffer_text_inserts_mut_guard(buffer_handle),
+            ctx.editor.events.writer(),
         );
 
         ctx.editor.string_pool.release(content);
@@ -575,25 +1222,10 @@ pub fn register_commands(commands: &mut CommandManager) {
             .get(buffer_view_handle)
             .buffer_handle;
         let buffer = ctx.editor.buffers.get_mut(buffer_handle);
-        let range = BufferRange::between(BufferPosition::zero(), buffer.content().end());
-        buffer.delete_range(
+        buffer.set_read_only_content(
             &mut ctx.editor.word_database,
-            range,
-            &mut ctx
-                .editor
-                .events
-                .writer()
-                .buffer_range_deletes_mut_guard(buffer_handle),
-        );
-        buffer.insert_text(
-            &mut ctx.editor.word_database,
-            BufferPosition::zero(),
             &content,
-            &mut ctx
-                .editor
-                .events
-                .writer()
-                .buffer_text_inserts_mut_guard(buffer_handle),
+            ctx.editor.events.writer(),
         );
 
         ctx.editor.string_pool.release(content);
@@ -648,25 +1280,10 @@ pub fn register_commands(commands: &mut CommandManager) {
             .get(buffer_view_handle)
             .buffer_handle;
         let buffer = ctx.editor.buffers.get_mut(buffer_handle);
-        let range = BufferRange::between(BufferPosition::zero(), buffer.content().end());
-        buffer.delete_range(
-            &mut ctx.editor.word_database,
-            range,
-            &mut ctx
-                .editor
-                .events
-                .writer()
-                .buffer_range_deletes_mut_guard(buffer_handle),
-        );
-        buffer.insert_text(
+        buffer.set_read_only_content(
             &mut ctx.editor.word_database,
-            BufferPosition::zero(),
             &content,
-            &mut ctx
-                .editor
-                .events
-                .writer()
-                .buffer_text_inserts_mut_guard(buffer_handle),
+            ctx.editor.events.writer(),
         );
 
         ctx.editor.string_pool.release(content);
@@ -709,6 +1326,7 @@ pub fn register_commands(commands: &mut CommandManager) {
 
         let buffer_view_handle = io.current_buffer_view_handle(ctx)?;
         let buffer_view = ctx.editor.buffer_views.get(buffer_view_handle);
+        io.assert_can_edit_buffer(ctx, buffer_view.buffer_handle)?;
         buffer_view.delete_text_in_cursor_ranges(
             &mut ctx.editor.buffers,
             &mut ctx.editor.word_database,
@@ -741,6 +1359,7 @@ pub fn register_commands(commands: &mut CommandManager) {
 
         let buffer_view_handle = io.current_buffer_view_handle(ctx)?;
         let buffer_view = ctx.editor.buffer_views.get(buffer_view_handle);
+        io.assert_can_edit_buffer(ctx, buffer_view.buffer_handle)?;
         let buffer = ctx.editor.buffers.get_mut(buffer_view.buffer_handle);
 
         let mut cursor_texts = ctx.editor.string_pool.acquire();
@@ -812,6 +1431,7 @@ pub fn register_commands(commands: &mut CommandManager) {
 
         let buffer_view_handle = io.current_buffer_view_handle(ctx)?;
         let buffer_view = ctx.editor.buffer_views.get(buffer_view_handle);
+        io.assert_can_edit_buffer(ctx, buffer_view.buffer_handle)?;
         let buffer = ctx.editor.buffers.get_mut(buffer_view.buffer_handle);
 
         let mut events = BufferEditMutGuard::new(ctx.editor.events.writer(), buffer.handle());
@@ -886,6 +1506,175 @@ pub fn register_commands(commands: &mut CommandManager) {
         Ok(())
     });
 
+    static RETAB_COMPLETIONS: &[CompletionSource] =
+        &[CompletionSource::Custom(&["tabs", "spaces"])];
+    r("retab", RETAB_COMPLETIONS, |ctx, io| {
+        let style = io.args.try_next();
+        io.args.assert_empty()?;
+
+        let buffer_view_handle = io.current_buffer_view_handle(ctx)?;
+        let buffer_handle = ctx
+            .editor
+            .buffer_views
+            .get(buffer_view_handle)
+            .buffer_handle;
+        io.assert_can_edit_buffer(ctx, buffer_handle)?;
+        let config = ctx.editor.buffer_config(buffer_handle);
+        let indent_with_tabs = match style {
+            Some("tabs") => true,
+            Some("spaces") => false,
+            Some(_) => return Err(CommandError::InvalidIndentationStyle),
+            None => config.indent_with_tabs,
+        };
+        let indentation_config = BufferIndentationConfig {
+            indent_with_tabs,
+            tab_size: config.tab_size,
+        };
+
+        let buffer_view = ctx.editor.buffer_views.get(buffer_view_handle);
+        let buffer = ctx.editor.buffers.get_mut(buffer_handle);
+        let mut events = BufferEditMutGuard::new(ctx.editor.events.writer(), buffer_handle);
+
+        // without any selection, the whole buffer is converted
+        let cursors = &buffer_view.cursors[..];
+        if cursors.iter().all(|c| c.position == c.anchor) {
+            let line_count = buffer.content().lines().len() as _;
+            buffer.retab(indentation_config, 0..line_count, &mut events);
+            // only a whole buffer conversion changes its indentation style
+            buffer.config_overrides.indent_with_tabs = Some(indent_with_tabs);
+        } else {
+            let mut previous_line_index = BufferPositionIndex::MAX;
+            for cursor in cursors {
+                let range = cursor.to_range();
+                let from_line_index = previous_line_index
+                    .wrapping_add(1)
+                    .max(range.from.line_index);
+                let to_line_index = range.to.line_index + 1;
+                previous_line_index = range.to.line_index;
+                if from_line_index < to_line_index {
+                    buffer.retab(
+                        indentation_config,
+                        from_line_index..to_line_index,
+                        &mut events,
+                    );
+                }
+            }
+        }
+
+        buffer.commit_edits();
+        Ok(())
+    });
+
+    r("replace", &[], |ctx, io| {
+        let pattern = io.args.next()?;
+        let template = io.args.next()?;
+        io.args.assert_empty()?;
+
+        let buffer_view_handle = io.current_buffer_view_handle(ctx)?;
+        let buffer_view = ctx.editor.buffer_views.get(buffer_view_handle);
+        let buffer_handle = buffer_view.buffer_handle;
+        io.assert_can_edit_buffer(ctx, buffer_handle)?;
+
+        let pattern_dialect = ctx.editor.buffer_config(buffer_handle).pattern_dialect;
+        ctx.editor
+            .aux_pattern
+            .compile_with_captures(pattern, pattern_dialect)
+            .map_err(CommandError::PatternError)?;
+        let pattern = &ctx.editor.aux_pattern;
+
+        let buffer = ctx.editor.buffers.get_mut(buffer_handle);
+        let lines = buffer.content().lines();
+
+        // without any selection, the whole buffer is searched
+        let cursors = &buffer_view.cursors[..];
+        let mut ranges = Vec::new();
+        if cursors.iter().all(|c| c.position == c.anchor) {
+            let last_line_index = lines.len() - 1;
+            ranges.push(BufferRange::between(
+                BufferPosition::zero(),
+                BufferPosition::line_col(
+                    last_line_index as _,
+                    lines[last_line_index].as_str().len() as _,
+                ),
+            ));
+        } else {
+            ranges.extend(cursors.iter().map(|c| c.to_range()));
+        }
+
+        // all replacement texts are expanded before editing so an invalid template changes nothing
+        let mut captures = PatternCaptures::new();
+        let mut replacement_texts = ctx.editor.string_pool.acquire();
+        let mut replacements = Vec::new();
+        for range in &ranges {
+            let from = range.from;
+            let to = range.to;
+            for line_index in from.line_index..=to.line_index {
+                let text = lines[line_index as usize].as_str();
+                // matching against the whole line keeps `$` anchored to its end
+                let end = if line_index == to.line_index {
+                    to.column_byte_index as usize
+                } else {
+                    text.len()
+                };
+
+                let mut index = if line_index == from.line_index {
+                    from.column_byte_index as usize
+                } else {
+                    0
+                };
+                while index < end {
+                    match pattern.matches_with_captures(text, index, &mut captures) {
+                        MatchResult::Ok(match_end) if match_end > index && match_end <= end => {
+                            let expanded_from = replacement_texts.len();
+                            if let Err(error) =
+                                captures.expand_template(text, template, &mut replacement_texts)
+                            {
+                                ctx.editor.string_pool.release(replacement_texts);
+                                return Err(CommandError::PatternError(error));
+                            }
+
+                            let match_range = BufferRange::between(
+                                BufferPosition::line_col(line_index, index as _),
+                                BufferPosition::line_col(line_index, match_end as _),
+                            );
+                            replacements
+                                .push((match_range, expanded_from..replacement_texts.len()));
+                            index = match_end;
+                        }
+                        _ => match text[index..].chars().next() {
+                            Some(c) => index += c.len_utf8(),
+                            None => break,
+                        },
+                    }
+                }
+            }
+        }
+
+        // replacing from the end keeps the remaining match ranges valid
+        let mut events = BufferEditMutGuard::new(ctx.editor.events.writer(), buffer_handle);
+        for (range, text_range) in replacements.iter().rev() {
+            buffer.delete_range(
+                &mut ctx.editor.word_database,
+                *range,
+                events.to_range_deletes(),
+            );
+            buffer.insert_text(
+                &mut ctx.editor.word_database,
+                range.from,
+                &replacement_texts[text_range.clone()],
+                events.to_text_inserts(),
+            );
+        }
+        buffer.commit_edits();
+        ctx.editor.string_pool.release(replacement_texts);
+
+        ctx.editor
+            .logger
+            .write(LogKind::Status)
+            .fmt(format_args!("{} occurrences replaced", replacements.len()));
+        Ok(())
+    });
+
     r("set-register", &[], |ctx, io| {
         let key = io.args.next()?;
         let value = io.args.next()?;
@@ -1005,6 +1794,12 @@ pub fn register_commands(commands: &mut CommandManager) {
         }
 
         let buffer_view_handle = io.current_buffer_view_handle(ctx)?;
+        let buffer_handle = ctx
+            .editor
+            .buffer_views
+            .get(buffer_view_handle)
+            .buffer_handle;
+        io.assert_can_edit_buffer(ctx, buffer_handle)?;
         let buffer_view = ctx.editor.buffer_views.get_mut(buffer_view_handle);
 
         for cursor in buffer_view.cursors[..].iter().rev() {
@@ -1,4 +1,9 @@
-use std::fmt;
+use std::{fmt, str::FromStr};
+
+use crate::{
+    glob::{Glob, InvalidGlobError},
+    pattern::PatternDialect,
+};
 
 pub enum ParseConfigError {
     NoSuchConfig,
@@ -13,10 +18,95 @@ impl fmt::Display for ParseConfigError {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SaveBackup {
+    None,
+    Single,
+    Timestamped,
+}
+impl FromStr for SaveBackup {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "single" => Ok(Self::Single),
+            "timestamped" => Ok(Self::Timestamped),
+            _ => Err(()),
+        }
+    }
+}
+impl fmt::Display for SaveBackup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::None => f.write_str("none"),
+            Self::Single => f.write_str("single"),
+            Self::Timestamped => f.write_str("timestamped"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LineNumbers {
+    None,
+    Absolute,
+    Relative,
+    Hybrid,
+}
+impl FromStr for LineNumbers {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "absolute" => Ok(Self::Absolute),
+            "relative" => Ok(Self::Relative),
+            "hybrid" => Ok(Self::Hybrid),
+            _ => Err(()),
+        }
+    }
+}
+impl fmt::Display for LineNumbers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::None => f.write_str("none"),
+            Self::Absolute => f.write_str("absolute"),
+            Self::Relative => f.write_str("relative"),
+            Self::Hybrid => f.write_str("hybrid"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum WrapMode {
+    None,
+    Char,
+    Word,
+}
+impl FromStr for WrapMode {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "char" => Ok(Self::Char),
+            "word" => Ok(Self::Word),
+            _ => Err(()),
+        }
+    }
+}
+impl fmt::Display for WrapMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::None => f.write_str("none"),
+            Self::Char => f.write_str("char"),
+            Self::Word => f.write_str("word"),
+        }
+    }
+}
+
 macro_rules! config_values {
     ($($name:ident: $type:ty = $default:expr,)*) => {
         pub static CONFIG_NAMES: &[&str] = &[$(stringify!($name),)*];
 
+        #[derive(Clone)]
         pub struct Config {
             $(pub $name: $type,)*
         }
@@ -52,6 +142,31 @@ macro_rules! config_values {
             }
         }
 
+        // a config layer that only changes the values that were set in it
+        #[derive(Default, Clone)]
+        pub struct ConfigOverrides {
+            $(pub $name: Option<$type>,)*
+        }
+
+        impl ConfigOverrides {
+            pub fn parse_config(&mut self, key: &str, value: &str) -> Result<(), ParseConfigError> {
+                match key {
+                    $(stringify!($name) => match value.parse() {
+                        Ok(value) => self.$name = Some(value),
+                        Err(_) => return Err(ParseConfigError::InvalidValue),
+                    },)*
+                    _ => return Err(ParseConfigError::NoSuchConfig),
+                }
+                Ok(())
+            }
+
+            pub fn apply_to(&self, config: &mut Config) {
+                $(if let Some(value) = &self.$name {
+                    config.$name = value.clone();
+                })*
+            }
+        }
+
         pub struct DisplayConfig<'a> {
             config: &'a Config,
             writter: fn(&Config, &mut fmt::Formatter) -> fmt::Result
@@ -65,16 +180,105 @@ macro_rules! config_values {
     }
 }
 
+struct GlobConfigOverride {
+    pattern: String,
+    glob: Glob,
+    overrides: ConfigOverrides,
+}
+
+// overrides for buffers whose path matches a glob.
+// when several globs match, the ones added last take precedence
+#[derive(Default)]
+pub struct GlobConfigOverrides {
+    entries: Vec<GlobConfigOverride>,
+}
+impl GlobConfigOverrides {
+    pub fn get_or_add(&mut self, pattern: &str) -> Result<&mut ConfigOverrides, InvalidGlobError> {
+        let index = match self.entries.iter().position(|e| e.pattern == pattern) {
+            Some(index) => index,
+            None => {
+                let mut glob = Glob::default();
+                glob.compile(pattern)?;
+                self.entries.push(GlobConfigOverride {
+                    pattern: pattern.into(),
+                    glob,
+                    overrides: ConfigOverrides::default(),
+                });
+                self.entries.len() - 1
+            }
+        };
+        Ok(&mut self.entries[index].overrides)
+    }
+
+    pub fn apply_to(&self, config: &mut Config, path: &str) {
+        for entry in &self.entries {
+            if entry.glob.matches(path) {
+                entry.overrides.apply_to(config);
+            }
+        }
+    }
+}
+
 config_values! {
     tab_size: u8 = 4,
     indent_with_tabs: bool = false,
+    detect_indentation: bool = true,
 
     visual_empty: char = '~',
     visual_space: char = '.',
     visual_tab_first: char = '|',
     visual_tab_repeat: char = ' ',
+    visual_wrap: char = '\\',
 
     completion_min_len: u8 = 3,
     picker_max_height: u8 = 8,
     status_bar_max_height: u8 = 8,
+    line_numbers: LineNumbers = LineNumbers::None,
+    wrap: WrapMode = WrapMode::Char,
+    wrap_indentation: bool = false,
+
+    pattern_dialect: PatternDialect = PatternDialect::Pepper,
+
+    save_backup: SaveBackup = SaveBackup::None,
+    large_file_size: u32 = 16,
+    vcs_markers: bool = false,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_override_layers() {
+        let mut glob_overrides = GlobConfigOverrides::default();
+        let overrides = glob_overrides.get_or_add("**/*.c").unwrap();
+        assert!(overrides.parse_config("tab_size", "8").is_ok());
+        assert!(overrides.parse_config("indent_with_tabs", "true").is_ok());
+        assert!(overrides.parse_config("tab_size", "x").is_err());
+        assert!(overrides.parse_config("no_such_config", "0").is_err());
+        let overrides = glob_overrides.get_or_add("src/*.c").unwrap();
+        assert!(overrides.parse_config("tab_size", "3").is_ok());
+        assert!(glob_overrides.get_or_add("{").is_err());
+
+        let mut config = Config::default();
+        glob_overrides.apply_to(&mut config, "other/main.c");
+        assert_eq!(8, config.tab_size);
+        assert!(config.indent_with_tabs);
+
+        let mut config = Config::default();
+        glob_overrides.apply_to(&mut config, "src/main.c");
+        assert_eq!(3, config.tab_size);
+        assert!(config.indent_with_tabs);
+
+        let mut config = Config::default();
+        glob_overrides.apply_to(&mut config, "src/main.rs");
+        assert_eq!(4, config.tab_size);
+        assert!(!config.indent_with_tabs);
+
+        let mut buffer_overrides = ConfigOverrides::default();
+        assert!(buffer_overrides.parse_config("tab_size", "2").is_ok());
+        buffer_overrides.apply_to(&mut config);
+        assert_eq!(2, config.tab_size);
+        assert!(!config.indent_with_tabs);
+    }
 }
@@ -0,0 +1,388 @@
+use std::{fmt, ops::Range};
+
+use crate::{buffer::BufferContent, buffer_position::BufferPosition};
+
+// past this many edits, whatever is left between the common prefix and suffix is reported as a
+// single hunk so that diffing two very different texts does not take forever
+const MAX_EDIT_COUNT: usize = 1024;
+
+// lines in `old[old_range]` were replaced by the lines in `new[new_range]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffHunk {
+    pub old_range: Range<usize>,
+    pub new_range: Range<usize>,
+}
+
+// myers' diff algorithm (http://www.xmailserver.org/diff2.pdf)
+pub fn diff<T>(old: &[T], new: &[T], hunks: &mut Vec<DiffHunk>)
+where
+    T: PartialEq,
+{
+    hunks.clear();
+
+    let prefix_len = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let old_rest = &old[prefix_len..];
+    let new_rest = &new[prefix_len..];
+    let suffix_len = old_rest
+        .iter()
+        .rev()
+        .zip(new_rest.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old_rest[..old_rest.len() - suffix_len];
+    let new_middle = &new_rest[..new_rest.len() - suffix_len];
+
+    if old_middle.is_empty() && new_middle.is_empty() {
+        return;
+    }
+
+    let old_len = old_middle.len() as isize;
+    let new_len = new_middle.len() as isize;
+    let max_edit_count = (old_middle.len() + new_middle.len()).min(MAX_EDIT_COUNT) as isize;
+
+    // `frontier[k + offset]` is the furthest x reached in diagonal k = x - y
+    let offset = max_edit_count + 1;
+    let mut frontier = vec![0; 2 * offset as usize + 1];
+    let mut trace = Vec::new();
+
+    let mut edit_count = None;
+    'edits: for d in 0..=max_edit_count {
+        trace.push(frontier[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let index = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && frontier[index - 1] < frontier[index + 1]) {
+                frontier[index + 1]
+            } else {
+                frontier[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < old_len && y < new_len && old_middle[x as usize] == new_middle[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            frontier[index] = x;
+            if x >= old_len && y >= new_len {
+                edit_count = Some(d);
+                break 'edits;
+            }
+        }
+    }
+
+    let edit_count = match edit_count {
+        Some(edit_count) => edit_count,
+        None => {
+            hunks.push(DiffHunk {
+                old_range: prefix_len..prefix_len + old_middle.len(),
+                new_range: prefix_len..prefix_len + new_middle.len(),
+            });
+            return;
+        }
+    };
+
+    // walk back the edit path collecting each matched line pair
+    let mut matches = Vec::new();
+    let mut x = old_len;
+    let mut y = new_len;
+    for d in (1..=edit_count).rev() {
+        let frontier = &trace[d as usize];
+        let k = x - y;
+        let index = (k + d) as usize;
+        let previous_k = if k == -d || (k != d && frontier[index - 1] < frontier[index + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = frontier[(previous_k + d) as usize];
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            matches.push((x as usize, y as usize));
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        matches.push((x as usize, y as usize));
+    }
+
+    let mut old_index = 0;
+    let mut new_index = 0;
+    for (x, y) in matches
+        .into_iter()
+        .rev()
+        .chain(std::iter::once((old_middle.len(), new_middle.len())))
+    {
+        if old_index < x || new_index < y {
+            hunks.push(DiffHunk {
+                old_range: prefix_len + old_index..prefix_len + x,
+                new_range: prefix_len + new_index..prefix_len + y,
+            });
+        }
+        old_index = x + 1;
+        new_index = y + 1;
+    }
+}
+
+pub const UNIFIED_DIFF_CONTEXT_LEN: usize = 3;
+
+// writes `hunks` in the unified diff format (`diff -u`) with `UNIFIED_DIFF_CONTEXT_LEN` lines of
+// context around each change
+pub fn write_unified_diff(
+    write: &mut dyn fmt::Write,
+    old_path: &str,
+    new_path: &str,
+    old: &BufferContent,
+    new: &BufferContent,
+    hunks: &[DiffHunk],
+) -> fmt::Result {
+    fn write_range(write: &mut dyn fmt::Write, prefix: char, range: Range<usize>) -> fmt::Result {
+        let len = range.end - range.start;
+        match len {
+            0 => write!(write, "{}{},0", prefix, range.start),
+            1 => write!(write, "{}{}", prefix, range.start + 1),
+            _ => write!(write, "{}{},{}", prefix, range.start + 1, len),
+        }
+    }
+
+    fn write_lines(
+        write: &mut dyn fmt::Write,
+        prefix: char,
+        content: &BufferContent,
+        range: Range<usize>,
+    ) -> fmt::Result {
+        for line in content.lines().range(range) {
+            write.write_char(prefix)?;
+            write.write_str(line.as_str())?;
+            write.write_char('\n')?;
+        }
+        Ok(())
+    }
+
+    if hunks.is_empty() {
+        return Ok(());
+    }
+
+    write!(write, "--- {}\n+++ {}\n", old_path, new_path)?;
+
+    let old_len = old.lines().len();
+    let new_len = new.lines().len();
+
+    let mut group_start = 0;
+    while group_start < hunks.len() {
+        let mut group_end = group_start + 1;
+        while group_end < hunks.len()
+            && hunks[group_end].old_range.start - hunks[group_end - 1].old_range.end
+                <= 2 * UNIFIED_DIFF_CONTEXT_LEN
+        {
+            group_end += 1;
+        }
+        let group = &hunks[group_start..group_end];
+        group_start = group_end;
+
+        let first = &group[0];
+        let last = &group[group.len() - 1];
+        let context_before_len = first.old_range.start.min(UNIFIED_DIFF_CONTEXT_LEN);
+        let context_after_len = (old_len - last.old_range.end).min(UNIFIED_DIFF_CONTEXT_LEN);
+        let old_range = first.old_range.start - context_before_len
+            ..(last.old_range.end + context_after_len).min(old_len);
+        let new_range = first.new_range.start - context_before_len
+            ..(last.new_range.end + context_after_len).min(new_len);
+
+        write.write_str("@@ ")?;
+        write_range(write, '-', old_range.clone())?;
+        write.write_char(' ')?;
+        write_range(write, '+', new_range)?;
+        write.write_str(" @@\n")?;
+
+        let mut old_index = old_range.start;
+        for hunk in group {
+            write_lines(write, ' ', old, old_index..hunk.old_range.start)?;
+            write_lines(write, '-', old, hunk.old_range.clone())?;
+            write_lines(write, '+', new, hunk.new_range.clone())?;
+            old_index = hunk.old_range.end;
+        }
+        write_lines(write, ' ', old, old_index..old_range.end)?;
+    }
+
+    Ok(())
+}
+
+// finds which file and position in it the line at `line_index` of a unified diff refers to
+pub fn find_unified_diff_target(
+    diff: &BufferContent,
+    line_index: usize,
+) -> Option<(&str, BufferPosition)> {
+    fn parse_new_range(header: &str) -> Option<(usize, usize)> {
+        let header = header.strip_prefix("@@ -")?;
+        let (_, new_range) = header.split_once(" +")?;
+        let new_range = new_range.split(' ').next()?;
+        match new_range.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((new_range.parse().ok()?, 1)),
+        }
+    }
+
+    fn find_new_path(diff: &BufferContent, line_index: usize) -> Option<&str> {
+        let lines = diff.lines();
+        for i in (0..=line_index.min(lines.len() - 1)).rev() {
+            if let Some(path) = lines[i].as_str().strip_prefix("+++ ") {
+                let path = path.split('\t').next().unwrap_or(path).trim_end();
+                let is_git_diff = i > 0 && lines[i - 1].as_str().starts_with("--- a/");
+                if is_git_diff {
+                    return Some(path.strip_prefix("b/").unwrap_or(path));
+                }
+                return Some(path);
+            }
+        }
+        None
+    }
+
+    let lines = diff.lines();
+    let line = lines.get(line_index)?.as_str();
+
+    let is_header = line.starts_with("+++ ")
+        || (line.starts_with("--- ")
+            && lines
+                .get(line_index + 1)
+                .map(|l| l.as_str().starts_with("+++ "))
+                .unwrap_or(false));
+    if is_header {
+        let path = find_new_path(diff, line_index + 1)?;
+        return Some((path, BufferPosition::zero()));
+    }
+
+    let mut new_line_count = 0;
+    for i in (0..=line_index).rev() {
+        let line = lines[i].as_str();
+        if line.starts_with("@@ ") {
+            let (start, len) = parse_new_range(line)?;
+            let start = if len == 0 {
+                start
+            } else {
+                start.saturating_sub(1)
+            };
+            let path = find_new_path(diff, i)?;
+            let position = BufferPosition::line_col((start + new_line_count) as _, 0);
+            return Some((path, position));
+        }
+        if i != line_index && !line.starts_with('-') && !line.starts_with('\\') {
+            new_line_count += 1;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content(text: &str) -> BufferContent {
+        let mut content = BufferContent::new();
+        content.insert_text(BufferPosition::zero(), text);
+        content
+    }
+
+    fn hunk(old_range: Range<usize>, new_range: Range<usize>) -> DiffHunk {
+        DiffHunk {
+            old_range,
+            new_range,
+        }
+    }
+
+    #[test]
+    fn diff_lines() {
+        let mut hunks = Vec::new();
+
+        diff(&["a", "b", "c"], &["a", "b", "c"], &mut hunks);
+        assert!(hunks.is_empty());
+
+        diff(&["a", "b", "c"], &["a", "x", "c"], &mut hunks);
+        assert_eq!(&[hunk(1..2, 1..2)], &hunks[..]);
+
+        diff(&["a", "b", "c"], &["a", "c"], &mut hunks);
+        assert_eq!(&[hunk(1..2, 1..1)], &hunks[..]);
+
+        diff(&[], &["a", "b"], &mut hunks);
+        assert_eq!(&[hunk(0..0, 0..2)], &hunks[..]);
+
+        diff(
+            &["a", "b", "c", "d", "e", "f"],
+            &["x", "b", "c", "e", "f", "y"],
+            &mut hunks,
+        );
+        assert_eq!(
+            &[hunk(0..1, 0..1), hunk(3..4, 3..3), hunk(6..6, 5..6)],
+            &hunks[..]
+        );
+
+        diff(
+            &["a", "b", "c", "a", "b", "b", "a"],
+            &["c", "b", "a", "b", "a", "c"],
+            &mut hunks,
+        );
+        let edit_count: usize = hunks
+            .iter()
+            .map(|h| h.old_range.len() + h.new_range.len())
+            .sum();
+        assert_eq!(5, edit_count);
+    }
+
+    #[test]
+    fn unified_diff() {
+        let old = content("1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12");
+        let new = content("1\n2\nthree\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13");
+        let old_lines: Vec<_> = old.lines().iter().map(|l| l.as_str()).collect();
+        let new_lines: Vec<_> = new.lines().iter().map(|l| l.as_str()).collect();
+        let mut hunks = Vec::new();
+        diff(&old_lines, &new_lines, &mut hunks);
+
+        let mut text = String::new();
+        write_unified_diff(&mut text, "a.txt", "a.txt", &old, &new, &hunks).unwrap();
+        assert_eq!(
+            concat!(
+                "--- a.txt\n",
+                "+++ a.txt\n",
+                "@@ -1,6 +1,6 @@\n",
+                " 1\n",
+                " 2\n",
+                "-3\n",
+                "+three\n",
+                " 4\n",
+                " 5\n",
+                " 6\n",
+                "@@ -10,3 +10,4 @@\n",
+                " 10\n",
+                " 11\n",
+                " 12\n",
+                "+13\n",
+            ),
+            text
+        );
+
+        let diff = content(&text);
+        assert_eq!(
+            Some(("a.txt", BufferPosition::zero())),
+            find_unified_diff_target(&diff, 0)
+        );
+        assert_eq!(
+            Some(("a.txt", BufferPosition::zero())),
+            find_unified_diff_target(&diff, 2)
+        );
+        assert_eq!(
+            Some(("a.txt", BufferPosition::line_col(2, 0))),
+            find_unified_diff_target(&diff, 5)
+        );
+        assert_eq!(
+            Some(("a.txt", BufferPosition::line_col(2, 0))),
+            find_unified_diff_target(&diff, 6)
+        );
+        assert_eq!(
+            Some(("a.txt", BufferPosition::line_col(12, 0))),
+            find_unified_diff_target(&diff, 14)
+        );
+    }
+}
@@ -4,15 +4,16 @@ use std::{
 };
 
 use crate::{
-    buffer::{BufferCollection, BufferHandle, BufferProperties, BufferReadError},
+    buffer::{BufferCollection, BufferFileChange, BufferHandle, BufferProperties, BufferReadError},
     buffer_position::{BufferPosition, BufferRange},
+    buffer_recovery::BufferRecovery,
     buffer_view::{BufferViewCollection, BufferViewHandle},
     client::{ClientHandle, ClientManager},
     command::CommandManager,
-    config::Config,
+    config::{Config, GlobConfigOverrides},
     editor_utils::{
-        KeyMapCollection, Logger, LoggerStatusBarDisplay, MatchResult, PickerEntriesProcessBuf,
-        RegisterCollection, RegisterKey, StringPool,
+        KeyMapCollection, LogKind, Logger, LoggerStatusBarDisplay, MatchResult,
+        PickerEntriesProcessBuf, RegisterCollection, RegisterKey, StringPool,
     },
     events::{
         ClientEvent, EditorEvent, EditorEventIter, EditorEventQueue, KeyParseAllError, KeyParser,
@@ -93,12 +94,18 @@ pub struct EditorContext {
 }
 impl EditorContext {
     pub(crate) fn render(&mut self) {
-        let picker_height = self
-            .editor
-            .picker
-            .update_scroll(self.editor.config.picker_max_height as _);
-        self.editor.logger.on_before_render();
         let focused_client = self.clients.focused_client();
+        let picker_max_height = match focused_client {
+            Some(handle) => {
+                let buffer_view_handle = self.clients.get(handle).buffer_view_handle();
+                self.editor
+                    .buffer_view_config(buffer_view_handle)
+                    .picker_max_height
+            }
+            None => self.editor.config.picker_max_height,
+        };
+        let picker_height = self.editor.picker.update_scroll(picker_max_height as _);
+        self.editor.logger.on_before_render();
 
         let mut status_bar_lines_buf = [""; u8::MAX as _];
 
@@ -118,10 +125,11 @@ impl EditorContext {
             }
 
             let has_focus = focused_client == Some(c.handle());
+            let config = self.editor.buffer_view_config(c.buffer_view_handle());
 
             let (status_bar_display, margin_bottom) = if has_focus {
                 let width = c.viewport_size.0.saturating_sub(1);
-                let max_height = self.editor.config.status_bar_max_height;
+                let max_height = config.status_bar_max_height;
                 let max_height = c.viewport_size.1.min(max_height as _) as _;
 
                 let status_bar_display = self
@@ -137,10 +145,10 @@ impl EditorContext {
                 (LoggerStatusBarDisplay::default(), 0)
             };
 
-            let scroll = c.scroll_to_main_cursor(
+            let (scroll, horizontal_scroll) = c.scroll_to_main_cursor(
                 &mut self.editor.buffer_views,
                 &self.editor.buffers,
-                self.editor.config.tab_size,
+                &config,
                 margin_bottom,
             );
 
@@ -148,9 +156,11 @@ impl EditorContext {
             let write = buf.write_with_len(ServerEvent::bytes_variant_header_len());
             let ctx = ui::RenderContext {
                 editor: &self.editor,
+                config: &config,
                 status_bar_display: &status_bar_display,
                 viewport_size: c.viewport_size,
                 scroll,
+                horizontal_scroll,
                 has_focus,
             };
             ui::draw(&ctx, c.buffer_view_handle(), write);
@@ -187,6 +197,7 @@ impl EditorContext {
                         self.editor
                             .buffers
                             .on_buffer_text_inserts(handle, inserts, event_writer);
+                        self.editor.buffer_recovery.on_buffer_changed(handle);
                         self.editor
                             .buffer_views
                             .on_buffer_text_inserts(handle, inserts);
@@ -201,6 +212,7 @@ impl EditorContext {
                         self.editor
                             .buffers
                             .on_buffer_range_deletes(handle, deletes, event_writer);
+                        self.editor.buffer_recovery.on_buffer_changed(handle);
                         self.editor
                             .buffer_views
                             .on_buffer_range_deletes(handle, deletes);
@@ -210,15 +222,44 @@ impl EditorContext {
                             .on_buffer_range_deletes(handle, deletes);
                     }
                     EditorEvent::BufferRead { handle } => {
+                        let vcs_markers = self.editor.buffer_config(handle).vcs_markers;
                         let buffer = self.editor.buffers.get_mut(handle);
                         buffer.refresh_syntax(&self.editor.syntaxes);
+                        buffer.refresh_vcs_diff(
+                            &mut self.platform,
+                            &self.editor.current_directory,
+                            vcs_markers,
+                        );
                         self.editor.buffer_views.on_buffer_read(buffer);
+                        if self.editor.buffer_recovery.on_buffer_read(
+                            buffer,
+                            &self.editor.current_directory,
+                            self.editor.logger.log_file_path(),
+                        ) {
+                            let mut write = self.editor.logger.write(LogKind::Info);
+                            write.fmt(format_args!(
+                                "buffer {:?} has unsaved changes from a previous session\n",
+                                &buffer.path
+                            ));
+                            write.str("use `recover` to restore them");
+                        }
                     }
                     EditorEvent::BufferWrite { handle, new_path } => {
+                        let vcs_markers = self.editor.buffer_config(handle).vcs_markers;
                         let buffer = self.editor.buffers.get_mut(handle);
                         if new_path {
                             buffer.refresh_syntax(&self.editor.syntaxes);
                         }
+                        buffer.refresh_vcs_diff(
+                            &mut self.platform,
+                            &self.editor.current_directory,
+                            vcs_markers,
+                        );
+                        self.editor.buffer_recovery.on_buffer_write(
+                            buffer,
+                            &self.editor.current_directory,
+                            self.editor.logger.log_file_path(),
+                        );
 
                         for client in self.clients.iter() {
                             if client.stdin_buffer_handle() == Some(buffer.handle()) {
@@ -243,6 +284,7 @@ impl EditorContext {
                         }
                     }
                     EditorEvent::BufferClose { handle } => {
+                        self.editor.buffer_recovery.on_buffer_close(handle);
                         self.editor.buffers.remove_now(
                             &mut self.platform,
                             handle,
@@ -287,6 +329,7 @@ pub struct Editor {
     pub session_name: String,
 
     pub config: Config,
+    pub glob_config_overrides: GlobConfigOverrides,
     pub theme: Theme,
     pub syntaxes: SyntaxCollection,
     pub keymaps: KeyMapCollection,
@@ -295,6 +338,7 @@ pub struct Editor {
     pub buffers: BufferCollection,
     pub buffer_views: BufferViewCollection,
     pub word_database: WordDatabase,
+    pub buffer_recovery: BufferRecovery,
 
     pub buffered_keys: BufferedKeys,
     pub recording_macro: Option<RegisterKey>,
@@ -317,6 +361,7 @@ impl Editor {
             session_name,
 
             config: Config::default(),
+            glob_config_overrides: GlobConfigOverrides::default(),
             theme: Theme::default(),
             syntaxes: SyntaxCollection::new(),
             keymaps: KeyMapCollection::default(),
@@ -326,6 +371,7 @@ impl Editor {
             buffers: BufferCollection::default(),
             buffer_views: BufferViewCollection::default(),
             word_database: WordDatabase::new(),
+            buffer_recovery: BufferRecovery::default(),
 
             buffered_keys: BufferedKeys::default(),
             recording_macro: None,
@@ -343,6 +389,19 @@ impl Editor {
         }
     }
 
+    pub fn buffer_config(&self, buffer_handle: BufferHandle) -> Config {
+        self.buffers
+            .get(buffer_handle)
+            .resolve_config(&self.config, &self.glob_config_overrides)
+    }
+
+    pub fn buffer_view_config(&self, buffer_view_handle: Option<BufferViewHandle>) -> Config {
+        match buffer_view_handle {
+            Some(handle) => self.buffer_config(self.buffer_views.get(handle).buffer_handle),
+            None => self.config.clone(),
+        }
+    }
+
     pub fn buffer_handle_from_path(
         &mut self,
         path: &Path,
@@ -362,9 +421,12 @@ impl Editor {
                 buffer.properties = properties;
 
                 let mut read_error = None;
-                if let Err(error) =
-                    buffer.read_from_file(&mut self.word_database, self.events.writer())
-                {
+                if let Err(error) = buffer.read_from_file(
+                    &mut self.word_database,
+                    &self.current_directory,
+                    self.config.large_file_size,
+                    self.events.writer(),
+                ) {
                     read_error = Some(error);
                 }
 
@@ -536,7 +598,70 @@ impl Editor {
         }
     }
 
-    pub(crate) fn on_idle(&mut self) {
+    pub(crate) fn on_idle(&mut self, clients: &ClientManager) {
+        // only buffers that are being shown are checked. the others are checked once they're
+        // shown again and saving them is guarded against overwriting external changes anyway
+        let mut checked_buffer_handles = Vec::new();
+        for client in clients.iter() {
+            let buffer_handle = match client.buffer_view_handle() {
+                Some(handle) => self.buffer_views.get(handle).buffer_handle,
+                None => continue,
+            };
+            if checked_buffer_handles.contains(&buffer_handle) {
+                continue;
+            }
+            checked_buffer_handles.push(buffer_handle);
+
+            let buffer = self.buffers.get_mut(buffer_handle);
+            match buffer.check_file_changed(
+                &mut self.word_database,
+                &self.current_directory,
+                self.config.large_file_size,
+                self.events.writer(),
+            ) {
+                BufferFileChange::None => (),
+                BufferFileChange::Reloaded => {
+                    self.logger.write(LogKind::Info).fmt(format_args!(
+                        "buffer {:?} reloaded because its file changed on disk",
+                        &buffer.path
+                    ));
+                }
+                BufferFileChange::ReloadFailed(error) => {
+                    self.logger.write(LogKind::Error).fmt(format_args!(
+                        "buffer {:?} could not be reloaded after its file changed on disk: {}",
+                        &buffer.path, error
+                    ));
+                }
+                BufferFileChange::Conflict => {
+                    let mut write = self.logger.write(LogKind::Error);
+                    write.fmt(format_args!(
+                        "buffer {:?} has unsaved changes but its file changed on disk\n",
+                        &buffer.path
+                    ));
+                    write.str("use `reopen!` to discard them or `save!` to overwrite the file");
+                }
+            }
+
+            // diffing the whole buffer is too slow to do on every render
+            buffer.update_vcs_diff();
+        }
+
+        self.buffer_recovery.on_idle(
+            &self.buffers,
+            &self.current_directory,
+            self.logger.log_file_path(),
+        );
+
         self.events.writer().enqueue(EditorEvent::Idle);
     }
+
+    pub(crate) fn on_buffer_read_process_exit(&mut self, handle: BufferHandle, id: u32) {
+        let buffer = self.buffers.get_mut(handle);
+        if let Err(error) = buffer.on_read_process_exit(id, self.events.writer()) {
+            self.logger.write(LogKind::Error).fmt(format_args!(
+                "could not read buffer {:?}: {}",
+                &buffer.path, error
+            ));
+        }
+    }
 }
@@ -610,8 +610,11 @@ impl PickerEntriesProcessBuf {
 }
 
 // FNV-1a : https://en.wikipedia.org/wiki/Fowler–Noll–Vo_hash_function
-pub const fn hash_bytes(mut bytes: &[u8]) -> u64 {
-    let mut hash: u64 = 0xcbf29ce484222325;
+pub const fn hash_bytes(bytes: &[u8]) -> u64 {
+    continue_hash_bytes(0xcbf29ce484222325, bytes)
+}
+
+pub const fn continue_hash_bytes(mut hash: u64, mut bytes: &[u8]) -> u64 {
     while let [b, rest @ ..] = bytes {
         hash ^= *b as u64;
         hash = hash.wrapping_mul(0x100000001b3);
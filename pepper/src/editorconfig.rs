@@ -0,0 +1,309 @@
+use std::{fs, path::Path};
+
+use crate::{buffer::BufferLineEnding, config::Config, glob::Glob};
+
+// properties read from `.editorconfig` files (https://editorconfig.org) that override
+// the editor config for a single buffer. `None` means the property is not set
+#[derive(Default, Clone, Copy)]
+pub struct EditorConfig {
+    pub indent_with_tabs: Option<bool>,
+    pub indent_size: Option<u8>,
+    pub tab_width: Option<u8>,
+    pub line_ending: Option<BufferLineEnding>,
+    pub insert_final_newline: Option<bool>,
+    pub trim_trailing_whitespace: Option<bool>,
+}
+
+impl EditorConfig {
+    // walks up from the file's directory until a `root = true` file is found.
+    // properties from files closer to the file take precedence
+    pub fn find(current_directory: &Path, path: &Path) -> Self {
+        let mut config = Self::default();
+        let path = current_directory.join(path);
+
+        let mut sources: Vec<(&Path, String)> = Vec::new();
+        let mut directory = path.parent();
+        while let Some(d) = directory {
+            if let Ok(source) = fs::read_to_string(d.join(".editorconfig")) {
+                let is_root = is_root(&source);
+                sources.push((d, source));
+                if is_root {
+                    break;
+                }
+            }
+            directory = d.parent();
+        }
+
+        let mut glob = Glob::default();
+        let mut pattern = String::new();
+        for (directory, source) in sources.iter().rev() {
+            let relative_path = match path.strip_prefix(directory).map(normalize_separators) {
+                Ok(Some(path)) => path,
+                _ => continue,
+            };
+            config.parse(source, &relative_path, &mut glob, &mut pattern);
+        }
+
+        config
+    }
+
+    fn parse(&mut self, source: &str, path: &str, glob: &mut Glob, pattern: &mut String) {
+        let mut section_matches = false;
+        for line in source.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(['#', ';']) {
+                continue;
+            }
+
+            if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                // sections without a `/` match files in any subdirectory
+                pattern.clear();
+                match section.strip_prefix('/') {
+                    Some(section) => pattern.push_str(section),
+                    None if section.contains('/') => pattern.push_str(section),
+                    None => {
+                        pattern.push_str("**/");
+                        pattern.push_str(section);
+                    }
+                }
+                section_matches = glob.compile(pattern).is_ok() && glob.matches(path);
+                continue;
+            }
+
+            if section_matches {
+                if let Some((key, value)) = line.split_once('=') {
+                    self.set(key.trim(), value.trim());
+                }
+            }
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) {
+        fn parse<T>(value: &str, f: fn(&str) -> Option<T>) -> Option<Option<T>> {
+            if value.eq_ignore_ascii_case("unset") {
+                Some(None)
+            } else {
+                f(value).map(Some)
+            }
+        }
+
+        fn parse_bool(value: &str) -> Option<bool> {
+            if value.eq_ignore_ascii_case("true") {
+                Some(true)
+            } else if value.eq_ignore_ascii_case("false") {
+                Some(false)
+            } else {
+                None
+            }
+        }
+
+        fn parse_size(value: &str) -> Option<u8> {
+            value.parse().ok().filter(|&size| size > 0)
+        }
+
+        // invalid values are ignored and keep the previous one
+        let key = key.to_ascii_lowercase();
+        match &key[..] {
+            "indent_style" => {
+                let value = parse(value, |v| {
+                    if v.eq_ignore_ascii_case("tab") {
+                        Some(true)
+                    } else if v.eq_ignore_ascii_case("space") {
+                        Some(false)
+                    } else {
+                        None
+                    }
+                });
+                if let Some(value) = value {
+                    self.indent_with_tabs = value;
+                }
+            }
+            "indent_size" => {
+                // `tab` means indent size is the same as the tab width
+                if value.eq_ignore_ascii_case("tab") {
+                    self.indent_size = None;
+                } else if let Some(value) = parse(value, parse_size) {
+                    self.indent_size = value;
+                }
+            }
+            "tab_width" => {
+                if let Some(value) = parse(value, parse_size) {
+                    self.tab_width = value;
+                }
+            }
+            "end_of_line" => {
+                let value = parse(value, |v| {
+                    if v.eq_ignore_ascii_case("lf") {
+                        Some(BufferLineEnding::Lf)
+                    } else if v.eq_ignore_ascii_case("crlf") {
+                        Some(BufferLineEnding::CrLf)
+                    } else {
+                        None
+                    }
+                });
+                if let Some(value) = value {
+                    self.line_ending = value;
+                }
+            }
+            "insert_final_newline" => {
+                if let Some(value) = parse(value, parse_bool) {
+                    self.insert_final_newline = value;
+                }
+            }
+            "trim_trailing_whitespace" => {
+                if let Some(value) = parse(value, parse_bool) {
+                    self.trim_trailing_whitespace = value;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // pepper uses a single size both for indentation and for displaying tabs
+    pub fn tab_size(&self) -> Option<u8> {
+        match self.indent_with_tabs {
+            Some(true) => self.tab_width.or(self.indent_size),
+            _ => self.indent_size.or(self.tab_width),
+        }
+    }
+
+    pub fn apply_to(&self, config: &mut Config) {
+        if let Some(indent_with_tabs) = self.indent_with_tabs {
+            config.indent_with_tabs = indent_with_tabs;
+        }
+        if let Some(tab_size) = self.tab_size() {
+            config.tab_size = tab_size;
+        }
+    }
+}
+
+fn is_root(source: &str) -> bool {
+    for line in source.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            break;
+        }
+        if let Some((key, value)) = line.split_once('=') {
+            if key.trim().eq_ignore_ascii_case("root") {
+                return value.trim().eq_ignore_ascii_case("true");
+            }
+        }
+    }
+    false
+}
+
+fn normalize_separators(path: &Path) -> Option<String> {
+    let path = path.to_str()?;
+    if cfg!(windows) {
+        Some(path.replace('\\', "/"))
+    } else {
+        Some(path.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn parse(source: &str, path: &str) -> EditorConfig {
+        let mut config = EditorConfig::default();
+        let mut glob = Glob::default();
+        let mut pattern = String::new();
+        config.parse(source, path, &mut glob, &mut pattern);
+        config
+    }
+
+    #[test]
+    fn parse_sections() {
+        let source = "
+            root = true
+
+            [*]
+            end_of_line = lf
+            insert_final_newline = true
+
+            # comment
+            [*.{js,yml}]
+            indent_style = space
+            indent_size = 2
+
+            [Makefile]
+            indent_style = tab
+            tab_width = 8
+
+            [/lib/**/*.c]
+            indent_size = 3
+
+            [src/*.c]
+            trim_trailing_whitespace = true
+            INDENT_SIZE = invalid
+        ";
+
+        let config = parse(source, "a/b.yml");
+        assert_eq!(Some(false), config.indent_with_tabs);
+        assert_eq!(Some(2), config.tab_size());
+        assert!(matches!(config.line_ending, Some(BufferLineEnding::Lf)));
+        assert_eq!(Some(true), config.insert_final_newline);
+        assert_eq!(None, config.trim_trailing_whitespace);
+
+        let config = parse(source, "Makefile");
+        assert_eq!(Some(true), config.indent_with_tabs);
+        assert_eq!(Some(8), config.tab_size());
+
+        let config = parse(source, "lib/a/b.c");
+        assert_eq!(Some(3), config.tab_size());
+
+        let config = parse(source, "src/main.c");
+        assert_eq!(None, config.tab_size());
+        assert_eq!(Some(true), config.trim_trailing_whitespace);
+
+        let config = parse(source, "other/src/main.c");
+        assert_eq!(None, config.trim_trailing_whitespace);
+
+        let config = parse("[*]\nindent_size = 4\n[*.md]\nindent_size = unset", "a.md");
+        assert_eq!(None, config.indent_size);
+
+        assert!(is_root(source));
+        assert!(!is_root("[*]\nroot = true"));
+    }
+
+    #[test]
+    fn find_in_parent_directories() {
+        let directory = env::temp_dir().join(format!(
+            "pepper-test-{}-editorconfig-find",
+            std::process::id()
+        ));
+        let nested_directory = directory.join("nested");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&nested_directory).unwrap();
+
+        fs::write(
+            directory.join(".editorconfig"),
+            "root = true\n[*]\nindent_style = tab\nindent_size = 8\n",
+        )
+        .unwrap();
+        fs::write(
+            nested_directory.join(".editorconfig"),
+            "[*.txt]\nindent_size = 3\n",
+        )
+        .unwrap();
+
+        let config = EditorConfig::find(Path::new(""), &nested_directory.join("file.txt"));
+        assert_eq!(Some(true), config.indent_with_tabs);
+        assert_eq!(Some(3), config.indent_size);
+
+        let config = EditorConfig::find(Path::new(""), &nested_directory.join("file.rs"));
+        assert_eq!(Some(8), config.indent_size);
+
+        // relative paths are resolved from the editor's current directory
+        let config = EditorConfig::find(&nested_directory, Path::new("file.txt"));
+        assert_eq!(Some(3), config.indent_size);
+        let config = EditorConfig::find(&directory, Path::new("nested/file.txt"));
+        assert_eq!(Some(3), config.indent_size);
+        let config = EditorConfig::find(&directory, Path::new("file.txt"));
+        assert_eq!(Some(8), config.indent_size);
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
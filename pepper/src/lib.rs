@@ -2,13 +2,16 @@ pub mod application;
 pub mod buffer;
 pub mod buffer_history;
 pub mod buffer_position;
+pub mod buffer_recovery;
 pub mod buffer_view;
 pub mod client;
 pub mod command;
 pub mod config;
 pub mod cursor;
+pub mod diff;
 pub mod editor;
 pub mod editor_utils;
+pub mod editorconfig;
 pub mod events;
 pub mod glob;
 pub mod help;
@@ -22,6 +25,8 @@ pub mod serialization;
 pub mod syntax;
 pub mod theme;
 pub mod ui;
+pub mod unicode_width;
+pub mod vcs;
 pub mod word_database;
 
 pub const DEFAULT_CONFIGS: ResourceFile = ResourceFile {
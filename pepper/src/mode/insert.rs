@@ -1,13 +1,14 @@
 use std::fmt::Write;
 
 use crate::{
-    buffer::BufferHandle,
-    buffer_position::{BufferPosition, BufferRange},
+    buffer::{BufferHandle, BufferIndentationConfig},
+    buffer_position::{BufferPosition, BufferPositionIndex, BufferRange},
     buffer_view::{BufferViewHandle, CursorMovement, CursorMovementKind},
     client::ClientHandle,
+    config::Config,
     editor::{Editor, EditorContext, EditorFlow, KeysIterator},
-    editor_utils::REGISTER_AUTO_MACRO,
-    events::EditorEventTextInsert,
+    editor_utils::{LogKind, REGISTER_AUTO_MACRO},
+    events::{BufferEditMutGuard, EditorEventTextInsert},
     mode::{ModeKind, ModeState},
     platform::{Key, KeyCode},
     plugin::{CompletionContext, PluginHandle},
@@ -79,9 +80,20 @@ impl ModeState for State {
             }
         };
 
-        ctx.editor.mode.insert_state.editing_buffer_handle =
-            Some(ctx.editor.buffer_views.get(handle).buffer_handle);
+        let buffer_handle = ctx.editor.buffer_views.get(handle).buffer_handle;
+        if ctx.editor.buffers.get(buffer_handle).properties.read_only {
+            ctx.editor
+                .logger
+                .write(LogKind::Error)
+                .str("buffer is read-only");
+            keys.index = ctx.editor.buffered_keys.as_slice().len();
+            ctx.editor.enter_mode(ModeKind::default());
+            return Some(EditorFlow::Continue);
+        }
+
+        ctx.editor.mode.insert_state.editing_buffer_handle = Some(buffer_handle);
 
+        let config = ctx.editor.buffer_view_config(Some(handle));
         let key = keys.next(&ctx.editor.buffered_keys);
         let register = ctx.editor.registers.get_mut(REGISTER_AUTO_MACRO);
         let _ = write!(register, "{}", key);
@@ -112,7 +124,7 @@ impl ModeState for State {
                     &ctx.editor.buffers,
                     CursorMovement::LinesForward {
                         count: 1,
-                        tab_size: ctx.editor.config.tab_size,
+                        tab_size: config.tab_size,
                     },
                     CursorMovementKind::PositionAndAnchor,
                 );
@@ -124,7 +136,7 @@ impl ModeState for State {
                     &ctx.editor.buffers,
                     CursorMovement::LinesBackward {
                         count: 1,
-                        tab_size: ctx.editor.config.tab_size,
+                        tab_size: config.tab_size,
                     },
                     CursorMovementKind::PositionAndAnchor,
                 );
@@ -142,28 +154,27 @@ impl ModeState for State {
             }
             Key { code: KeyCode::Char('\t'), control: false, alt: false, .. } => {
                 static SPACES_BUF: &[u8; u8::MAX as usize] = &[b' '; u8::MAX as usize];
-                let text = if ctx.editor.config.indent_with_tabs {
+                let buffer_view = ctx.editor.buffer_views.get(handle);
+                let text = if config.indent_with_tabs {
                     "\t"
                 } else {
-                    let len = ctx.editor.config.tab_size as usize;
+                    let len = config.tab_size as usize;
                     unsafe { std::str::from_utf8_unchecked(&SPACES_BUF[..len]) }
                 };
 
-                ctx.editor
-                    .buffer_views
-                    .get(handle)
-                    .insert_text_at_cursor_positions(
-                        &mut ctx.editor.buffers,
-                        &mut ctx.editor.word_database,
-                        text,
-                        ctx.editor.events.writer(),
-                    );
+                buffer_view.insert_text_at_cursor_positions(
+                    &mut ctx.editor.buffers,
+                    &mut ctx.editor.word_database,
+                    text,
+                    ctx.editor.events.writer(),
+                );
             }
             Key { code: KeyCode::Char('\n'), control: false, alt: false, .. }
             | Key { code: KeyCode::Char('m'), shift: false, control: true, alt: false } => {
                 let buffer_view = ctx.editor.buffer_views.get(handle);
                 let cursor_count = buffer_view.cursors[..].len();
                 let buffer = ctx.editor.buffers.get_mut(buffer_view.buffer_handle);
+                let syntax = ctx.editor.syntaxes.get(buffer.syntax_handle());
 
                 let mut buf = ctx.editor.string_pool.acquire();
                 let mut events = ctx.editor.events.writer().buffer_text_inserts_mut_guard(buffer.handle());
@@ -181,6 +192,18 @@ impl ModeState for State {
                         buf.push_str(&indentation_word.text[..indentation_len as usize]);
                     }
 
+                    let line = buffer.content().lines()[position.line_index as usize].as_str();
+                    let (line_before, line_after) = line.split_at(position.column_byte_index as _);
+                    if syntax.indents(line_before) && !syntax.dedents(line_after) {
+                        if config.indent_with_tabs {
+                            buf.push('\t');
+                        } else {
+                            for _ in 0..config.tab_size {
+                                buf.push(' ');
+                            }
+                        }
+                    }
+
                     buffer.insert_text(
                         &mut ctx.editor.word_database,
                         position,
@@ -201,6 +224,7 @@ impl ModeState for State {
                     s,
                     ctx.editor.events.writer(),
                 );
+                fix_dedented_cursor_lines(ctx, handle, &config, s.len());
             }
             Key { code: KeyCode::Backspace, shift: false, control: false, alt: false }
             | Key { code: KeyCode::Char('h'), shift: false, control: true, alt: false } => {
@@ -259,6 +283,52 @@ impl ModeState for State {
     }
 }
 
+// re-indents lines whose dedent pattern match was just completed by typing its last char.
+// cursors are only moved past the typed text once the event handlers run, so that is accounted for
+fn fix_dedented_cursor_lines(
+    ctx: &mut EditorContext,
+    handle: BufferViewHandle,
+    config: &Config,
+    typed_len: usize,
+) {
+    let buffer_view = ctx.editor.buffer_views.get(handle);
+    let buffer = ctx.editor.buffers.get_mut(buffer_view.buffer_handle);
+    let syntax = ctx.editor.syntaxes.get(buffer.syntax_handle());
+
+    let indentation_config = BufferIndentationConfig {
+        indent_with_tabs: config.indent_with_tabs,
+        tab_size: config.tab_size,
+    };
+    let mut events = BufferEditMutGuard::new(ctx.editor.events.writer(), buffer.handle());
+
+    let mut line_index = BufferPositionIndex::MAX;
+    let mut line_typed_len = 0;
+    let mut fixed_line_index = BufferPositionIndex::MAX;
+    for cursor in &buffer_view.cursors[..] {
+        let position = cursor.position;
+        if position.line_index != line_index {
+            line_index = position.line_index;
+            line_typed_len = 0;
+        }
+        line_typed_len += typed_len;
+        if position.line_index == fixed_line_index {
+            continue;
+        }
+
+        let line = buffer.content().lines()[position.line_index as usize].as_str();
+        let column = position.column_byte_index as usize + line_typed_len;
+        if syntax.dedent_match_end(line) == Some(column) {
+            fixed_line_index = position.line_index;
+            buffer.fix_line_indentation(
+                indentation_config,
+                &ctx.editor.syntaxes,
+                position.line_index,
+                &mut events,
+            );
+        }
+    }
+}
+
 fn cancel_completion(editor: &mut Editor) {
     editor.picker.clear();
     editor.mode.insert_state.completion_positions.clear();
@@ -303,8 +373,11 @@ fn update_completions(
             None => {
                 ctx.editor.picker.clear();
 
-                let completion_requested = word.kind == WordKind::Identifier
-                    && word.text.len() >= ctx.editor.config.completion_min_len as _;
+                let completion_min_len = buffer
+                    .resolve_config(&ctx.editor.config, &ctx.editor.glob_config_overrides)
+                    .completion_min_len;
+                let completion_requested =
+                    word.kind == WordKind::Identifier && word.text.len() >= completion_min_len as _;
                 let completion_ctx = CompletionContext {
                     client_handle,
                     buffer_handle,
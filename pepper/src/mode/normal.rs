@@ -2,10 +2,11 @@ use std::{cmp::Ordering, fmt::Write, path::Path};
 
 use crate::{
     buffer::{BufferContent, BufferHandle, BufferIndentationConfig, BufferProperties},
-    buffer_position::{BufferPosition, BufferPositionIndex, BufferRange},
+    buffer_position::{BufferPosition, BufferPositionIndex, BufferRange, BufferRangesParser},
     buffer_view::{BufferViewHandle, CursorMovement, CursorMovementKind},
     client::{ClientHandle, ViewAnchor},
     cursor::Cursor,
+    diff::find_unified_diff_target,
     editor::{Editor, EditorContext, EditorFlow, KeysIterator},
     editor_utils::{
         find_path_and_ranges_at, hash_bytes, parse_path_and_ranges, LogKind, RegisterKey,
@@ -16,6 +17,7 @@ use crate::{
     navigation_history::{NavigationHistory, NavigationMovement},
     pattern::PatternEscaper,
     platform::{Key, KeyCode},
+    syntax::{HighlightedBuffer, TokenKind},
     word_database::WordKind,
 };
 
@@ -82,6 +84,9 @@ impl State {
         keys: &mut KeysIterator,
         handle: BufferViewHandle,
     ) -> Option<EditorFlow> {
+        let config = ctx.editor.buffer_view_config(Some(handle));
+        let buffer_handle = ctx.editor.buffer_views.get(handle).buffer_handle;
+        let read_only = ctx.editor.buffers.get(buffer_handle).properties.read_only;
         let state = &mut ctx.editor.mode.normal_state;
         let keys_from_index = keys.index;
         match keys.next(&ctx.editor.buffered_keys) {
@@ -104,7 +109,7 @@ impl State {
                 &ctx.editor.buffers,
                 CursorMovement::LinesForward {
                     count: state.count.max(1) as _,
-                    tab_size: ctx.editor.config.tab_size,
+                    tab_size: config.tab_size,
                 },
                 state.movement_kind,
             ),
@@ -117,7 +122,7 @@ impl State {
                 &ctx.editor.buffers,
                 CursorMovement::LinesBackward {
                     count: state.count.max(1) as _,
-                    tab_size: ctx.editor.config.tab_size,
+                    tab_size: config.tab_size,
                 },
                 state.movement_kind,
             ),
@@ -292,10 +297,62 @@ impl State {
                     }
                 }
 
+                // the exclusive selection leaves out the token's delimiters which are guessed as
+                // its leading punctuation and as much of its trailing punctuation
+                fn select_token(
+                    buffer: &BufferContent,
+                    highlighted: &HighlightedBuffer,
+                    cursors: &mut [Cursor],
+                    kind: TokenKind,
+                    select_exclusive: bool,
+                ) {
+                    fn is_delimiter(b: &u8) -> bool {
+                        b.is_ascii_punctuation() && *b != b'_'
+                    }
+
+                    for cursor in cursors {
+                        let mut range = match highlighted.token_range_at(buffer, cursor.position) {
+                            Some((token_kind, range)) if token_kind == kind => range,
+                            _ => continue,
+                        };
+
+                        if select_exclusive {
+                            let from_line = buffer.lines()[range.from.line_index as usize].as_str();
+                            let to_line = buffer.lines()[range.to.line_index as usize].as_str();
+                            let mut left_len = from_line[range.from.column_byte_index as usize..]
+                                .bytes()
+                                .take_while(is_delimiter)
+                                .count();
+                            let mut right_len = to_line[..range.to.column_byte_index as usize]
+                                .bytes()
+                                .rev()
+                                .take_while(is_delimiter)
+                                .count()
+                                .min(left_len);
+                            if range.from.line_index == range.to.line_index {
+                                let len = (range.to.column_byte_index
+                                    - range.from.column_byte_index)
+                                    as usize;
+                                if left_len + right_len > len {
+                                    left_len = len / 2;
+                                    right_len = len / 2;
+                                }
+                            }
+                            range.from.column_byte_index += left_len as BufferPositionIndex;
+                            range.to.column_byte_index -= right_len as BufferPositionIndex;
+                        }
+
+                        cursor.anchor = range.from;
+                        cursor.position = range.to;
+                    }
+                }
+
                 let select_exclusive = c == 'a';
 
                 let buffer_view = ctx.editor.buffer_views.get_mut(handle);
-                let buffer = ctx.editor.buffers.get(buffer_view.buffer_handle).content();
+                let buffer = ctx.editor.buffers.get(buffer_view.buffer_handle);
+                let highlighted = buffer.highlighted();
+                let buffer = buffer.content();
                 let mut cursors = buffer_view.cursors.mut_guard();
 
                 match keys.next(&ctx.editor.buffered_keys) {
@@ -472,6 +529,30 @@ impl State {
                         alt: false,
                         ..
                     } => select_delimiter_pair(buffer, &mut cursors[..], '`', select_exclusive),
+                    Key {
+                        code: KeyCode::Char('s'),
+                        control: false,
+                        alt: false,
+                        ..
+                    } => select_token(
+                        buffer,
+                        highlighted,
+                        &mut cursors[..],
+                        TokenKind::String,
+                        select_exclusive,
+                    ),
+                    Key {
+                        code: KeyCode::Char('c'),
+                        control: false,
+                        alt: false,
+                        ..
+                    } => select_token(
+                        buffer,
+                        highlighted,
+                        &mut cursors[..],
+                        TokenKind::Comment,
+                        select_exclusive,
+                    ),
                     _ => (),
                 }
 
@@ -664,13 +745,28 @@ impl State {
                             }
 
                             let buffer = ctx.editor.buffers.get(buffer_view.buffer_handle);
-                            let line = buffer.content().lines()[line_index as usize].as_str();
-                            let line = ctx.editor.string_pool.acquire_with(line);
-
                             let from = range.from.column_byte_index;
                             let to = range.to.column_byte_index;
 
-                            let (path, ranges) = if from < to {
+                            // inside a diff, jump to the line in the changed file instead
+                            let syntax = ctx.editor.syntaxes.get(buffer.syntax_handle());
+                            let is_diff = syntax.name() == "diff";
+                            let diff_target = if is_diff && from == to {
+                                find_unified_diff_target(buffer.content(), line_index as _)
+                            } else {
+                                None
+                            };
+
+                            let line = match diff_target {
+                                Some((path, _)) => path,
+                                None => buffer.content().lines()[line_index as usize].as_str(),
+                            };
+                            let line = ctx.editor.string_pool.acquire_with(line);
+                            let diff_position = diff_target.map(|(_, position)| position);
+
+                            let (path, ranges) = if diff_position.is_some() {
+                                (&line[..], BufferRangesParser(""))
+                            } else if from < to {
                                 parse_path_and_ranges(&line[from as usize..to as usize])
                             } else {
                                 find_path_and_ranges_at(&line, from as _)
@@ -713,6 +809,14 @@ impl State {
                                         let mut cursors = buffer_view.cursors.mut_guard();
                                         cursors.clear();
 
+                                        if let Some(position) = diff_position {
+                                            let position =
+                                                buffer_content.saturate_position(position);
+                                            cursors.add(Cursor {
+                                                anchor: position,
+                                                position,
+                                            });
+                                        }
                                         for range in ranges {
                                             cursors.add(Cursor {
                                                 anchor: buffer_content.saturate_position(range.0),
@@ -1136,7 +1240,7 @@ impl State {
                     &ctx.editor.buffers,
                     CursorMovement::LinesForward {
                         count: half_height as usize * state.count.max(1) as usize,
-                        tab_size: ctx.editor.config.tab_size,
+                        tab_size: config.tab_size,
                     },
                     state.movement_kind,
                 );
@@ -1153,7 +1257,7 @@ impl State {
                     &ctx.editor.buffers,
                     CursorMovement::LinesBackward {
                         count: half_height as usize * state.count.max(1) as usize,
-                        tab_size: ctx.editor.config.tab_size,
+                        tab_size: config.tab_size,
                     },
                     state.movement_kind,
                 );
@@ -1164,6 +1268,9 @@ impl State {
                 alt: false,
                 ..
             } => {
+                if read_only {
+                    return log_read_only_error(&mut ctx.editor, keys);
+                }
                 let buffer_view = ctx.editor.buffer_views.get(handle);
                 buffer_view.delete_text_in_cursor_ranges(
                     &mut ctx.editor.buffers,
@@ -1185,6 +1292,9 @@ impl State {
                 alt: false,
                 ..
             } => {
+                if read_only {
+                    return log_read_only_error(&mut ctx.editor, keys);
+                }
                 state.movement_kind = CursorMovementKind::PositionAndAnchor;
 
                 let buffer_view = ctx.editor.buffer_views.get(handle);
@@ -1203,9 +1313,13 @@ impl State {
                 control: false,
                 alt: false,
                 ..
-            } if ctx.editor.config.tab_size > 0 => {
+            } if config.tab_size > 0 => {
+                if read_only {
+                    return log_read_only_error(&mut ctx.editor, keys);
+                }
                 let buffer_view = ctx.editor.buffer_views.get(handle);
                 let buffer = ctx.editor.buffers.get_mut(buffer_view.buffer_handle);
+                let tab_size = config.tab_size;
                 let count = state.count.max(1);
 
                 let mut events = ctx
@@ -1233,7 +1347,7 @@ impl State {
                                 Some((i, c @ '\t')) => i + c.len_utf8(),
                                 Some((i, c @ ' ')) => {
                                     match chars
-                                        .take(ctx.editor.config.tab_size as usize - 1)
+                                        .take(tab_size as usize - 1)
                                         .take_while(|(_, c)| *c == ' ')
                                         .last()
                                     {
@@ -1262,19 +1376,22 @@ impl State {
                 control: false,
                 alt: false,
                 ..
-            } if ctx.editor.config.tab_size > 0 => {
-                let extender = if ctx.editor.config.indent_with_tabs {
+            } if config.tab_size > 0 => {
+                if read_only {
+                    return log_read_only_error(&mut ctx.editor, keys);
+                }
+                let buffer_view = ctx.editor.buffer_views.get(handle);
+                let buffer = ctx.editor.buffers.get_mut(buffer_view.buffer_handle);
+
+                let extender = if config.indent_with_tabs {
                     let count = state.count.max(1) as _;
                     std::iter::repeat('\t').take(count)
                 } else {
-                    let tab_size = ctx.editor.config.tab_size as usize;
+                    let tab_size = config.tab_size as usize;
                     let count = state.count.max(1) as usize * tab_size;
                     std::iter::repeat(' ').take(count)
                 };
 
-                let buffer_view = ctx.editor.buffer_views.get(handle);
-                let buffer = ctx.editor.buffers.get_mut(buffer_view.buffer_handle);
-
                 let mut buf = ctx.editor.string_pool.acquire();
                 buf.extend(extender);
 
@@ -1331,14 +1448,18 @@ impl State {
                 control: false,
                 alt: false,
                 ..
-            } if ctx.editor.config.tab_size > 0 => {
+            } if config.tab_size > 0 => {
+                if read_only {
+                    return log_read_only_error(&mut ctx.editor, keys);
+                }
                 let buffer_view = ctx.editor.buffer_views.get(handle);
                 let indentation_config = BufferIndentationConfig {
-                    indent_with_tabs: ctx.editor.config.indent_with_tabs,
-                    tab_size: ctx.editor.config.tab_size,
+                    indent_with_tabs: config.indent_with_tabs,
+                    tab_size: config.tab_size,
                 };
                 buffer_view.fix_indentation_in_cursor_ranges(
                     indentation_config,
+                    &ctx.editor.syntaxes,
                     &mut ctx.editor.buffers,
                     ctx.editor.events.writer(),
                 );
@@ -1696,6 +1817,9 @@ impl State {
                 alt: false,
                 ..
             } => {
+                if read_only {
+                    return log_read_only_error(&mut ctx.editor, keys);
+                }
                 let mut text = ctx.editor.string_pool.acquire();
                 ctx.platform.read_from_clipboard(&mut text);
                 paste_text(ctx, handle, &text);
@@ -1732,6 +1856,9 @@ impl State {
                             ctx.editor.string_pool.release(text);
                         }
                     } else {
+                        if read_only {
+                            return log_read_only_error(&mut ctx.editor, keys);
+                        }
                         if let Some(key) = RegisterKey::from_char(key) {
                             let register = ctx.editor.registers.get(key);
                             let text = ctx.editor.string_pool.acquire_with(register);
@@ -1749,6 +1876,9 @@ impl State {
                 alt: false,
                 ..
             } => {
+                if read_only {
+                    return log_read_only_error(&mut ctx.editor, keys);
+                }
                 let buffer_view = ctx.editor.buffer_views.get_mut(handle);
                 buffer_view.undo(
                     &mut ctx.editor.buffers,
@@ -1764,6 +1894,9 @@ impl State {
                 alt: false,
                 ..
             } => {
+                if read_only {
+                    return log_read_only_error(&mut ctx.editor, keys);
+                }
                 let buffer_view = ctx.editor.buffer_views.get_mut(handle);
                 buffer_view.redo(
                     &mut ctx.editor.buffers,
@@ -2244,6 +2377,16 @@ fn paste_text(ctx: &mut EditorContext, buffer_view_handle: BufferViewHandle, tex
         .commit_edits();
 }
 
+// also skips the remaining keys so mappings like `o` stop right away
+fn log_read_only_error(editor: &mut Editor, keys: &mut KeysIterator) -> Option<EditorFlow> {
+    editor
+        .logger
+        .write(LogKind::Error)
+        .str("buffer is read-only");
+    keys.index = editor.buffered_keys.as_slice().len();
+    Some(EditorFlow::Continue)
+}
+
 fn find_char(ctx: &mut EditorContext, client_handle: ClientHandle, forward: bool) {
     let state = &ctx.editor.mode.normal_state;
     let skip;
@@ -2311,16 +2454,23 @@ where
         Some(handle) => handle,
         None => return,
     };
+    let pattern_dialect = ctx.editor.buffer_view_config(Some(handle)).pattern_dialect;
     let buffer_view = ctx.editor.buffer_views.get_mut(handle);
     let buffer = ctx.editor.buffers.get_mut(buffer_view.buffer_handle);
 
+    // large files are only searched around the cursor so they need to be searched again
     let mut search_ranges = buffer.search_ranges();
-    if search_ranges.is_empty() {
+    if search_ranges.is_empty() || buffer.is_large_file() {
         let search = ctx.editor.registers.get(REGISTER_SEARCH);
         if !search.is_empty() {
-            match ctx.editor.aux_pattern.compile_searcher(search) {
+            match ctx
+                .editor
+                .aux_pattern
+                .compile_searcher(search, pattern_dialect)
+            {
                 Ok(()) => {
-                    buffer.set_search(&ctx.editor.aux_pattern);
+                    let main_position = buffer_view.cursors.main_cursor().position;
+                    buffer.set_search(&ctx.editor.aux_pattern, main_position);
                     search_ranges = buffer.search_ranges();
                 }
                 Err(error) => {
@@ -2366,6 +2516,7 @@ fn search_word_or_move_to_it(
         Some(handle) => handle,
         None => return,
     };
+    let pattern_dialect = ctx.editor.buffer_view_config(Some(handle)).pattern_dialect;
     let buffer_view = ctx.editor.buffer_views.get_mut(handle);
     let buffer = ctx.editor.buffers.get_mut(buffer_view.buffer_handle);
 
@@ -2411,8 +2562,11 @@ fn search_word_or_move_to_it(
             register.push_str("%b");
         }
 
-        let _ = ctx.editor.aux_pattern.compile_searcher(register);
-        buffer.set_search(&ctx.editor.aux_pattern);
+        let _ = ctx
+            .editor
+            .aux_pattern
+            .compile_searcher(register, pattern_dialect);
+        buffer.set_search(&ctx.editor.aux_pattern, position);
     } else {
         NavigationHistory::save_snapshot(
             ctx.clients.get_mut(client_handle),
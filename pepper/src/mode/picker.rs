@@ -46,6 +46,11 @@ impl ModeState for State {
         client_handle: ClientHandle,
         keys: &mut KeysIterator,
     ) -> Option<EditorFlow> {
+        let buffer_view_handle = ctx.clients.get(client_handle).buffer_view_handle();
+        let picker_max_height = ctx
+            .editor
+            .buffer_view_config(buffer_view_handle)
+            .picker_max_height;
         let this = &mut ctx.editor.mode.picker_state;
         let poll = readline_poll(
             ctx.editor.registers.get_mut(REGISTER_READLINE_INPUT),
@@ -97,12 +102,8 @@ impl ModeState for State {
                     control: false,
                     alt: false,
                 } => {
-                    let picker_height = ctx
-                        .editor
-                        .picker
-                        .len()
-                        .min(ctx.editor.config.picker_max_height as _)
-                        as isize;
+                    let picker_height =
+                        ctx.editor.picker.len().min(picker_max_height as _) as isize;
                     ctx.editor.picker.move_cursor(picker_height / 2);
                 }
                 Key {
@@ -117,12 +118,8 @@ impl ModeState for State {
                     control: false,
                     alt: false,
                 } => {
-                    let picker_height = ctx
-                        .editor
-                        .picker
-                        .len()
-                        .min(ctx.editor.config.picker_max_height as _)
-                        as isize;
+                    let picker_height =
+                        ctx.editor.picker.len().min(picker_max_height as _) as isize;
                     ctx.editor.picker.move_cursor(-picker_height / 2);
                 }
                 Key {
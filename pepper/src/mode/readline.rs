@@ -164,14 +164,16 @@ pub mod search {
             Some(handle) => handle,
             None => return,
         };
+        let pattern_dialect = ctx.editor.buffer_view_config(Some(handle)).pattern_dialect;
         let buffer_view = ctx.editor.buffer_views.get_mut(handle);
         let buffer = ctx.editor.buffers.get_mut(buffer_view.buffer_handle);
 
-        let _ = ctx
-            .editor
-            .aux_pattern
-            .compile_searcher(ctx.editor.registers.get(REGISTER_READLINE_INPUT));
-        buffer.set_search(&ctx.editor.aux_pattern);
+        let _ = ctx.editor.aux_pattern.compile_searcher(
+            ctx.editor.registers.get(REGISTER_READLINE_INPUT),
+            pattern_dialect,
+        );
+        let main_position = buffer_view.cursors.main_cursor().position;
+        buffer.set_search(&ctx.editor.aux_pattern, main_position);
         let search_ranges = buffer.search_ranges();
 
         if search_ranges.is_empty() {
@@ -295,6 +297,11 @@ pub mod filter_cursors {
             }
         }
 
+        let buffer_view_handle = ctx.clients.get(client_handle).buffer_view_handle();
+        let pattern_dialect = ctx
+            .editor
+            .buffer_view_config(buffer_view_handle)
+            .pattern_dialect;
         let pattern = ctx.editor.registers.get(REGISTER_READLINE_INPUT);
         let pattern = if pattern.is_empty() {
             ctx.editor.registers.get(REGISTER_SEARCH)
@@ -302,7 +309,11 @@ pub mod filter_cursors {
             pattern
         };
 
-        if let Err(error) = ctx.editor.aux_pattern.compile_searcher(pattern) {
+        if let Err(error) = ctx
+            .editor
+            .aux_pattern
+            .compile_searcher(pattern, pattern_dialect)
+        {
             ctx.editor
                 .logger
                 .write(LogKind::Error)
@@ -436,6 +447,11 @@ pub mod split_cursors {
         client_handle: ClientHandle,
         add_matches: fn(&mut CursorCollectionMutGuard, &str, &Pattern, BufferPosition),
     ) {
+        let buffer_view_handle = ctx.clients.get(client_handle).buffer_view_handle();
+        let pattern_dialect = ctx
+            .editor
+            .buffer_view_config(buffer_view_handle)
+            .pattern_dialect;
         let pattern = ctx.editor.registers.get(REGISTER_READLINE_INPUT);
         let pattern = if pattern.is_empty() {
             ctx.editor.registers.get(REGISTER_SEARCH)
@@ -443,7 +459,11 @@ pub mod split_cursors {
             pattern
         };
 
-        if let Err(error) = ctx.editor.aux_pattern.compile_searcher(pattern) {
+        if let Err(error) = ctx
+            .editor
+            .aux_pattern
+            .compile_searcher(pattern, pattern_dialect)
+        {
             ctx.editor
                 .logger
                 .write(LogKind::Error)
@@ -1,4 +1,9 @@
-use std::{fmt, num::TryFromIntError, ops::Range, str::Chars};
+use std::{
+    fmt,
+    num::TryFromIntError,
+    ops::Range,
+    str::{Chars, FromStr},
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MatchResult {
@@ -16,6 +21,10 @@ pub enum PatternError {
     EmptyGroup,
     GroupWithElementsOfDifferentSize,
     PatternTooLong,
+    InvalidRegexEscaping(char),
+    InvalidRange(char, char),
+    InvalidRepetition(u16, u16),
+    NestingTooDeep,
 }
 impl fmt::Display for PatternError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -29,6 +38,12 @@ impl fmt::Display for PatternError {
                 write!(f, "pattern group has elements of different size")
             }
             Self::PatternTooLong => write!(f, "pattern is too long"),
+            Self::InvalidRegexEscaping(c) => write!(f, "invalid escaping '\\{}'", c),
+            Self::InvalidRange(from, to) => write!(f, "invalid range '{}-{}'", from, to),
+            Self::InvalidRepetition(min, max) => {
+                write!(f, "invalid repetition '{{{},{}}}'", min, max)
+            }
+            Self::NestingTooDeep => write!(f, "pattern nesting is too deep"),
         }
     }
 }
@@ -38,6 +53,30 @@ impl From<TryFromIntError> for PatternError {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PatternDialect {
+    Pepper,
+    Regex,
+}
+impl FromStr for PatternDialect {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pepper" => Ok(Self::Pepper),
+            "regex" => Ok(Self::Regex),
+            _ => Err(()),
+        }
+    }
+}
+impl fmt::Display for PatternDialect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Pepper => f.write_str("pepper"),
+            Self::Regex => f.write_str("regex"),
+        }
+    }
+}
+
 pub struct PatternEscaper<'a> {
     chars: Chars<'a>,
     pending_char: Option<char>,
@@ -100,6 +139,78 @@ impl<'pattern, 'text> Iterator for MatchIndices<'pattern, 'text> {
     }
 }
 
+// capture 0 is the whole match and the others are the `(...)` sequences in the order they open
+pub const PATTERN_CAPTURE_COUNT: usize = 10;
+
+#[derive(Clone)]
+pub struct PatternCaptures {
+    starts: [usize; PATTERN_CAPTURE_COUNT],
+    ranges: [Option<(usize, usize)>; PATTERN_CAPTURE_COUNT],
+}
+impl PatternCaptures {
+    pub fn new() -> Self {
+        Self {
+            starts: [0; PATTERN_CAPTURE_COUNT],
+            ranges: [None; PATTERN_CAPTURE_COUNT],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.ranges = [None; PATTERN_CAPTURE_COUNT];
+    }
+
+    fn start(&mut self, index: u8, text_index: usize) {
+        self.starts[index as usize] = text_index;
+    }
+
+    fn end(&mut self, index: u8, text_index: usize) {
+        let index = index as usize;
+        self.ranges[index] = Some((self.starts[index], text_index));
+    }
+
+    fn backtrack(&self, index: u8) -> Backtrack {
+        let i = index as usize;
+        Backtrack::Capture(index, self.starts[i], self.ranges[i])
+    }
+
+    pub fn get(&self, index: usize) -> Option<Range<usize>> {
+        let (start, end) = (*self.ranges.get(index)?)?;
+        Some(start..end)
+    }
+
+    // `%0` to `%9` expand to the captured text and `%%` to `%`
+    pub fn expand_template(
+        &self,
+        text: &str,
+        template: &str,
+        output: &mut String,
+    ) -> Result<(), PatternError> {
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                output.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('%') => output.push('%'),
+                Some(c @ '0'..='9') => {
+                    if let Some(range) = self.get(c as usize - '0' as usize) {
+                        output.push_str(&text[range]);
+                    }
+                }
+                Some(c) => return Err(PatternError::InvalidEscaping(c)),
+                None => return Err(PatternError::UnexpectedEndOfPattern),
+            }
+        }
+        Ok(())
+    }
+}
+impl Default for PatternCaptures {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PatternState {
     op_jump: Jump,
@@ -139,7 +250,43 @@ impl Pattern {
     }
 
     pub fn compile(&mut self, pattern: &str) -> Result<(), PatternError> {
-        match PatternCompiler::new(&mut self.ops, pattern).compile() {
+        self.compile_with(pattern, PatternDialect::Pepper, false)
+    }
+
+    pub fn compile_regex(&mut self, pattern: &str) -> Result<(), PatternError> {
+        self.compile_with(pattern, PatternDialect::Regex, false)
+    }
+
+    pub fn compile_dialect(
+        &mut self,
+        pattern: &str,
+        dialect: PatternDialect,
+    ) -> Result<(), PatternError> {
+        self.compile_with(pattern, dialect, false)
+    }
+
+    // only patterns compiled this way record what their `(...)` sequences matched
+    pub fn compile_with_captures(
+        &mut self,
+        pattern: &str,
+        dialect: PatternDialect,
+    ) -> Result<(), PatternError> {
+        self.compile_with(pattern, dialect, true)
+    }
+
+    fn compile_with(
+        &mut self,
+        pattern: &str,
+        dialect: PatternDialect,
+        captures: bool,
+    ) -> Result<(), PatternError> {
+        let mut compiler = PatternCompiler::new(&mut self.ops, pattern);
+        compiler.captures = captures;
+        let result = match dialect {
+            PatternDialect::Pepper => compiler.compile(),
+            PatternDialect::Regex => compiler.compile_regex(),
+        };
+        match result {
             Ok(start_jump) => {
                 self.start_jump = start_jump;
                 Ok(())
@@ -151,15 +298,33 @@ impl Pattern {
         }
     }
 
-    pub fn compile_searcher(&mut self, pattern: &str) -> Result<(), PatternError> {
-        let (is_literal, ignore_case, pattern) = match pattern.as_bytes() {
-            [b'f', b'/', ..] => (true, true, &pattern[2..]),
-            [b'F', b'/', ..] => (true, false, &pattern[2..]),
-            [b'p', b'/', ..] => (false, true, &pattern[2..]),
-            [b'P', b'/', ..] => (false, false, &pattern[2..]),
+    // `dialect` is only used when the pattern has no `p/` or `r/` prefix
+    pub fn compile_searcher(
+        &mut self,
+        pattern: &str,
+        dialect: PatternDialect,
+    ) -> Result<(), PatternError> {
+        let (is_literal, ignore_case, dialect, pattern) = match pattern.as_bytes() {
+            [b'f', b'/', ..] => (true, true, dialect, &pattern[2..]),
+            [b'F', b'/', ..] => (true, false, dialect, &pattern[2..]),
+            [b'p', b'/', ..] => (false, true, PatternDialect::Pepper, &pattern[2..]),
+            [b'P', b'/', ..] => (false, false, PatternDialect::Pepper, &pattern[2..]),
+            [b'r', b'/', ..] => (false, true, PatternDialect::Regex, &pattern[2..]),
+            [b'R', b'/', ..] => (false, false, PatternDialect::Regex, &pattern[2..]),
             _ => (
-                !pattern.chars().any(|c| matches!(c, '%' | '^' | '$')),
+                match dialect {
+                    PatternDialect::Pepper => {
+                        !pattern.chars().any(|c| matches!(c, '%' | '^' | '$'))
+                    }
+                    PatternDialect::Regex => !pattern.chars().any(|c| {
+                        matches!(
+                            c,
+                            '\\' | '^' | '$' | '.' | '*' | '+' | '?' | '(' | '[' | '{' | '|'
+                        )
+                    }),
+                },
                 !pattern.chars().any(|c| c.is_ascii_uppercase()),
+                dialect,
                 pattern,
             ),
         };
@@ -193,7 +358,7 @@ impl Pattern {
             self.ops.push(Op::Ok);
             self.start_jump = Jump(1);
         } else {
-            self.compile(pattern)?;
+            self.compile_dialect(pattern, dialect)?;
         }
 
         if ignore_case {
@@ -207,6 +372,7 @@ impl Pattern {
         for op in &mut self.ops {
             match *op {
                 Op::Char(okj, erj, c) => *op = Op::CharCaseInsensitive(okj, erj, c),
+                Op::Range(okj, erj, from, to) => *op = Op::RangeCaseInsensitive(okj, erj, from, to),
                 Op::String(okj, erj, len, bytes) => {
                     *op = Op::StringCaseInsensitive(okj, erj, len, bytes)
                 }
@@ -222,7 +388,11 @@ impl Pattern {
 
     pub fn search_anchor(&self) -> Option<char> {
         let ops = OpsSlice(&self.ops);
-        let (c, erj) = match ops.at(self.start_jump) {
+        let mut start_jump = self.start_jump;
+        while let &Op::CaptureStart(jump, _) = ops.at(start_jump) {
+            start_jump = jump;
+        }
+        let (c, erj) = match ops.at(start_jump) {
             Op::Error => return Some('\0'),
             &Op::Char(_, erj, c) => (c, erj),
             &Op::String(_, erj, len, bytes) => {
@@ -263,14 +433,89 @@ impl Pattern {
     }
 
     pub fn matches_with_state(&self, text: &str, index: usize, state: PatternState) -> MatchResult {
+        self.run(text, index, state.op_jump, None)
+    }
+
+    pub fn matches_with_captures(
+        &self,
+        text: &str,
+        start_index: usize,
+        captures: &mut PatternCaptures,
+    ) -> MatchResult {
+        captures.clear();
+        let result = self.run(text, start_index, self.start_jump, Some(captures));
+        match result {
+            MatchResult::Ok(index) => captures.ranges[0] = Some((start_index, index)),
+            _ => captures.clear(),
+        }
+        result
+    }
+
+    fn run(
+        &self,
+        text: &str,
+        index: usize,
+        op_jump: Jump,
+        mut captures: Option<&mut PatternCaptures>,
+    ) -> MatchResult {
         let mut chars = text[index..].chars();
         let ops = OpsSlice(&self.ops);
-        let mut op_jump = state.op_jump;
+        let mut op_jump = op_jump;
+        let mut positions = [0; REGEX_POSITION_COUNT];
+        // only regex patterns push to it so other patterns never allocate
+        let mut backtracks = Vec::new();
 
         fn offset(text: &str, chars: &Chars) -> usize {
             chars.as_str().as_ptr() as usize - text.as_ptr() as usize
         }
 
+        // ops that only keep track of text positions
+        fn track_position<'text>(
+            op: &Op,
+            text: &'text str,
+            chars: &mut Chars<'text>,
+            positions: &mut [usize; REGEX_POSITION_COUNT],
+            backtracks: &mut Vec<Backtrack>,
+            captures: &mut Option<&mut PatternCaptures>,
+        ) -> Option<Jump> {
+            match *op {
+                Op::CaptureStart(jump, i) => {
+                    if let Some(captures) = captures {
+                        if !backtracks.is_empty() {
+                            backtracks.push(captures.backtrack(i));
+                        }
+                        captures.start(i, offset(text, chars));
+                    }
+                    Some(jump)
+                }
+                Op::CaptureEnd(jump, i) => {
+                    if let Some(captures) = captures {
+                        if !backtracks.is_empty() {
+                            backtracks.push(captures.backtrack(i));
+                        }
+                        captures.end(i, offset(text, chars));
+                    }
+                    Some(jump)
+                }
+                Op::SavePosition(jump, i) => {
+                    positions[i as usize] = offset(text, chars);
+                    Some(jump)
+                }
+                Op::Fork(jump, alternative_jump) => {
+                    backtracks.push(Backtrack::Jump(alternative_jump, offset(text, chars)));
+                    Some(jump)
+                }
+                Op::PositionAdvanced(okj, erj, i) => {
+                    if offset(text, chars) > positions[i as usize] {
+                        Some(okj)
+                    } else {
+                        Some(erj)
+                    }
+                }
+                _ => None,
+            }
+        }
+
         fn check_and_jump<F>(chars: &mut Chars, okj: Jump, erj: Jump, predicate: F) -> Jump
         where
             F: Fn(char) -> bool,
@@ -288,11 +533,47 @@ impl Pattern {
         loop {
             match ops.at(op_jump) {
                 Op::Ok => return MatchResult::Ok(offset(text, &chars)),
-                Op::Error => return MatchResult::Err,
+                // errors resume from the last alternative a regex left behind
+                Op::Error => loop {
+                    match backtracks.pop() {
+                        Some(Backtrack::Jump(jump, offset)) => {
+                            chars = text[offset..].chars();
+                            op_jump = jump;
+                            break;
+                        }
+                        Some(Backtrack::Capture(i, start, range)) => {
+                            if let Some(captures) = &mut captures {
+                                captures.starts[i as usize] = start;
+                                captures.ranges[i as usize] = range;
+                            }
+                        }
+                        None => return MatchResult::Err,
+                    }
+                },
                 &Op::Reset(jump) => {
                     chars = text[index..].chars();
+                    if let Some(captures) = &mut captures {
+                        captures.clear();
+                    }
                     op_jump = jump;
                 }
+                op @ (Op::CaptureStart(..)
+                | Op::CaptureEnd(..)
+                | Op::SavePosition(..)
+                | Op::Fork(..)
+                | Op::PositionAdvanced(..)) => {
+                    match track_position(
+                        op,
+                        text,
+                        &mut chars,
+                        &mut positions,
+                        &mut backtracks,
+                        &mut captures,
+                    ) {
+                        Some(jump) => op_jump = jump,
+                        None => unreachable!(),
+                    }
+                }
                 &Op::Unwind(jump, len) => {
                     let len = (len.0 - 1) as _;
                     let offset = offset(text, &chars);
@@ -311,10 +592,24 @@ impl Pattern {
                 &Op::EndingAnchor(okj, erj) => {
                     if chars.as_str().is_empty() {
                         op_jump = okj;
-                        return match ops.at(op_jump) {
-                            Op::Ok => MatchResult::Ok(offset(text, &chars)),
-                            _ => MatchResult::Pending(PatternState { op_jump }),
-                        };
+                        // ops that only track positions do not keep the match pending
+                        loop {
+                            let op = ops.at(op_jump);
+                            if let Op::Ok = op {
+                                return MatchResult::Ok(offset(text, &chars));
+                            }
+                            match track_position(
+                                op,
+                                text,
+                                &mut chars,
+                                &mut positions,
+                                &mut backtracks,
+                                &mut captures,
+                            ) {
+                                Some(jump) => op_jump = jump,
+                                None => return MatchResult::Pending(PatternState { op_jump }),
+                            }
+                        }
                     } else {
                         op_jump = erj;
                     }
@@ -364,6 +659,17 @@ impl Pattern {
                 &Op::CharCaseInsensitive(okj, erj, ch) => {
                     op_jump = check_and_jump(&mut chars, okj, erj, |c| c.eq_ignore_ascii_case(&ch))
                 }
+                &Op::Range(okj, erj, from, to) => {
+                    op_jump = check_and_jump(&mut chars, okj, erj, |c| from <= c && c <= to)
+                }
+                &Op::RangeCaseInsensitive(okj, erj, from, to) => {
+                    op_jump = check_and_jump(&mut chars, okj, erj, |c| {
+                        let range = from..=to;
+                        range.contains(&c)
+                            || range.contains(&c.to_ascii_lowercase())
+                            || range.contains(&c.to_ascii_uppercase())
+                    })
+                }
                 &Op::String(okj, erj, len, bytes) => {
                     let len = len as usize;
                     let bytes = &bytes[..len];
@@ -453,14 +759,30 @@ enum JumpFrom {
 }
 
 const OP_STRING_LEN: usize = 10;
+// regex repeated groups remember where each iteration started so they stop once one matches nothing
+const REGEX_POSITION_COUNT: usize = 32;
+const REGEX_MAX_GROUP_NESTING: usize = 32;
+// each optional repetition is emitted as its own copy of what is repeated
+const REGEX_MAX_OPTIONAL_REPETITIONS: u16 = 255;
 const _ASSERT_OP_SIZE: [(); 16] = [(); std::mem::size_of::<Op>()];
 
+// what to undo when a regex backtracks
+enum Backtrack {
+    Jump(Jump, usize),
+    Capture(u8, usize, Option<(usize, usize)>),
+}
+
 #[derive(Clone)]
 enum Op {
     Ok,
     Error,
     Reset(Jump),
     Unwind(Jump, Length),
+    CaptureStart(Jump, u8),
+    CaptureEnd(Jump, u8),
+    SavePosition(Jump, u8),
+    Fork(Jump, Jump),
+    PositionAdvanced(Jump, Jump, u8),
     BeginningAnchor(Jump, Jump),
     EndingAnchor(Jump, Jump),
     WordBoundary(Jump, Jump),
@@ -473,6 +795,8 @@ enum Op {
     Alphanumeric(Jump, Jump),
     Char(Jump, Jump, char),
     CharCaseInsensitive(Jump, Jump, char),
+    Range(Jump, Jump, char, char),
+    RangeCaseInsensitive(Jump, Jump, char, char),
     String(Jump, Jump, u8, [u8; OP_STRING_LEN]),
     StringCaseInsensitive(Jump, Jump, u8, [u8; OP_STRING_LEN]),
 }
@@ -497,6 +821,40 @@ impl fmt::Debug for Op {
                 jump.0,
                 width = WIDTH - 4
             ),
+            Op::CaptureStart(jump, i) => write!(
+                f,
+                "{:width$}[{}] {}",
+                "CaptureStart",
+                i,
+                jump.0,
+                width = WIDTH - 4
+            ),
+            Op::CaptureEnd(jump, i) => write!(
+                f,
+                "{:width$}[{}] {}",
+                "CaptureEnd",
+                i,
+                jump.0,
+                width = WIDTH - 4
+            ),
+            Op::SavePosition(jump, i) => write!(
+                f,
+                "{:width$}[{}] {}",
+                "SavePosition",
+                i,
+                jump.0,
+                width = WIDTH - 4
+            ),
+            &Op::Fork(jump, alternative_jump) => p(f, "Fork", jump, alternative_jump),
+            &Op::PositionAdvanced(okj, erj, i) => write!(
+                f,
+                "{:width$}[{}] {} {}",
+                "PositionAdvanced",
+                i,
+                okj.0,
+                erj.0,
+                width = WIDTH - 4
+            ),
             &Op::BeginningAnchor(okj, erj) => p(f, "BeginningAnchor", okj, erj),
             &Op::EndingAnchor(okj, erj) => p(f, "EndAnchor", okj, erj),
             &Op::WordBoundary(okj, erj) => p(f, "WordBoundary", okj, erj),
@@ -533,6 +891,26 @@ impl fmt::Debug for Op {
                 erj.0,
                 width = WIDTH - 4
             ),
+            &Op::Range(okj, erj, from, to) => write!(
+                f,
+                "{:width$}'{}-{}' {} {}",
+                "Range",
+                from,
+                to,
+                okj.0,
+                erj.0,
+                width = WIDTH - 4
+            ),
+            &Op::RangeCaseInsensitive(okj, erj, from, to) => write!(
+                f,
+                "{:width$}'{}-{}' {} {}",
+                "RangeCaseInsensitive",
+                from,
+                to,
+                okj.0,
+                erj.0,
+                width = WIDTH - 4
+            ),
             &Op::String(okj, erj, len, bytes) => write!(
                 f,
                 "{:width$}'{}' {} {}",
@@ -555,11 +933,47 @@ impl fmt::Debug for Op {
     }
 }
 
+#[derive(Clone)]
+enum RegexSetItem {
+    Char(char),
+    Range(char, char),
+    Digit,
+    Alphanumeric,
+}
+
+#[derive(Clone)]
+enum RegexNode {
+    Char(char),
+    Any,
+    Set {
+        negated: bool,
+        items: Vec<RegexSetItem>,
+    },
+    BeginningAnchor,
+    EndingAnchor,
+    WordBoundary,
+    Group {
+        capture_index: Option<u8>,
+        alternatives: Vec<Vec<RegexNode>>,
+    },
+    // only for nodes that match a single char
+    Repeat {
+        node: Box<RegexNode>,
+        min: u16,
+        max: Option<u16>,
+    },
+    GroupLoop(Box<RegexNode>),
+    OptionalGroups(Box<RegexNode>, u16),
+}
+
 struct PatternCompiler<'a> {
     pub text: Chars<'a>,
     pub current_char: char,
     pub start_jump: Jump,
+    pub captures: bool,
+    pub capture_count: u8,
     pub ops: &'a mut Vec<Op>,
+    pub labels: Vec<Jump>,
 }
 
 impl<'a> PatternCompiler<'a> {
@@ -569,7 +983,10 @@ impl<'a> PatternCompiler<'a> {
             text: text.chars(),
             current_char: '\0',
             start_jump: Jump(2),
+            captures: false,
+            capture_count: 0,
             ops,
+            labels: Vec::new(),
         }
     }
 
@@ -649,6 +1066,9 @@ impl<'a> PatternCompiler<'a> {
     fn parse_stmt(&mut self, erj: JumpFrom) -> Result<(), PatternError> {
         match self.current_char {
             '{' => self.parse_repeat_stmt(erj),
+            '(' if self.captures && self.text.clone().next() != Some('!') => {
+                self.parse_capture_stmt(erj)
+            }
             _ => match self.parse_expr(JumpFrom::End(Jump(0)), erj) {
                 Ok(_) => Ok(()),
                 Err(e) => Err(e),
@@ -739,6 +1159,41 @@ impl<'a> PatternCompiler<'a> {
         Ok(())
     }
 
+    // sequences that are statements can contain other statements (like repeats) since on error
+    // the whole subpattern is reset
+    fn parse_capture_stmt(&mut self, erj: JumpFrom) -> Result<(), PatternError> {
+        let capture_index = self.begin_capture()?;
+        while !self.next_is(')')? {
+            self.parse_stmt(erj)?;
+        }
+        self.end_capture(capture_index)?;
+
+        self.assert_current(')')?;
+        Ok(())
+    }
+
+    // sequences past the last capture index still match but are not captured
+    fn begin_capture(&mut self) -> Result<Option<u8>, PatternError> {
+        let capture_index = self.capture_count as usize + 1;
+        if self.captures && capture_index < PATTERN_CAPTURE_COUNT {
+            self.capture_count += 1;
+            let jump = Jump((self.ops.len() + 1).try_into()?);
+            self.ops.push(Op::CaptureStart(jump, capture_index as _));
+            Ok(Some(capture_index as _))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // a capture is only updated once its whole sequence matches
+    fn end_capture(&mut self, capture_index: Option<u8>) -> Result<(), PatternError> {
+        if let Some(capture_index) = capture_index {
+            let jump = Jump((self.ops.len() + 1).try_into()?);
+            self.ops.push(Op::CaptureEnd(jump, capture_index));
+        }
+        Ok(())
+    }
+
     fn parse_sequence_expr(
         &mut self,
         okj: JumpFrom,
@@ -765,11 +1220,13 @@ impl<'a> PatternCompiler<'a> {
         } else {
             self.text = previous_state;
             let abs_erj = self.get_absolute_jump(erj)?;
+            let capture_index = self.begin_capture()?;
             while !self.next_is(')')? {
                 let expr_len = self.parse_expr(JumpFrom::End(Jump(1)), JumpFrom::End(Jump(0)))?;
                 self.ops.push(Op::Unwind(abs_erj, len));
                 len.add(expr_len)?;
             }
+            self.end_capture(capture_index)?;
             self.jump_at_end(okj)?;
             self.patch_unwind_jump(erj, abs_erj)?;
         }
@@ -891,6 +1348,536 @@ impl<'a> PatternCompiler<'a> {
         Ok(Length(1))
     }
 
+    pub fn compile_regex(mut self) -> Result<Jump, PatternError> {
+        self.ops.push(Op::Error);
+        self.ops.push(Op::Ok);
+        self.labels.push(Jump(0));
+        self.labels.push(Jump(1));
+
+        let alternatives = self.parse_regex_alternatives(0)?;
+        if let Some(c) = self.text.next() {
+            return Err(PatternError::Unescaped(c));
+        }
+        let root = RegexNode::Group {
+            capture_index: None,
+            alternatives,
+        };
+
+        let start_jump = self.regex_label_at(self.ops.len())?;
+        self.emit_regex(&root, 0)?;
+        self.ops.push(Op::Unwind(Jump(1), Length(0)));
+
+        let labels = &self.labels;
+        let resolve = |jump: &mut Jump| *jump = labels[jump.0 as usize];
+        self.start_jump = labels[start_jump.0 as usize];
+        for op in self.ops.iter_mut() {
+            match op {
+                Op::Ok | Op::Error => (),
+                Op::Reset(j)
+                | Op::Unwind(j, _)
+                | Op::CaptureStart(j, _)
+                | Op::CaptureEnd(j, _)
+                | Op::SavePosition(j, _) => resolve(j),
+                Op::Fork(okj, erj)
+                | Op::PositionAdvanced(okj, erj, _)
+                | Op::BeginningAnchor(okj, erj)
+                | Op::EndingAnchor(okj, erj)
+                | Op::WordBoundary(okj, erj)
+                | Op::SkipOne(okj, erj)
+                | Op::SkipMany(okj, erj, _)
+                | Op::Alphabetic(okj, erj)
+                | Op::Lower(okj, erj)
+                | Op::Upper(okj, erj)
+                | Op::Digit(okj, erj)
+                | Op::Alphanumeric(okj, erj)
+                | Op::Char(okj, erj, _)
+                | Op::CharCaseInsensitive(okj, erj, _)
+                | Op::Range(okj, erj, _, _)
+                | Op::RangeCaseInsensitive(okj, erj, _, _)
+                | Op::String(okj, erj, _, _)
+                | Op::StringCaseInsensitive(okj, erj, _, _) => {
+                    resolve(okj);
+                    resolve(erj);
+                }
+            }
+        }
+
+        // jumps may land in the middle of char sequences so only plain jumps are removed
+        let mut i = 0;
+        while i < self.ops.len() {
+            match self.ops[i] {
+                Op::Unwind(jump, Length(0)) => self.remove_jump_at(i, jump),
+                _ => i += 1,
+            }
+        }
+
+        Ok(self.start_jump)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text.clone().next()
+    }
+
+    fn parse_regex_alternatives(
+        &mut self,
+        depth: usize,
+    ) -> Result<Vec<Vec<RegexNode>>, PatternError> {
+        let mut alternatives = Vec::new();
+        loop {
+            alternatives.push(self.parse_regex_sequence(depth)?);
+            match self.peek() {
+                Some('|') => {
+                    self.next()?;
+                }
+                _ => break Ok(alternatives),
+            }
+        }
+    }
+
+    fn parse_regex_sequence(&mut self, depth: usize) -> Result<Vec<RegexNode>, PatternError> {
+        let mut nodes = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some('|') | Some(')') => break Ok(nodes),
+                Some(_) => (),
+            }
+
+            let node = self.parse_regex_atom(depth)?;
+            let (min, max) = match self.peek() {
+                Some('*') => {
+                    self.next()?;
+                    (0, None)
+                }
+                Some('+') => {
+                    self.next()?;
+                    (1, None)
+                }
+                Some('?') => {
+                    self.next()?;
+                    (0, Some(1))
+                }
+                Some('{') => match self.parse_regex_repetition()? {
+                    Some(repetition) => repetition,
+                    None => {
+                        nodes.push(node);
+                        continue;
+                    }
+                },
+                _ => {
+                    nodes.push(node);
+                    continue;
+                }
+            };
+            if let Some(c @ ('*' | '+' | '?')) = self.peek() {
+                return Err(PatternError::Unescaped(c));
+            }
+
+            Self::push_regex_repeat(&mut nodes, node, min, max);
+        }
+    }
+
+    // `{n}`, `{n,}` and `{n,m}`. anything else is not a repetition and `{` is then matched literally
+    fn parse_regex_repetition(&mut self) -> Result<Option<(u16, Option<u16>)>, PatternError> {
+        fn parse_number(chars: &mut Chars) -> Result<Option<u16>, PatternError> {
+            let digits = chars.as_str();
+            let len = digits.bytes().take_while(u8::is_ascii_digit).count();
+            if len == 0 {
+                return Ok(None);
+            }
+            *chars = digits[len..].chars();
+            match digits[..len].parse() {
+                Ok(number) => Ok(Some(number)),
+                Err(_) => Err(PatternError::PatternTooLong),
+            }
+        }
+
+        let mut chars = self.text.clone();
+        chars.next();
+        let min = match parse_number(&mut chars)? {
+            Some(min) => min,
+            None => return Ok(None),
+        };
+        let max = match chars.next() {
+            Some('}') => Some(min),
+            Some(',') => {
+                let max = parse_number(&mut chars)?;
+                if chars.next() != Some('}') {
+                    return Ok(None);
+                }
+                max
+            }
+            _ => return Ok(None),
+        };
+
+        if let Some(max) = max {
+            if max < min || max == 0 || max - min > REGEX_MAX_OPTIONAL_REPETITIONS {
+                return Err(PatternError::InvalidRepetition(min, max));
+            }
+        }
+
+        self.text = chars;
+        self.current_char = '}';
+        Ok(Some((min, max)))
+    }
+
+    fn push_regex_repeat(nodes: &mut Vec<RegexNode>, node: RegexNode, min: u16, max: Option<u16>) {
+        match node {
+            RegexNode::BeginningAnchor | RegexNode::EndingAnchor | RegexNode::WordBoundary => {
+                if min > 0 {
+                    nodes.push(node);
+                }
+            }
+            RegexNode::Group { .. } => {
+                for _ in 0..min {
+                    nodes.push(node.clone());
+                }
+                match max {
+                    None => nodes.push(RegexNode::GroupLoop(Box::new(node))),
+                    Some(max) if max > min => {
+                        nodes.push(RegexNode::OptionalGroups(Box::new(node), max - min))
+                    }
+                    Some(_) => (),
+                }
+            }
+            _ => nodes.push(RegexNode::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+            }),
+        }
+    }
+
+    fn parse_regex_atom(&mut self, depth: usize) -> Result<RegexNode, PatternError> {
+        let node = match self.next()? {
+            '(' => {
+                if depth >= REGEX_MAX_GROUP_NESTING {
+                    return Err(PatternError::NestingTooDeep);
+                }
+                let capture_index = if self.peek() == Some('?') {
+                    self.next()?;
+                    if !self.next_is(':')? {
+                        return Err(PatternError::Expected(':'));
+                    }
+                    None
+                } else if self.captures && (self.capture_count as usize + 1) < PATTERN_CAPTURE_COUNT
+                {
+                    self.capture_count += 1;
+                    Some(self.capture_count)
+                } else {
+                    None
+                };
+                let alternatives = self.parse_regex_alternatives(depth + 1)?;
+                self.next()?;
+                self.assert_current(')')?;
+                RegexNode::Group {
+                    capture_index,
+                    alternatives,
+                }
+            }
+            '[' => self.parse_regex_set()?,
+            '.' => RegexNode::Any,
+            '^' => RegexNode::BeginningAnchor,
+            '$' => RegexNode::EndingAnchor,
+            c @ ('*' | '+' | '?') => return Err(PatternError::Unescaped(c)),
+            '\\' => match self.next()? {
+                'd' => RegexNode::Set {
+                    negated: false,
+                    items: vec![RegexSetItem::Digit],
+                },
+                'D' => RegexNode::Set {
+                    negated: true,
+                    items: vec![RegexSetItem::Digit],
+                },
+                'w' => RegexNode::Set {
+                    negated: false,
+                    items: Self::regex_word_items(),
+                },
+                'W' => RegexNode::Set {
+                    negated: true,
+                    items: Self::regex_word_items(),
+                },
+                's' => RegexNode::Set {
+                    negated: false,
+                    items: Self::regex_space_items(),
+                },
+                'S' => RegexNode::Set {
+                    negated: true,
+                    items: Self::regex_space_items(),
+                },
+                'b' => RegexNode::WordBoundary,
+                c => RegexNode::Char(Self::regex_escaped_char(c)?),
+            },
+            c => RegexNode::Char(c),
+        };
+        Ok(node)
+    }
+
+    fn parse_regex_set(&mut self) -> Result<RegexNode, PatternError> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.next()?;
+        }
+
+        let mut items = Vec::new();
+        let mut is_first = true;
+        loop {
+            let c = self.next()?;
+            if c == ']' && !is_first {
+                break;
+            }
+            is_first = false;
+
+            let from = match c {
+                '\\' => match self.next()? {
+                    'd' => {
+                        items.push(RegexSetItem::Digit);
+                        continue;
+                    }
+                    'w' => {
+                        items.append(&mut Self::regex_word_items());
+                        continue;
+                    }
+                    's' => {
+                        items.append(&mut Self::regex_space_items());
+                        continue;
+                    }
+                    c => Self::regex_escaped_char(c)?,
+                },
+                c => c,
+            };
+
+            let mut chars = self.text.clone();
+            if chars.next() == Some('-') && !matches!(chars.next(), None | Some(']')) {
+                self.next()?;
+                let to = match self.next()? {
+                    '\\' => Self::regex_escaped_char(self.next()?)?,
+                    c => c,
+                };
+                if to < from {
+                    return Err(PatternError::InvalidRange(from, to));
+                }
+                items.push(RegexSetItem::Range(from, to));
+            } else {
+                items.push(RegexSetItem::Char(from));
+            }
+        }
+
+        Ok(RegexNode::Set { negated, items })
+    }
+
+    fn regex_escaped_char(c: char) -> Result<char, PatternError> {
+        match c {
+            't' => Ok('\t'),
+            'n' => Ok('\n'),
+            'r' => Ok('\r'),
+            c if c.is_ascii_punctuation() => Ok(c),
+            c => Err(PatternError::InvalidRegexEscaping(c)),
+        }
+    }
+
+    fn regex_word_items() -> Vec<RegexSetItem> {
+        vec![RegexSetItem::Alphanumeric, RegexSetItem::Char('_')]
+    }
+
+    fn regex_space_items() -> Vec<RegexSetItem> {
+        [' ', '\t', '\n', '\r', '\x0b', '\x0c']
+            .iter()
+            .map(|&c| RegexSetItem::Char(c))
+            .collect()
+    }
+
+    fn regex_label_at(&mut self, op_index: usize) -> Result<Jump, PatternError> {
+        let label = Jump(self.labels.len().try_into()?);
+        self.labels.push(Jump(op_index.try_into()?));
+        Ok(label)
+    }
+
+    fn place_regex_label(&mut self, label: Jump) -> Result<(), PatternError> {
+        self.labels[label.0 as usize] = Jump(self.ops.len().try_into()?);
+        Ok(())
+    }
+
+    fn next_op_label(&mut self) -> Result<Jump, PatternError> {
+        self.regex_label_at(self.ops.len() + 1)
+    }
+
+    fn assert_regex_positions(position: u8, count: usize) -> Result<(), PatternError> {
+        if position as usize + count <= REGEX_POSITION_COUNT {
+            Ok(())
+        } else {
+            Err(PatternError::NestingTooDeep)
+        }
+    }
+
+    // jumps are label indices until they are resolved at the end of `compile_regex`.
+    // every node is emitted once and failing to match it jumps to `Op::Error` which then resumes
+    // from the last alternative pushed by an `Op::Fork`
+    fn emit_regex(&mut self, node: &RegexNode, position: u8) -> Result<(), PatternError> {
+        match node {
+            RegexNode::Char(_) | RegexNode::Any | RegexNode::Set { .. } => {
+                let okj = self.regex_label_at(0)?;
+                self.emit_regex_single_char(node, okj, Jump(0))?;
+                self.place_regex_label(okj)?;
+            }
+            RegexNode::BeginningAnchor => {
+                let okj = self.next_op_label()?;
+                self.ops.push(Op::BeginningAnchor(okj, Jump(0)));
+            }
+            RegexNode::EndingAnchor => {
+                let okj = self.next_op_label()?;
+                self.ops.push(Op::EndingAnchor(okj, Jump(0)));
+            }
+            RegexNode::WordBoundary => {
+                let okj = self.next_op_label()?;
+                self.ops.push(Op::WordBoundary(okj, Jump(0)));
+            }
+            RegexNode::Group {
+                capture_index,
+                alternatives,
+            } => {
+                if let Some(index) = *capture_index {
+                    let jump = self.next_op_label()?;
+                    self.ops.push(Op::CaptureStart(jump, index));
+                }
+
+                let end_jump = self.regex_label_at(0)?;
+                let last_index = alternatives.len() - 1;
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    let alternative_jump = if i < last_index {
+                        let alternative_jump = self.regex_label_at(0)?;
+                        let jump = self.next_op_label()?;
+                        self.ops.push(Op::Fork(jump, alternative_jump));
+                        Some(alternative_jump)
+                    } else {
+                        None
+                    };
+
+                    for node in alternative {
+                        self.emit_regex(node, position)?;
+                    }
+
+                    if let Some(alternative_jump) = alternative_jump {
+                        self.ops.push(Op::Unwind(end_jump, Length(0)));
+                        self.place_regex_label(alternative_jump)?;
+                    }
+                }
+                self.place_regex_label(end_jump)?;
+
+                if let Some(index) = *capture_index {
+                    let jump = self.next_op_label()?;
+                    self.ops.push(Op::CaptureEnd(jump, index));
+                }
+            }
+            // greedily matches as many chars as possible and then gives them back one by one
+            // until the rest of the pattern matches
+            RegexNode::Repeat { node, min, max } => {
+                for _ in 0..*min {
+                    let okj = self.regex_label_at(0)?;
+                    self.emit_regex_single_char(node, okj, Jump(0))?;
+                    self.place_regex_label(okj)?;
+                }
+
+                let exit_jump = self.regex_label_at(0)?;
+                match max {
+                    Some(max) => {
+                        for _ in *min..*max {
+                            let jump = self.next_op_label()?;
+                            self.ops.push(Op::Fork(jump, exit_jump));
+                            let okj = self.regex_label_at(0)?;
+                            self.emit_regex_single_char(node, okj, Jump(0))?;
+                            self.place_regex_label(okj)?;
+                        }
+                    }
+                    None => {
+                        let loop_jump = self.regex_label_at(self.ops.len())?;
+                        let jump = self.next_op_label()?;
+                        self.ops.push(Op::Fork(jump, exit_jump));
+                        self.emit_regex_single_char(node, loop_jump, Jump(0))?;
+                    }
+                }
+                self.place_regex_label(exit_jump)?;
+            }
+            // repeated groups also give back their iterations one by one, but stop repeating
+            // once an iteration matches nothing
+            RegexNode::GroupLoop(node) => {
+                Self::assert_regex_positions(position, 1)?;
+
+                let loop_jump = self.regex_label_at(self.ops.len())?;
+                let jump = self.next_op_label()?;
+                self.ops.push(Op::SavePosition(jump, position));
+
+                let exit_jump = self.regex_label_at(0)?;
+                let jump = self.next_op_label()?;
+                self.ops.push(Op::Fork(jump, exit_jump));
+                self.emit_regex(node, position + 1)?;
+                self.ops
+                    .push(Op::PositionAdvanced(loop_jump, exit_jump, position));
+                self.place_regex_label(exit_jump)?;
+            }
+            // like repeated chars, but once an iteration fails the remaining ones are skipped
+            RegexNode::OptionalGroups(node, count) => {
+                let exit_jump = self.regex_label_at(0)?;
+                for _ in 0..*count {
+                    let jump = self.next_op_label()?;
+                    self.ops.push(Op::Fork(jump, exit_jump));
+                    self.emit_regex(node, position)?;
+                }
+                self.place_regex_label(exit_jump)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn emit_regex_single_char(
+        &mut self,
+        node: &RegexNode,
+        okj: Jump,
+        erj: Jump,
+    ) -> Result<(), PatternError> {
+        fn set_item_op(item: &RegexSetItem, okj: Jump, erj: Jump) -> Op {
+            match *item {
+                RegexSetItem::Char(c) => Op::Char(okj, erj, c),
+                RegexSetItem::Range(from, to) => Op::Range(okj, erj, from, to),
+                RegexSetItem::Digit => Op::Digit(okj, erj),
+                RegexSetItem::Alphanumeric => Op::Alphanumeric(okj, erj),
+            }
+        }
+
+        match node {
+            &RegexNode::Char(c) => self.ops.push(Op::Char(okj, erj, c)),
+            RegexNode::Any => self.ops.push(Op::SkipOne(okj, erj)),
+            RegexNode::Set {
+                negated: false,
+                items,
+            } => {
+                let last_index = items.len() - 1;
+                for (i, item) in items.iter().enumerate() {
+                    let item_erj = if i == last_index {
+                        erj
+                    } else {
+                        self.next_op_label()?
+                    };
+                    self.ops.push(set_item_op(item, okj, item_erj));
+                }
+            }
+            RegexNode::Set {
+                negated: true,
+                items,
+            } => {
+                let reject_jump = self.regex_label_at(0)?;
+                for item in items {
+                    let item_erj = self.next_op_label()?;
+                    self.ops.push(set_item_op(item, reject_jump, item_erj));
+                }
+                self.ops.push(Op::SkipOne(okj, erj));
+                self.place_regex_label(reject_jump)?;
+                self.ops.push(Op::Unwind(erj, Length(1)));
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
     fn optimize(&mut self) {
         let mut i = 0;
         while i < self.ops.len() {
@@ -930,8 +1917,14 @@ impl<'a> PatternCompiler<'a> {
         for op in self.ops.iter_mut() {
             match op {
                 Op::Ok | Op::Error => (),
-                Op::Reset(j) | Op::Unwind(j, _) => fix_jump(j, index, jump),
-                Op::BeginningAnchor(okj, erj)
+                Op::Reset(j)
+                | Op::Unwind(j, _)
+                | Op::CaptureStart(j, _)
+                | Op::CaptureEnd(j, _)
+                | Op::SavePosition(j, _) => fix_jump(j, index, jump),
+                Op::Fork(okj, erj)
+                | Op::PositionAdvanced(okj, erj, _)
+                | Op::BeginningAnchor(okj, erj)
                 | Op::EndingAnchor(okj, erj)
                 | Op::WordBoundary(okj, erj)
                 | Op::SkipOne(okj, erj)
@@ -943,6 +1936,8 @@ impl<'a> PatternCompiler<'a> {
                 | Op::Alphanumeric(okj, erj)
                 | Op::Char(okj, erj, _)
                 | Op::CharCaseInsensitive(okj, erj, _)
+                | Op::Range(okj, erj, _, _)
+                | Op::RangeCaseInsensitive(okj, erj, _, _)
                 | Op::String(okj, erj, _, _)
                 | Op::StringCaseInsensitive(okj, erj, _, _) => {
                     fix_jump(okj, index, jump);
@@ -996,8 +1991,14 @@ impl<'a> PatternCompiler<'a> {
         for op in self.ops.iter_mut() {
             match op {
                 Op::Ok | Op::Error => (),
-                Op::Reset(j) | Op::Unwind(j, _) => fix_jump(j, index, fix),
-                Op::BeginningAnchor(okj, erj)
+                Op::Reset(j)
+                | Op::Unwind(j, _)
+                | Op::CaptureStart(j, _)
+                | Op::CaptureEnd(j, _)
+                | Op::SavePosition(j, _) => fix_jump(j, index, fix),
+                Op::Fork(okj, erj)
+                | Op::PositionAdvanced(okj, erj, _)
+                | Op::BeginningAnchor(okj, erj)
                 | Op::EndingAnchor(okj, erj)
                 | Op::WordBoundary(okj, erj)
                 | Op::SkipOne(okj, erj)
@@ -1009,6 +2010,8 @@ impl<'a> PatternCompiler<'a> {
                 | Op::Alphanumeric(okj, erj)
                 | Op::Char(okj, erj, _)
                 | Op::CharCaseInsensitive(okj, erj, _)
+                | Op::Range(okj, erj, _, _)
+                | Op::RangeCaseInsensitive(okj, erj, _, _)
                 | Op::String(okj, erj, _, _)
                 | Op::StringCaseInsensitive(okj, erj, _, _) => {
                     fix_jump(okj, index, fix);
@@ -1078,8 +2081,14 @@ impl<'a> PatternCompiler<'a> {
         for op in self.ops.iter_mut() {
             match op {
                 Op::Ok | Op::Error => (),
-                Op::Reset(j) | Op::Unwind(j, _) => fix_jump(j, index, fix),
-                Op::BeginningAnchor(okj, erj)
+                Op::Reset(j)
+                | Op::Unwind(j, _)
+                | Op::CaptureStart(j, _)
+                | Op::CaptureEnd(j, _)
+                | Op::SavePosition(j, _) => fix_jump(j, index, fix),
+                Op::Fork(okj, erj)
+                | Op::PositionAdvanced(okj, erj, _)
+                | Op::BeginningAnchor(okj, erj)
                 | Op::EndingAnchor(okj, erj)
                 | Op::WordBoundary(okj, erj)
                 | Op::SkipOne(okj, erj)
@@ -1091,6 +2100,8 @@ impl<'a> PatternCompiler<'a> {
                 | Op::Alphanumeric(okj, erj)
                 | Op::Char(okj, erj, _)
                 | Op::CharCaseInsensitive(okj, erj, _)
+                | Op::Range(okj, erj, _, _)
+                | Op::RangeCaseInsensitive(okj, erj, _, _)
                 | Op::String(okj, erj, _, _)
                 | Op::StringCaseInsensitive(okj, erj, _, _) => {
                     fix_jump(okj, index, fix);
@@ -1115,6 +2126,22 @@ mod tests {
         try_new_pattern(pattern).unwrap()
     }
 
+    fn try_new_regex(pattern: &str) -> Result<Pattern, PatternError> {
+        let mut p = Pattern::new();
+        p.compile_regex(pattern)?;
+        Ok(p)
+    }
+
+    fn new_regex(pattern: &str) -> Pattern {
+        try_new_regex(pattern).unwrap()
+    }
+
+    fn new_capturing_pattern(pattern: &str, dialect: PatternDialect) -> Pattern {
+        let mut p = Pattern::new();
+        p.compile_with_captures(pattern, dialect).unwrap();
+        p
+    }
+
     #[test]
     fn search_anchor() {
         assert_eq!(None, new_pattern("").search_anchor());
@@ -1628,4 +2655,345 @@ mod tests {
             Err(PatternError::UnexpectedEndOfPattern)
         ));
     }
+
+    #[test]
+    fn captures() {
+        let mut captures = PatternCaptures::new();
+
+        let p = new_capturing_pattern("(%a{%a})=({%d})", PatternDialect::Pepper);
+        assert_eq!(
+            MatchResult::Ok(7),
+            p.matches_with_captures("abc=123", 0, &mut captures)
+        );
+        assert_eq!(Some(0..7), captures.get(0));
+        assert_eq!(Some(0..3), captures.get(1));
+        assert_eq!(Some(4..7), captures.get(2));
+        assert_eq!(None, captures.get(3));
+
+        let p = new_capturing_pattern("{(ab)}c", PatternDialect::Pepper);
+        assert_eq!(
+            MatchResult::Ok(6),
+            p.matches_with_captures("_ababc", 1, &mut captures)
+        );
+        assert_eq!(Some(1..6), captures.get(0));
+        assert_eq!(Some(3..5), captures.get(1));
+
+        let p = new_capturing_pattern("{(ab)}", PatternDialect::Pepper);
+        assert_eq!(
+            MatchResult::Ok(2),
+            p.matches_with_captures("abac", 0, &mut captures)
+        );
+        assert_eq!(Some(0..2), captures.get(1));
+
+        let p = new_capturing_pattern("(a)b|a(c)", PatternDialect::Pepper);
+        assert_eq!(
+            MatchResult::Ok(2),
+            p.matches_with_captures("ac", 0, &mut captures)
+        );
+        assert_eq!(None, captures.get(1));
+        assert_eq!(Some(1..2), captures.get(2));
+
+        let p = new_capturing_pattern("a[(b)(c$)]", PatternDialect::Pepper);
+        assert_eq!(
+            MatchResult::Ok(2),
+            p.matches_with_captures("ac", 0, &mut captures)
+        );
+        assert_eq!(None, captures.get(1));
+        assert_eq!(Some(1..2), captures.get(2));
+
+        let p = new_capturing_pattern("(!a)(b)", PatternDialect::Pepper);
+        assert_eq!(
+            MatchResult::Ok(2),
+            p.matches_with_captures("xb", 0, &mut captures)
+        );
+        assert_eq!(Some(1..2), captures.get(1));
+
+        assert_eq!(
+            MatchResult::Err,
+            p.matches_with_captures("ab", 0, &mut captures)
+        );
+        assert_eq!(None, captures.get(0));
+
+        let p = new_pattern("(ab)c");
+        assert_eq!(
+            MatchResult::Ok(3),
+            p.matches_with_captures("abc", 0, &mut captures)
+        );
+        assert_eq!(Some(0..3), captures.get(0));
+        assert_eq!(None, captures.get(1));
+    }
+
+    #[test]
+    fn expand_template() {
+        let mut captures = PatternCaptures::new();
+        let p = new_capturing_pattern("(%a{%a})=({%d})", PatternDialect::Pepper);
+        let text = "abc=123";
+        assert_eq!(
+            MatchResult::Ok(7),
+            p.matches_with_captures(text, 0, &mut captures)
+        );
+
+        let mut output = String::new();
+        captures
+            .expand_template(text, "%2 %% %1 (%0)%3", &mut output)
+            .unwrap();
+        assert_eq!("123 % abc (abc=123)", output);
+
+        assert!(matches!(
+            captures.expand_template(text, "%", &mut output),
+            Err(PatternError::UnexpectedEndOfPattern)
+        ));
+        assert!(matches!(
+            captures.expand_template(text, "%a", &mut output),
+            Err(PatternError::InvalidEscaping('a'))
+        ));
+    }
+    #[test]
+    fn regex_simple() {
+        let p = new_regex("");
+        assert_eq!(MatchResult::Ok(0), p.matches("abc", 0));
+        assert!(p.is_empty());
+
+        let p = new_regex("abc");
+        assert_eq!(Some('a'), p.search_anchor());
+        assert_eq!(MatchResult::Ok(3), p.matches("abcd", 0));
+        assert_eq!(MatchResult::Err, p.matches("abd", 0));
+
+        let p = new_regex(r"a\.\d\D\w\W\s\S");
+        assert_eq!(MatchResult::Ok(8), p.matches("a.1x_- !", 0));
+        assert_eq!(MatchResult::Err, p.matches("ax1x_- !", 0));
+        assert_eq!(MatchResult::Err, p.matches("a.1x_-!!", 0));
+
+        let p = new_regex("a.c");
+        assert_eq!(MatchResult::Ok(3), p.matches("abc", 0));
+        assert_eq!(MatchResult::Ok(4), p.matches("aéc", 0));
+        assert_eq!(MatchResult::Err, p.matches("ac", 0));
+
+        let p = new_regex("{a}");
+        assert_eq!(MatchResult::Ok(3), p.matches("{a}", 0));
+
+        let p = new_regex(r"\}*\{+");
+        assert_eq!(MatchResult::Ok(3), p.matches("}}{", 0));
+    }
+
+    #[test]
+    fn regex_set() {
+        let p = new_regex("[a-c_]+");
+        assert_eq!(MatchResult::Ok(4), p.matches("b_ca", 0));
+        assert_eq!(MatchResult::Ok(2), p.matches("abd", 0));
+        assert_eq!(MatchResult::Err, p.matches("d", 0));
+
+        let p = new_regex("[^a-c0-9]");
+        assert_eq!(MatchResult::Ok(1), p.matches("d", 0));
+        assert_eq!(MatchResult::Err, p.matches("b", 0));
+        assert_eq!(MatchResult::Err, p.matches("5", 0));
+        assert_eq!(MatchResult::Err, p.matches("", 0));
+
+        let p = new_regex(r"[]\d-]*x");
+        assert_eq!(MatchResult::Ok(5), p.matches("]1-2x", 0));
+
+        let p = new_regex(r"[^\s]+");
+        assert_eq!(MatchResult::Ok(3), p.matches("abc def", 0));
+    }
+
+    #[test]
+    fn regex_repeat() {
+        let p = new_regex("ab*c");
+        assert_eq!(MatchResult::Ok(2), p.matches("ac", 0));
+        assert_eq!(MatchResult::Ok(4), p.matches("abbc", 0));
+        assert_eq!(MatchResult::Err, p.matches("abbd", 0));
+
+        let p = new_regex("ab+c");
+        assert_eq!(MatchResult::Err, p.matches("ac", 0));
+        assert_eq!(MatchResult::Ok(3), p.matches("abc", 0));
+
+        let p = new_regex("ab?c");
+        assert_eq!(MatchResult::Ok(2), p.matches("ac", 0));
+        assert_eq!(MatchResult::Ok(3), p.matches("abc", 0));
+        assert_eq!(MatchResult::Err, p.matches("abbc", 0));
+
+        let p = new_regex(r"\d{2,3}");
+        assert_eq!(MatchResult::Err, p.matches("1", 0));
+        assert_eq!(MatchResult::Ok(2), p.matches("12", 0));
+        assert_eq!(MatchResult::Ok(3), p.matches("1234", 0));
+
+        let p = new_regex("a{2}");
+        assert_eq!(MatchResult::Err, p.matches("a", 0));
+        assert_eq!(MatchResult::Ok(2), p.matches("aaa", 0));
+
+        let p = new_regex("a{2,}");
+        assert_eq!(MatchResult::Ok(4), p.matches("aaaa", 0));
+
+        let p = new_regex("a{,2}");
+        assert_eq!(MatchResult::Ok(5), p.matches("a{,2}", 0));
+    }
+
+    #[test]
+    fn regex_backtracking() {
+        let p = new_regex("a.*b");
+        assert_eq!(MatchResult::Ok(5), p.matches("axbxbx", 0));
+        assert_eq!(MatchResult::Err, p.matches("axxx", 0));
+
+        let p = new_regex(r"\w+\s*=\s*\d+;");
+        assert_eq!(MatchResult::Ok(10), p.matches("abc = 123;", 0));
+        assert_eq!(MatchResult::Ok(6), p.matches("abc=1;", 0));
+        assert_eq!(MatchResult::Err, p.matches("abc = ;", 0));
+
+        let p = new_regex("(a|ab)c");
+        assert_eq!(MatchResult::Ok(2), p.matches("ac", 0));
+        assert_eq!(MatchResult::Ok(3), p.matches("abc", 0));
+
+        let p = new_regex("x*x*x");
+        assert_eq!(MatchResult::Ok(3), p.matches("xxx", 0));
+
+        let p = new_regex("(ab)*abc");
+        assert_eq!(MatchResult::Ok(5), p.matches("ababc", 0));
+        let p = new_regex("(ab)*ab");
+        assert_eq!(MatchResult::Ok(4), p.matches("abab", 0));
+        let p = new_regex("(ab)*c");
+        assert_eq!(MatchResult::Ok(5), p.matches("ababc", 0));
+
+        let p = new_regex(&format!("{}c", "(a|b)".repeat(16)));
+        assert_eq!(MatchResult::Ok(17), p.matches("abababababababbac", 0));
+        assert_eq!(MatchResult::Err, p.matches("abababababababbab", 0));
+        let p = new_regex(&"a*".repeat(20));
+        assert_eq!(MatchResult::Ok(3), p.matches("aaa", 0));
+    }
+
+    #[test]
+    fn regex_group() {
+        let p = new_regex("(ab|cd)+e");
+        assert_eq!(MatchResult::Ok(5), p.matches("abcde", 0));
+        assert_eq!(MatchResult::Ok(3), p.matches("cde", 0));
+        assert_eq!(MatchResult::Err, p.matches("e", 0));
+
+        let p = new_regex("(?:ab)?c");
+        assert_eq!(MatchResult::Ok(1), p.matches("c", 0));
+        assert_eq!(MatchResult::Ok(3), p.matches("abc", 0));
+
+        let p = new_regex("(a?)*b");
+        assert_eq!(MatchResult::Ok(3), p.matches("aab", 0));
+        assert_eq!(MatchResult::Ok(1), p.matches("b", 0));
+
+        let p = new_regex("(ab){0,255}c");
+        assert_eq!(MatchResult::Ok(7), p.matches("abababc", 0));
+        assert_eq!(MatchResult::Ok(1), p.matches("c", 0));
+
+        let p = new_regex("(ab){1,2}c");
+        assert_eq!(MatchResult::Ok(3), p.matches("abc", 0));
+        assert_eq!(MatchResult::Ok(5), p.matches("ababc", 0));
+        assert_eq!(MatchResult::Err, p.matches("abababc", 0));
+
+        let p = new_regex("abc|def");
+        assert_eq!(MatchResult::Ok(3), p.matches("abc", 0));
+        assert_eq!(MatchResult::Ok(3), p.matches("def", 0));
+        assert_eq!(MatchResult::Err, p.matches("abd", 0));
+    }
+
+    #[test]
+    fn regex_anchors() {
+        let p = new_regex(r"^\bab$");
+        assert_eq!(MatchResult::Ok(2), p.matches("ab", 0));
+        assert_eq!(MatchResult::Err, p.matches("xab", 1));
+        assert_eq!(MatchResult::Err, p.matches("abc", 0));
+
+        let p = new_regex(r"a\b");
+        assert_eq!(MatchResult::Ok(1), p.matches("a b", 0));
+        assert_eq!(MatchResult::Err, p.matches("ab", 0));
+    }
+
+    #[test]
+    fn regex_captures() {
+        let mut captures = PatternCaptures::new();
+
+        let p = new_capturing_pattern(r"(\w+)=(\d+)", PatternDialect::Regex);
+        assert_eq!(
+            MatchResult::Ok(7),
+            p.matches_with_captures("abc=123", 0, &mut captures)
+        );
+        assert_eq!(Some(0..3), captures.get(1));
+        assert_eq!(Some(4..7), captures.get(2));
+
+        let p = new_capturing_pattern("(?:x)(a|b)+", PatternDialect::Regex);
+        assert_eq!(
+            MatchResult::Ok(3),
+            p.matches_with_captures("xab", 0, &mut captures)
+        );
+        assert_eq!(Some(2..3), captures.get(1));
+
+        let p = new_capturing_pattern("(ab)*ab", PatternDialect::Regex);
+        assert_eq!(
+            MatchResult::Ok(4),
+            p.matches_with_captures("abab", 0, &mut captures)
+        );
+        assert_eq!(Some(0..2), captures.get(1));
+    }
+
+    #[test]
+    fn regex_searcher() {
+        let mut p = Pattern::new();
+        p.compile_searcher("a.c", PatternDialect::Regex).unwrap();
+        assert_eq!(MatchResult::Ok(3), p.matches("ABC", 0));
+        p.compile_searcher("[a-c]+", PatternDialect::Regex).unwrap();
+        assert_eq!(MatchResult::Ok(3), p.matches("AbC", 0));
+        p.compile_searcher("r/[a-c]+", PatternDialect::Pepper)
+            .unwrap();
+        assert_eq!(MatchResult::Ok(2), p.matches("ab", 0));
+        p.compile_searcher("R/a+", PatternDialect::Pepper).unwrap();
+        assert_eq!(MatchResult::Err, p.matches("A", 0));
+        p.compile_searcher("p/%d", PatternDialect::Regex).unwrap();
+        assert_eq!(MatchResult::Ok(1), p.matches("1", 0));
+    }
+
+    #[test]
+    fn regex_bad_pattern() {
+        assert!(matches!(
+            try_new_regex("("),
+            Err(PatternError::UnexpectedEndOfPattern)
+        ));
+        assert!(matches!(
+            try_new_regex(")"),
+            Err(PatternError::Unescaped(')'))
+        ));
+        assert!(matches!(
+            try_new_regex("[a"),
+            Err(PatternError::UnexpectedEndOfPattern)
+        ));
+        assert!(matches!(
+            try_new_regex("*a"),
+            Err(PatternError::Unescaped('*'))
+        ));
+        assert!(matches!(
+            try_new_regex("a**"),
+            Err(PatternError::Unescaped('*'))
+        ));
+        assert!(matches!(
+            try_new_regex(r"\q"),
+            Err(PatternError::InvalidRegexEscaping('q'))
+        ));
+        assert!(matches!(
+            try_new_regex("[z-a]"),
+            Err(PatternError::InvalidRange('z', 'a'))
+        ));
+        assert!(matches!(
+            try_new_regex("a{3,2}"),
+            Err(PatternError::InvalidRepetition(3, 2))
+        ));
+        assert!(matches!(
+            try_new_regex("(?x)"),
+            Err(PatternError::Expected(':'))
+        ));
+        assert!(matches!(
+            try_new_regex(&"(".repeat(40)),
+            Err(PatternError::NestingTooDeep)
+        ));
+        assert!(matches!(
+            try_new_regex("(a){0,5000}"),
+            Err(PatternError::InvalidRepetition(0, 5000))
+        ));
+        assert!(matches!(
+            try_new_regex("a{10,20000}"),
+            Err(PatternError::InvalidRepetition(10, 20000))
+        ));
+    }
 }
@@ -4,7 +4,10 @@ use std::{
     process::{Command, Stdio},
 };
 
-use crate::{client::ClientHandle, editor_utils::parse_process_command, plugin::PluginHandle};
+use crate::{
+    buffer::BufferHandle, client::ClientHandle, editor_utils::parse_process_command,
+    plugin::PluginHandle,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KeyCode {
@@ -135,6 +138,14 @@ pub enum ProcessTag {
         plugin_handle: PluginHandle,
         id: u32,
     },
+    VcsBase {
+        buffer_handle: BufferHandle,
+        id: u32,
+    },
+    BufferRead {
+        buffer_handle: BufferHandle,
+        id: u32,
+    },
 }
 
 #[derive(Clone, Copy)]
@@ -1,10 +1,15 @@
-use std::str::FromStr;
+use std::{env, fmt::Write, str::FromStr};
 
 use crate::{
     buffer::BufferContent,
     buffer_position::{BufferPosition, BufferPositionIndex, BufferRange},
+    client::ClientManager,
+    command::CommandManager,
+    editor::{Editor, EditorContext},
     glob::{Glob, InvalidGlobError},
     pattern::{MatchResult, Pattern, PatternError, PatternState},
+    platform::Platform,
+    plugin::PluginCollection,
 };
 
 #[cfg(not(debug_assertions))]
@@ -22,6 +27,8 @@ pub enum TokenKind {
     Comment,
     Text,
     Whitespace,
+    // declared at runtime by `SyntaxCollection::add_token_kind`
+    Custom(u8),
 }
 impl FromStr for TokenKind {
     type Err = ();
@@ -60,11 +67,60 @@ impl Default for Token {
     }
 }
 
+// how deep embedded syntax regions can nest (e.g. markdown > html > javascript)
+const MAX_SYNTAX_REGION_DEPTH: usize = 4;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct SyntaxRegionFrame {
+    parent_handle: SyntaxHandle,
+    region_index: u16,
+    syntax_handle: SyntaxHandle,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct SyntaxRegionStack {
+    len: u8,
+    frames: [SyntaxRegionFrame; MAX_SYNTAX_REGION_DEPTH],
+}
+
+impl SyntaxRegionStack {
+    fn top(&self) -> Option<&SyntaxRegionFrame> {
+        match self.len {
+            0 => None,
+            len => Some(&self.frames[len as usize - 1]),
+        }
+    }
+
+    fn current_syntax_handle(&self, root_handle: SyntaxHandle) -> SyntaxHandle {
+        match self.top() {
+            Some(frame) => frame.syntax_handle,
+            None => root_handle,
+        }
+    }
+
+    fn push(&mut self, frame: SyntaxRegionFrame) -> bool {
+        if (self.len as usize) < MAX_SYNTAX_REGION_DEPTH {
+            self.frames[self.len as usize] = frame;
+            self.len += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn pop(&mut self) {
+        if self.len > 0 {
+            self.len -= 1;
+            self.frames[self.len as usize] = SyntaxRegionFrame::default();
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum LineParseState {
     Dirty,
-    Finished,
-    Unfinished(TokenKind, PatternState),
+    Finished(SyntaxRegionStack),
+    Unfinished(SyntaxRegionStack, TokenKind, PatternState),
 }
 
 impl Default for LineParseState {
@@ -73,9 +129,45 @@ impl Default for LineParseState {
     }
 }
 
+struct SyntaxRegion {
+    syntax_name: String,
+    start: Pattern,
+    end: Pattern,
+}
+
+fn builtin_rule_index(kind: TokenKind) -> Option<usize> {
+    match kind {
+        TokenKind::Keyword => Some(0),
+        TokenKind::Type => Some(1),
+        TokenKind::Symbol => Some(2),
+        TokenKind::Literal => Some(3),
+        TokenKind::String => Some(4),
+        TokenKind::Comment => Some(5),
+        TokenKind::Text => Some(6),
+        TokenKind::Whitespace | TokenKind::Custom(_) => None,
+    }
+}
+
+fn match_indentation_pattern(pattern: &Pattern, line: &str) -> Option<usize> {
+    if pattern.is_empty() {
+        return None;
+    }
+    let start_index = line.len() - line.trim_start().len();
+    match pattern.matches(line, start_index) {
+        MatchResult::Ok(end_index) => Some(end_index),
+        _ => None,
+    }
+}
+
 pub struct Syntax {
+    name: String,
     glob: Glob,
+    first_line: Pattern,
+    indent: Pattern,
+    dedent: Pattern,
     rules: [Pattern; 7],
+    custom_rules: Vec<(TokenKind, Pattern)>,
+    regions: Vec<SyntaxRegion>,
 }
 
 impl Syntax {
@@ -83,7 +175,11 @@ impl Syntax {
         let mut text_pattern = Pattern::new();
         let _ = text_pattern.compile("%a{%w_}|_{%w_}");
         Self {
+            name: String::new(),
             glob: Glob::default(),
+            first_line: Pattern::new(),
+            indent: Pattern::new(),
+            dedent: Pattern::new(),
             rules: [
                 Pattern::new(),
                 Pattern::new(),
@@ -93,19 +189,289 @@ impl Syntax {
                 Pattern::new(),
                 text_pattern,
             ],
+            custom_rules: Vec::new(),
+            regions: Vec::new(),
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     fn set_glob(&mut self, glob: &str) -> Result<(), InvalidGlobError> {
         self.glob.compile(glob)
     }
 
+    pub fn set_name(&mut self, name: &str) {
+        self.name.clear();
+        self.name.push_str(name);
+    }
+
+    pub fn set_first_line(&mut self, pattern: &str) -> Result<(), PatternError> {
+        self.first_line.compile(pattern)
+    }
+
+    fn matches_first_line(&self, line: &str) -> bool {
+        !self.first_line.is_empty()
+            && matches!(self.first_line.matches(line, 0), MatchResult::Ok(_))
+    }
+
+    pub fn set_indent(&mut self, pattern: &str) -> Result<(), PatternError> {
+        self.indent.compile(pattern)
+    }
+
+    pub fn set_dedent(&mut self, pattern: &str) -> Result<(), PatternError> {
+        self.dedent.compile(pattern)
+    }
+
+    pub fn indents(&self, line: &str) -> bool {
+        match_indentation_pattern(&self.indent, line).is_some()
+    }
+
+    pub fn dedents(&self, line: &str) -> bool {
+        match_indentation_pattern(&self.dedent, line).is_some()
+    }
+
+    pub fn dedent_match_end(&self, line: &str) -> Option<usize> {
+        match_indentation_pattern(&self.dedent, line)
+    }
+
     pub fn set_rule(&mut self, kind: TokenKind, pattern: &str) -> Result<(), PatternError> {
-        self.rules[kind as usize].compile(pattern)
+        if let Some(index) = builtin_rule_index(kind) {
+            return self.rules[index].compile(pattern);
+        }
+
+        match self.custom_rules.iter_mut().find(|(k, _)| *k == kind) {
+            Some((_, rule)) => rule.compile(pattern),
+            None => {
+                let mut rule = Pattern::new();
+                rule.compile(pattern)?;
+                self.custom_rules.push((kind, rule));
+                Ok(())
+            }
+        }
+    }
+
+    fn rule(&self, kind: TokenKind) -> Option<&Pattern> {
+        match builtin_rule_index(kind) {
+            Some(index) => Some(&self.rules[index]),
+            None => self
+                .custom_rules
+                .iter()
+                .find(|(k, _)| *k == kind)
+                .map(|(_, rule)| rule),
+        }
+    }
+
+    pub fn add_region(
+        &mut self,
+        syntax_name: &str,
+        start: &str,
+        end: &str,
+    ) -> Result<(), PatternError> {
+        let mut region = SyntaxRegion {
+            syntax_name: syntax_name.into(),
+            start: Pattern::new(),
+            end: Pattern::new(),
+        };
+        region.start.compile(start)?;
+        region.end.compile(end)?;
+        self.regions.push(region);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SyntaxHandle(u32);
+
+// how many lines at the start and at the end of a buffer are checked for a modeline
+const MODELINE_LINE_COUNT: usize = 5;
+
+// vim style (`vim: set ft=python:`) or emacs style (`-*- mode: python -*-`) modelines
+fn parse_modeline_syntax_name(line: &str) -> Option<&str> {
+    if let Some(index) = line.find("-*-") {
+        let rest = &line[index + 3..];
+        if let Some(index) = rest.find("-*-") {
+            let variables = rest[..index].trim();
+            if !variables.contains(':') {
+                return Some(variables).filter(|v| !v.is_empty());
+            }
+            for variable in variables.split(';') {
+                if let Some((key, value)) = variable.split_once(':') {
+                    if key.trim().eq_ignore_ascii_case("mode") {
+                        return Some(value.trim()).filter(|v| !v.is_empty());
+                    }
+                }
+            }
+        }
+    }
+
+    for marker in ["vim:", "vi:", "ex:"] {
+        let index = match line.find(marker) {
+            Some(index) => index,
+            None => continue,
+        };
+        if !line[..index]
+            .chars()
+            .next_back()
+            .map(char::is_whitespace)
+            .unwrap_or(true)
+        {
+            continue;
+        }
+
+        let options = &line[index + marker.len()..];
+        for option in options.split(|c: char| c == ':' || c.is_whitespace()) {
+            for key in ["ft=", "filetype=", "syn=", "syntax="] {
+                if let Some(name) = option.strip_prefix(key) {
+                    if !name.is_empty() {
+                        return Some(name);
+                    }
+                }
+            }
+        }
+    }
+
+    None
+}
+
+pub struct SyntaxCollection {
+    syntaxes: Vec<Syntax>,
+    custom_token_kind_names: Vec<String>,
+}
+
+impl SyntaxCollection {
+    pub fn new() -> Self {
+        Self {
+            syntaxes: vec![Syntax::new()],
+            custom_token_kind_names: Vec::new(),
+        }
+    }
+
+    // returns the already registered kind if there's one with this name
+    pub fn add_token_kind(&mut self, name: &str) -> Option<TokenKind> {
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return None;
+        }
+
+        match self.custom_token_kind_names.iter().position(|n| n == name) {
+            Some(index) => Some(TokenKind::Custom(index as _)),
+            None => {
+                let index = self.custom_token_kind_names.len();
+                if index > u8::MAX as usize {
+                    return None;
+                }
+                self.custom_token_kind_names.push(name.into());
+                Some(TokenKind::Custom(index as _))
+            }
+        }
+    }
+
+    pub fn find_token_kind(&self, name: &str) -> Option<TokenKind> {
+        let index = self
+            .custom_token_kind_names
+            .iter()
+            .position(|n| n == name)?;
+        Some(TokenKind::Custom(index as _))
+    }
+
+    pub fn token_kind_name(&self, kind: TokenKind) -> &str {
+        match kind {
+            TokenKind::Keyword => "keywords",
+            TokenKind::Type => "types",
+            TokenKind::Symbol => "symbols",
+            TokenKind::Literal => "literals",
+            TokenKind::String => "strings",
+            TokenKind::Comment => "comments",
+            TokenKind::Text => "texts",
+            TokenKind::Whitespace => "whitespace",
+            TokenKind::Custom(index) => match self.custom_token_kind_names.get(index as usize) {
+                Some(name) => name,
+                None => "",
+            },
+        }
+    }
+
+    pub fn find_handle_by_path(&self, path: &str) -> Option<SyntaxHandle> {
+        for (i, syntax) in self.syntaxes.iter().enumerate().rev() {
+            if syntax.glob.matches(path) {
+                return Some(SyntaxHandle(i as _));
+            }
+        }
+
+        None
+    }
+
+    pub fn find_handle_by_name(&self, name: &str) -> Option<SyntaxHandle> {
+        if name.is_empty() {
+            return None;
+        }
+        for (i, syntax) in self.syntaxes.iter().enumerate().rev() {
+            if syntax.name == name {
+                return Some(SyntaxHandle(i as _));
+            }
+        }
+
+        None
+    }
+
+    // a modeline naming a syntax wins over the path which wins over the first line patterns
+    pub fn find_handle(&self, path: &str, content: &BufferContent) -> Option<SyntaxHandle> {
+        let lines = content.lines();
+        let modeline_lines = if lines.len() > 2 * MODELINE_LINE_COUNT {
+            let tail_index = lines.len() - MODELINE_LINE_COUNT;
+            lines
+                .range(..MODELINE_LINE_COUNT)
+                .chain(lines.range(tail_index..))
+        } else {
+            lines.iter().chain(lines.range(..0))
+        };
+        for line in modeline_lines {
+            if let Some(name) = parse_modeline_syntax_name(line.as_str()) {
+                if let Some(handle) = self.find_handle_by_name(name) {
+                    return Some(handle);
+                }
+            }
+        }
+
+        if !path.is_empty() {
+            if let Some(handle) = self.find_handle_by_path(path) {
+                return Some(handle);
+            }
+        }
+
+        let first_line = lines[0].as_str();
+        for (i, syntax) in self.syntaxes.iter().enumerate().rev() {
+            if syntax.matches_first_line(first_line) {
+                return Some(SyntaxHandle(i as _));
+            }
+        }
+
+        None
+    }
+
+    pub(crate) fn add_from_glob(&mut self, glob: &str) -> Result<(), InvalidGlobError> {
+        let mut syntax = Syntax::new();
+        syntax.set_glob(glob)?;
+        self.syntaxes.push(syntax);
+        Ok(())
+    }
+
+    pub(crate) fn get_last(&mut self) -> &mut Syntax {
+        self.syntaxes.last_mut().unwrap()
+    }
+
+    pub fn get(&self, handle: SyntaxHandle) -> &Syntax {
+        &self.syntaxes[handle.0 as usize]
     }
 
     fn parse_line(
         &self,
+        root_handle: SyntaxHandle,
         line: &str,
         previous_parse_state: LineParseState,
         tokens: &mut Vec<Token>,
@@ -114,11 +480,16 @@ impl Syntax {
 
         let mut index = 0;
 
-        match previous_parse_state {
+        let mut regions = match previous_parse_state {
             LineParseState::Dirty => unreachable!(),
-            LineParseState::Finished => (),
-            LineParseState::Unfinished(kind, state) => {
-                match self.rules[kind as usize].matches_with_state(line, 0, state) {
+            LineParseState::Finished(regions) => regions,
+            LineParseState::Unfinished(regions, kind, state) => {
+                let syntax = self.get(regions.current_syntax_handle(root_handle));
+                let result = match syntax.rule(kind) {
+                    Some(rule) => rule.matches_with_state(line, 0, state),
+                    None => MatchResult::Err,
+                };
+                match result {
                     MatchResult::Ok(end) => {
                         tokens.push(Token {
                             kind,
@@ -134,19 +505,64 @@ impl Syntax {
                             from: 0,
                             to: line.len() as _,
                         });
-                        return LineParseState::Unfinished(kind, state);
+                        return LineParseState::Unfinished(regions, kind, state);
                     }
                 }
+                regions
             }
-        }
+        };
 
-        while index < line.len() {
+        'tokens: while index < line.len() {
             let from = index;
             index += line[from..]
                 .bytes()
                 .take_while(u8::is_ascii_whitespace)
                 .count();
 
+            if let Some(frame) = regions.top() {
+                let region = &self.get(frame.parent_handle).regions[frame.region_index as usize];
+                if let MatchResult::Ok(end) = region.end.matches(line, index) {
+                    if end > index {
+                        regions.pop();
+                        index = end;
+                        tokens.push(Token {
+                            kind: TokenKind::Keyword,
+                            from: from as _,
+                            to: index as _,
+                        });
+                        continue;
+                    }
+                }
+            }
+
+            let syntax_handle = regions.current_syntax_handle(root_handle);
+            let syntax = self.get(syntax_handle);
+
+            for (i, region) in syntax.regions.iter().enumerate() {
+                let end = match region.start.matches(line, index) {
+                    MatchResult::Ok(end) if end > index => end,
+                    _ => continue,
+                };
+                let region_syntax_handle = match self.find_handle_by_name(&region.syntax_name) {
+                    Some(handle) => handle,
+                    None => continue,
+                };
+                let frame = SyntaxRegionFrame {
+                    parent_handle: syntax_handle,
+                    region_index: i as _,
+                    syntax_handle: region_syntax_handle,
+                };
+                if regions.push(frame) {
+                    index = end;
+                    tokens.push(Token {
+                        kind: TokenKind::Keyword,
+                        from: from as _,
+                        to: index as _,
+                    });
+                    continue 'tokens;
+                }
+            }
+
             let mut best_pattern_kind = TokenKind::Text;
             let mut max_end = index;
 
@@ -160,8 +576,13 @@ impl Syntax {
                 TokenKind::Text,
             ];
 
-            for kind in ALL_NON_WHITESPACE_TOKEN_KINDS {
-                let pattern = &self.rules[kind as usize];
+            // custom kinds come first so they win over builtin kinds matching the same text
+            let custom_rules = syntax.custom_rules.iter().map(|(kind, rule)| (*kind, rule));
+            let builtin_rules = ALL_NON_WHITESPACE_TOKEN_KINDS
+                .iter()
+                .copied()
+                .zip(syntax.rules.iter());
+            for (kind, pattern) in custom_rules.chain(builtin_rules) {
                 match pattern.matches(line, index) {
                     MatchResult::Ok(end) => {
                         if end > max_end {
@@ -176,7 +597,7 @@ impl Syntax {
                             from: from as _,
                             to: line.len() as _,
                         });
-                        return LineParseState::Unfinished(kind, state);
+                        return LineParseState::Unfinished(regions, kind, state);
                     }
                 }
             }
@@ -206,47 +627,7 @@ impl Syntax {
             });
         }
 
-        LineParseState::Finished
-    }
-}
-
-#[derive(Clone, Copy, Default, PartialEq, Eq)]
-pub struct SyntaxHandle(u32);
-
-pub struct SyntaxCollection {
-    syntaxes: Vec<Syntax>,
-}
-
-impl SyntaxCollection {
-    pub fn new() -> Self {
-        Self {
-            syntaxes: vec![Syntax::new()],
-        }
-    }
-
-    pub fn find_handle_by_path(&self, path: &str) -> Option<SyntaxHandle> {
-        for (i, syntax) in self.syntaxes.iter().enumerate().rev() {
-            if syntax.glob.matches(path) {
-                return Some(SyntaxHandle(i as _));
-            }
-        }
-
-        None
-    }
-
-    pub(crate) fn add_from_glob(&mut self, glob: &str) -> Result<(), InvalidGlobError> {
-        let mut syntax = Syntax::new();
-        syntax.set_glob(glob)?;
-        self.syntaxes.push(syntax);
-        Ok(())
-    }
-
-    pub(crate) fn get_last(&mut self) -> &mut Syntax {
-        self.syntaxes.last_mut().unwrap()
-    }
-
-    pub fn get(&self, handle: SyntaxHandle) -> &Syntax {
-        &self.syntaxes[handle.0 as usize]
+        LineParseState::Finished(regions)
     }
 }
 
@@ -344,7 +725,8 @@ impl HighlightedBuffer {
 
     pub fn highlight_dirty_lines(
         &mut self,
-        syntax: &Syntax,
+        syntaxes: &SyntaxCollection,
+        syntax_handle: SyntaxHandle,
         buffer: &BufferContent,
     ) -> HighlightResult {
         let buffer_lines = buffer.lines();
@@ -366,7 +748,7 @@ impl HighlightedBuffer {
 
         let mut previous_parse_state = match index.checked_sub(1) {
             Some(i) => self.lines[i as usize].parse_state,
-            None => LineParseState::Finished,
+            None => LineParseState::Finished(SyntaxRegionStack::default()),
         };
 
         let mut i = 0;
@@ -387,8 +769,12 @@ impl HighlightedBuffer {
                 let hline = &mut self.lines[index as usize];
 
                 let previous_state = hline.parse_state;
-                previous_parse_state =
-                    syntax.parse_line(bline, previous_parse_state, &mut hline.tokens);
+                previous_parse_state = syntaxes.parse_line(
+                    syntax_handle,
+                    bline,
+                    previous_parse_state,
+                    &mut hline.tokens,
+                );
                 hline.parse_state = previous_parse_state;
 
                 index += 1;
@@ -402,8 +788,9 @@ impl HighlightedBuffer {
                     return HighlightResult::Pending;
                 }
 
-                if previous_state == LineParseState::Finished
-                    && previous_parse_state == LineParseState::Finished
+                // only stop once the line ends exactly as before, in the same embedded regions
+                if previous_state == previous_parse_state
+                    && matches!(previous_parse_state, LineParseState::Finished(_))
                 {
                     break;
                 }
@@ -414,6 +801,131 @@ impl HighlightedBuffer {
         HighlightResult::Complete
     }
 
+    // writes the line parse state (with the embedded syntaxes it ends in) followed by its tokens
+    pub fn write_line_inspection(
+        &self,
+        syntaxes: &SyntaxCollection,
+        line_index: usize,
+        line: &str,
+        output: &mut String,
+    ) {
+        let _ = write!(output, "{}", line_index + 1);
+
+        let parse_state = match self.lines.get(line_index) {
+            Some(hline) if line_index < self.highlighted_len => hline.parse_state,
+            _ => LineParseState::Dirty,
+        };
+        let regions = match parse_state {
+            LineParseState::Dirty => {
+                output.push_str(" dirty\n");
+                return;
+            }
+            LineParseState::Finished(regions) => {
+                output.push_str(" finished");
+                regions
+            }
+            LineParseState::Unfinished(regions, kind, _) => {
+                output.push_str(" unfinished ");
+                output.push_str(syntaxes.token_kind_name(kind));
+                regions
+            }
+        };
+        for (i, frame) in regions.frames[..regions.len as usize].iter().enumerate() {
+            output.push_str(if i == 0 { " in " } else { " > " });
+            output.push_str(syntaxes.get(frame.syntax_handle).name());
+        }
+        output.push('\n');
+
+        for token in &self.lines[line_index].tokens {
+            let text = line
+                .get(token.from as usize..token.to as usize)
+                .unwrap_or("");
+            let _ = writeln!(
+                output,
+                "  {} {}..{} {:?}",
+                syntaxes.token_kind_name(token.kind),
+                token.from,
+                token.to,
+                text,
+            );
+        }
+    }
+
+    // the range (without leading whitespace) of the token at `position`, following it through
+    // the lines it continues into when it's an unfinished multiline token
+    pub fn token_range_at(
+        &self,
+        buffer: &BufferContent,
+        position: BufferPosition,
+    ) -> Option<(TokenKind, BufferRange)> {
+        fn unfinished_kind(line: &HighlightedLine) -> Option<TokenKind> {
+            match line.parse_state {
+                LineParseState::Unfinished(_, kind, _) => Some(kind),
+                _ => None,
+            }
+        }
+
+        let line_index = position.line_index as usize;
+        if line_index >= self.highlighted_len {
+            return None;
+        }
+
+        let lines = buffer.lines();
+        let column = position.column_byte_index;
+        let tokens = &self.lines[line_index].tokens;
+        let line_len = lines[line_index].as_str().len() as BufferPositionIndex;
+        let token_index = match tokens.iter().position(|t| t.contains(column)) {
+            Some(index) => index,
+            None if column >= line_len && !tokens.is_empty() => tokens.len() - 1,
+            None => return None,
+        };
+        let kind = tokens[token_index].kind;
+
+        let mut from_line_index = line_index;
+        let mut from_token = &tokens[token_index];
+        let mut is_first_token = token_index == 0;
+        while is_first_token && from_token.from == 0 && from_line_index > 0 {
+            let previous_line = &self.lines[from_line_index - 1];
+            if unfinished_kind(previous_line) != Some(kind) {
+                break;
+            }
+            from_line_index -= 1;
+            from_token = match previous_line.tokens.last() {
+                Some(token) => token,
+                None => break,
+            };
+            is_first_token = previous_line.tokens.len() == 1;
+        }
+        let from_line = lines[from_line_index].as_str();
+        let leading_whitespace_len = from_line[from_token.from as usize..from_token.to as usize]
+            .bytes()
+            .take_while(u8::is_ascii_whitespace)
+            .count();
+        let from = BufferPosition::line_col(
+            from_line_index as _,
+            from_token.from + leading_whitespace_len as BufferPositionIndex,
+        );
+
+        let mut to_line_index = line_index;
+        let mut to_token = &tokens[token_index];
+        let mut is_last_token = token_index == tokens.len() - 1;
+        while is_last_token
+            && unfinished_kind(&self.lines[to_line_index]) == Some(kind)
+            && to_line_index + 1 < self.highlighted_len
+        {
+            let next_line = &self.lines[to_line_index + 1];
+            match next_line.tokens.first() {
+                Some(token) if token.kind == kind && token.from == 0 => to_token = token,
+                _ => break,
+            }
+            to_line_index += 1;
+            is_last_token = next_line.tokens.len() == 1;
+        }
+        let to = BufferPosition::line_col(to_line_index as _, to_token.to);
+
+        Some((kind, BufferRange::between(from, to)))
+    }
+
     pub fn line_tokens(&self, line_index: usize) -> &[Token] {
         if line_index < self.highlighted_len {
             &self.lines[line_index].tokens
@@ -423,6 +935,69 @@ impl HighlightedBuffer {
     }
 }
 
+// evaluates the syntax definitions in `source` (written like `default_syntaxes.pepper`), highlights
+// `fixture` with the syntax named `syntax_name` and compares it to `expected` which is written
+// just like `syntax-inspect` output (without its first line). returns what did not match
+pub fn check_syntax_fixture(
+    source: &str,
+    syntax_name: &str,
+    fixture: &str,
+    expected: &str,
+) -> Result<(), String> {
+    let current_dir = env::current_dir().unwrap_or_default();
+    let mut ctx = EditorContext {
+        editor: Editor::new(current_dir, String::new()),
+        platform: Platform::default(),
+        clients: ClientManager::default(),
+        plugins: PluginCollection::default(),
+    };
+    if let Err(error) = CommandManager::eval(&mut ctx, None, "syntax source", source) {
+        return Err(format!("could not evaluate syntax source: {}", error));
+    }
+    let syntaxes = &ctx.editor.syntaxes;
+    let syntax_handle = match syntaxes.find_handle_by_name(syntax_name) {
+        Some(handle) => handle,
+        None => return Err(format!("no syntax named '{}'", syntax_name)),
+    };
+
+    let mut buffer = BufferContent::new();
+    let mut highlighted = HighlightedBuffer::new();
+    let range = buffer.insert_text(BufferPosition::zero(), fixture);
+    highlighted.insert_range(range);
+    while let HighlightResult::Pending =
+        highlighted.highlight_dirty_lines(syntaxes, syntax_handle, &buffer)
+    {}
+
+    let mut actual = String::new();
+    for (i, line) in buffer.lines().iter().enumerate() {
+        highlighted.write_line_inspection(syntaxes, i, line.as_str(), &mut actual);
+    }
+
+    let mut expected_lines = expected.lines();
+    for (i, actual_line) in actual.lines().enumerate() {
+        match expected_lines.next() {
+            Some(expected_line) if expected_line == actual_line => (),
+            expected_line => {
+                return Err(format!(
+                    "fixture mismatch at expected line {}\nexpected: {:?}\n  actual: {:?}\n\nwhole actual output:\n{}",
+                    i + 1,
+                    expected_line,
+                    actual_line,
+                    actual,
+                ))
+            }
+        }
+    }
+    if let Some(expected_line) = expected_lines.next() {
+        return Err(format!(
+            "fixture is missing expected line {:?}\n\nwhole actual output:\n{}",
+            expected_line, actual,
+        ));
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -456,12 +1031,20 @@ mod tests {
 
     #[test]
     fn no_syntax() {
-        let syntax = Syntax::new();
+        let syntaxes = SyntaxCollection::new();
         let mut tokens = Vec::new();
         let line = " fn main() ;  ";
-        let parse_state = syntax.parse_line(line, LineParseState::Finished, &mut tokens);
+        let parse_state = syntaxes.parse_line(
+            SyntaxHandle::default(),
+            line,
+            LineParseState::Finished(SyntaxRegionStack::default()),
+            &mut tokens,
+        );
 
-        assert_eq!(LineParseState::Finished, parse_state);
+        assert_eq!(
+            LineParseState::Finished(SyntaxRegionStack::default()),
+            parse_state
+        );
         assert_eq!(6, tokens.len());
         assert_token(" fn", TokenKind::Text, line, &tokens[0]);
         assert_token(" main", TokenKind::Text, line, &tokens[1]);
@@ -473,14 +1056,23 @@ mod tests {
 
     #[test]
     fn one_rule_syntax() {
-        let mut syntax = Syntax::new();
+        let mut syntaxes = SyntaxCollection::new();
+        let syntax = syntaxes.get_last();
         syntax.set_rule(TokenKind::Symbol, ";").unwrap();
 
         let mut tokens = Vec::new();
         let line = " fn main() ;  ";
-        let parse_state = syntax.parse_line(line, LineParseState::Finished, &mut tokens);
+        let parse_state = syntaxes.parse_line(
+            SyntaxHandle::default(),
+            line,
+            LineParseState::Finished(SyntaxRegionStack::default()),
+            &mut tokens,
+        );
 
-        assert_eq!(LineParseState::Finished, parse_state);
+        assert_eq!(
+            LineParseState::Finished(SyntaxRegionStack::default()),
+            parse_state
+        );
         assert_eq!(6, tokens.len());
         assert_token(" fn", TokenKind::Text, line, &tokens[0]);
         assert_token(" main", TokenKind::Text, line, &tokens[1]);
@@ -492,15 +1084,24 @@ mod tests {
 
     #[test]
     fn simple_syntax() {
-        let mut syntax = Syntax::new();
+        let mut syntaxes = SyntaxCollection::new();
+        let syntax = syntaxes.get_last();
         syntax.set_rule(TokenKind::Keyword, "fn").unwrap();
         syntax.set_rule(TokenKind::Symbol, "%(|%)").unwrap();
 
         let mut tokens = Vec::new();
         let line = " fn main() ;  ";
-        let parse_state = syntax.parse_line(line, LineParseState::Finished, &mut tokens);
+        let parse_state = syntaxes.parse_line(
+            SyntaxHandle::default(),
+            line,
+            LineParseState::Finished(SyntaxRegionStack::default()),
+            &mut tokens,
+        );
 
-        assert_eq!(LineParseState::Finished, parse_state);
+        assert_eq!(
+            LineParseState::Finished(SyntaxRegionStack::default()),
+            parse_state
+        );
         assert_eq!(6, tokens.len());
         assert_token(" fn", TokenKind::Keyword, line, &tokens[0]);
         assert_token(" main", TokenKind::Text, line, &tokens[1]);
@@ -512,22 +1113,66 @@ mod tests {
 
     #[test]
     fn beginning_anchor_syntax() {
-        let mut syntax = Syntax::new();
+        let mut syntaxes = SyntaxCollection::new();
+        let syntax = syntaxes.get_last();
         syntax.set_rule(TokenKind::Keyword, "^{%w}").unwrap();
 
         let mut tokens = Vec::new();
         let line = "first second";
-        let parse_state = syntax.parse_line(line, LineParseState::Finished, &mut tokens);
+        let parse_state = syntaxes.parse_line(
+            SyntaxHandle::default(),
+            line,
+            LineParseState::Finished(SyntaxRegionStack::default()),
+            &mut tokens,
+        );
 
-        assert_eq!(LineParseState::Finished, parse_state);
+        assert_eq!(
+            LineParseState::Finished(SyntaxRegionStack::default()),
+            parse_state
+        );
         assert_eq!(2, tokens.len());
         assert_token("first", TokenKind::Keyword, line, &tokens[0]);
         assert_token(" second", TokenKind::Text, line, &tokens[1]);
     }
 
+    #[test]
+    fn custom_token_kind_syntax() {
+        let mut syntaxes = SyntaxCollection::new();
+        let constant = syntaxes.add_token_kind("constant").unwrap();
+        let todo = syntaxes.add_token_kind("todo").unwrap();
+        assert_eq!(Some(constant), syntaxes.add_token_kind("constant"));
+        assert_eq!(None, syntaxes.add_token_kind("not valid"));
+        assert_eq!(Some(todo), syntaxes.find_token_kind("todo"));
+        assert_eq!(None, syntaxes.find_token_kind("undeclared"));
+
+        let syntax = syntaxes.get_last();
+        syntax.set_rule(TokenKind::Type, "%u{%w_}").unwrap();
+        syntax.set_rule(constant, "%u{%u%d_}%b").unwrap();
+        syntax.set_rule(todo, "TODO:{.$}").unwrap();
+
+        let mut tokens = Vec::new();
+        let line = "Type CONST TODO: later";
+        let parse_state = syntaxes.parse_line(
+            SyntaxHandle::default(),
+            line,
+            LineParseState::Finished(SyntaxRegionStack::default()),
+            &mut tokens,
+        );
+
+        assert!(matches!(
+            parse_state,
+            LineParseState::Unfinished(_, kind, _) if kind == todo
+        ));
+        assert_eq!(3, tokens.len());
+        assert_token("Type", TokenKind::Type, line, &tokens[0]);
+        assert_token(" CONST", constant, line, &tokens[1]);
+        assert_token(" TODO: later", todo, line, &tokens[2]);
+    }
+
     #[test]
     fn multiline_syntax() {
-        let mut syntax = Syntax::new();
+        let mut syntaxes = SyntaxCollection::new();
+        let syntax = syntaxes.get_last();
         syntax.set_rule(TokenKind::Comment, "/*{!(*/).$}").unwrap();
 
         let mut tokens = Vec::new();
@@ -535,25 +1180,35 @@ mod tests {
         let line1 = "only comment";
         let line2 = "still comment */ after";
 
-        let line0_kind = syntax.parse_line(line0, LineParseState::Finished, &mut tokens);
+        let line0_kind = syntaxes.parse_line(
+            SyntaxHandle::default(),
+            line0,
+            LineParseState::Finished(SyntaxRegionStack::default()),
+            &mut tokens,
+        );
         match line0_kind {
-            LineParseState::Unfinished(i, _) => assert_eq!(TokenKind::Comment, i),
+            LineParseState::Unfinished(_, i, _) => assert_eq!(TokenKind::Comment, i),
             _ => panic!("{:?}", line0_kind),
         }
         assert_eq!(2, tokens.len());
         assert_token("before", TokenKind::Text, line0, &tokens[0]);
         assert_token(" /* comment", TokenKind::Comment, line0, &tokens[1]);
 
-        let line1_kind = syntax.parse_line(line1, line0_kind, &mut tokens);
+        let line1_kind =
+            syntaxes.parse_line(SyntaxHandle::default(), line1, line0_kind, &mut tokens);
         match line1_kind {
-            LineParseState::Unfinished(i, _) => assert_eq!(TokenKind::Comment, i),
+            LineParseState::Unfinished(_, i, _) => assert_eq!(TokenKind::Comment, i),
             _ => panic!("{:?}", line1_kind),
         }
         assert_eq!(1, tokens.len());
         assert_token("only comment", TokenKind::Comment, line1, &tokens[0]);
 
-        let line2_kind = syntax.parse_line(line2, line1_kind, &mut tokens);
-        assert_eq!(LineParseState::Finished, line2_kind);
+        let line2_kind =
+            syntaxes.parse_line(SyntaxHandle::default(), line2, line1_kind, &mut tokens);
+        assert_eq!(
+            LineParseState::Finished(SyntaxRegionStack::default()),
+            line2_kind
+        );
         assert_eq!(2, tokens.len());
         assert_token("still comment */", TokenKind::Comment, line2, &tokens[0]);
         assert_token(" after", TokenKind::Text, line2, &tokens[1]);
@@ -561,7 +1216,8 @@ mod tests {
 
     #[test]
     fn editing_highlighted_buffer() {
-        let mut syntax = Syntax::new();
+        let mut syntaxes = SyntaxCollection::new();
+        let syntax = syntaxes.get_last();
         syntax.set_rule(TokenKind::Comment, "/*{!(*/).$}").unwrap();
         syntax.set_rule(TokenKind::String, "'{!'.$}").unwrap();
 
@@ -570,7 +1226,7 @@ mod tests {
 
         let range = buffer.insert_text(BufferPosition::zero(), "/*\n*/");
         highlighted.insert_range(range);
-        highlighted.highlight_dirty_lines(&syntax, &buffer);
+        highlighted.highlight_dirty_lines(&syntaxes, SyntaxHandle::default(), &buffer);
         assert_eq!(buffer.lines().len(), highlighted.lines.len());
 
         {
@@ -582,7 +1238,7 @@ mod tests {
 
         let range = buffer.insert_text(BufferPosition::line_col(1, 0), "'");
         highlighted.insert_range(range);
-        highlighted.highlight_dirty_lines(&syntax, &buffer);
+        highlighted.highlight_dirty_lines(&syntaxes, SyntaxHandle::default(), &buffer);
 
         {
             let mut tokens = highlighted_tokens(&highlighted);
@@ -594,7 +1250,8 @@ mod tests {
 
     #[test]
     fn highlight_range_after_unfinished_line() {
-        let mut syntax = Syntax::new();
+        let mut syntaxes = SyntaxCollection::new();
+        let syntax = syntaxes.get_last();
         syntax.set_rule(TokenKind::Comment, "/*{!(*/).$}").unwrap();
 
         let mut buffer = BufferContent::new();
@@ -602,7 +1259,7 @@ mod tests {
 
         let range = buffer.insert_text(BufferPosition::zero(), "/*\n\n\n*/");
         highlighted.insert_range(range);
-        highlighted.highlight_dirty_lines(&syntax, &buffer);
+        highlighted.highlight_dirty_lines(&syntaxes, SyntaxHandle::default(), &buffer);
         assert_eq!(buffer.lines().len(), highlighted.lines.len());
 
         let mut tokens = highlighted_tokens(&highlighted);
@@ -615,7 +1272,8 @@ mod tests {
 
     #[test]
     fn highlight_lines_after_unfinished_to_finished() {
-        let mut syntax = Syntax::new();
+        let mut syntaxes = SyntaxCollection::new();
+        let syntax = syntaxes.get_last();
         syntax.set_rule(TokenKind::Comment, "/*{!(*/).$}").unwrap();
 
         let mut buffer = BufferContent::new();
@@ -623,7 +1281,7 @@ mod tests {
 
         let range = buffer.insert_text(BufferPosition::zero(), "/*\n* /\n*/");
         highlighted.insert_range(range);
-        highlighted.highlight_dirty_lines(&syntax, &buffer);
+        highlighted.highlight_dirty_lines(&syntaxes, SyntaxHandle::default(), &buffer);
 
         let range = BufferRange::between(
             BufferPosition::line_col(1, 1),
@@ -631,17 +1289,23 @@ mod tests {
         );
         buffer.delete_range(range);
         highlighted.delete_range(range);
-        highlighted.highlight_dirty_lines(&syntax, &buffer);
+        highlighted.highlight_dirty_lines(&syntaxes, SyntaxHandle::default(), &buffer);
 
         let mut parse_states = highlighted.lines[..highlighted.highlighted_len]
             .iter()
             .map(|l| l.parse_state);
         assert!(matches!(
             parse_states.next(),
-            Some(LineParseState::Unfinished(_, _))
+            Some(LineParseState::Unfinished(_, _, _))
         ));
-        assert_eq!(Some(LineParseState::Finished), parse_states.next());
-        assert_eq!(Some(LineParseState::Finished), parse_states.next());
+        assert_eq!(
+            Some(LineParseState::Finished(SyntaxRegionStack::default())),
+            parse_states.next()
+        );
+        assert_eq!(
+            Some(LineParseState::Finished(SyntaxRegionStack::default())),
+            parse_states.next()
+        );
         assert_eq!(None, parse_states.next());
 
         {
@@ -656,7 +1320,8 @@ mod tests {
 
     #[test]
     fn highlight_lines_after_became_unfinished() {
-        let mut syntax = Syntax::new();
+        let mut syntaxes = SyntaxCollection::new();
+        let syntax = syntaxes.get_last();
         syntax.set_rule(TokenKind::Comment, "/*{!(*/).$}").unwrap();
 
         let mut buffer = BufferContent::new();
@@ -664,7 +1329,7 @@ mod tests {
 
         let range = buffer.insert_text(BufferPosition::zero(), "/ *\na\n*/");
         highlighted.insert_range(range);
-        highlighted.highlight_dirty_lines(&syntax, &buffer);
+        highlighted.highlight_dirty_lines(&syntaxes, SyntaxHandle::default(), &buffer);
 
         let range = BufferRange::between(
             BufferPosition::line_col(0, 1),
@@ -672,7 +1337,7 @@ mod tests {
         );
         buffer.delete_range(range);
         highlighted.delete_range(range);
-        highlighted.highlight_dirty_lines(&syntax, &buffer);
+        highlighted.highlight_dirty_lines(&syntaxes, SyntaxHandle::default(), &buffer);
 
         let mut tokens = highlighted_tokens(&highlighted);
         assert_next_token(&mut tokens, TokenKind::Comment, 0..2);
@@ -683,7 +1348,8 @@ mod tests {
 
     #[test]
     fn highlight_unfinished_lines_on_multiline_delete() {
-        let mut syntax = Syntax::new();
+        let mut syntaxes = SyntaxCollection::new();
+        let syntax = syntaxes.get_last();
         syntax.set_rule(TokenKind::Comment, "/*{!(*/).$}").unwrap();
 
         let mut buffer = BufferContent::new();
@@ -691,7 +1357,7 @@ mod tests {
 
         let range = buffer.insert_text(BufferPosition::zero(), "a\n/*\nb\nc*/");
         highlighted.insert_range(range);
-        highlighted.highlight_dirty_lines(&syntax, &buffer);
+        highlighted.highlight_dirty_lines(&syntaxes, SyntaxHandle::default(), &buffer);
         assert_eq!(buffer.lines().len(), highlighted.highlighted_len);
 
         {
@@ -706,7 +1372,7 @@ mod tests {
         let range = BufferRange::between(BufferPosition::zero(), BufferPosition::line_col(1, 1));
         buffer.delete_range(range);
         highlighted.delete_range(range);
-        highlighted.highlight_dirty_lines(&syntax, &buffer);
+        highlighted.highlight_dirty_lines(&syntaxes, SyntaxHandle::default(), &buffer);
         assert_eq!(buffer.lines().len(), highlighted.highlighted_len);
 
         {
@@ -719,4 +1385,326 @@ mod tests {
             assert_eq!(None, tokens.next());
         }
     }
+
+    #[test]
+    fn multiline_token_range() {
+        let mut syntaxes = SyntaxCollection::new();
+        let syntax = syntaxes.get_last();
+        syntax.set_rule(TokenKind::Comment, "/*{!(*/).$}").unwrap();
+
+        let mut buffer = BufferContent::new();
+        let mut highlighted = HighlightedBuffer::new();
+
+        let range = buffer.insert_text(BufferPosition::zero(), "a /* b\n\nc */ d");
+        highlighted.insert_range(range);
+        highlighted.highlight_dirty_lines(&syntaxes, SyntaxHandle::default(), &buffer);
+
+        let token_range_at = |line_index, column_byte_index| {
+            highlighted.token_range_at(
+                &buffer,
+                BufferPosition::line_col(line_index, column_byte_index),
+            )
+        };
+        let range = |from: (u32, u32), to: (u32, u32)| {
+            BufferRange::between(
+                BufferPosition::line_col(from.0 as _, from.1 as _),
+                BufferPosition::line_col(to.0 as _, to.1 as _),
+            )
+        };
+
+        let comment = Some((TokenKind::Comment, range((0, 2), (2, 4))));
+        assert_eq!(comment, token_range_at(0, 3));
+        assert_eq!(comment, token_range_at(1, 0));
+        assert_eq!(comment, token_range_at(2, 1));
+        assert_eq!(
+            Some((TokenKind::Text, range((0, 0), (0, 1)))),
+            token_range_at(0, 0)
+        );
+        assert_eq!(
+            Some((TokenKind::Text, range((2, 5), (2, 6)))),
+            token_range_at(2, 5)
+        );
+        assert_eq!(None, token_range_at(3, 0));
+    }
+
+    fn region_syntaxes() -> SyntaxCollection {
+        let mut syntaxes = SyntaxCollection::new();
+
+        syntaxes.add_from_glob("**/*.md").unwrap();
+        let syntax = syntaxes.get_last();
+        syntax.set_name("md");
+        syntax.add_region("code", "```code{.}", "```{.}").unwrap();
+
+        syntaxes.add_from_glob("**/*.code").unwrap();
+        let syntax = syntaxes.get_last();
+        syntax.set_name("code");
+        syntax.set_rule(TokenKind::Keyword, "fn").unwrap();
+        syntax.set_rule(TokenKind::Comment, "/*{!(*/).$}").unwrap();
+
+        syntaxes
+    }
+
+    #[test]
+    fn embedded_region_syntax() {
+        let syntaxes = region_syntaxes();
+        let handle = syntaxes.find_handle_by_name("md").unwrap();
+
+        let mut tokens = Vec::new();
+        let mut parse_state = LineParseState::Finished(SyntaxRegionStack::default());
+        let mut parse_line = |line, tokens: &mut Vec<Token>| {
+            parse_state = syntaxes.parse_line(handle, line, parse_state, tokens);
+            parse_state
+        };
+
+        let line = "fn";
+        let state = parse_line(line, &mut tokens);
+        assert_eq!(
+            LineParseState::Finished(SyntaxRegionStack::default()),
+            state
+        );
+        assert_eq!(1, tokens.len());
+        assert_token("fn", TokenKind::Text, line, &tokens[0]);
+
+        let line = "```code";
+        let state = parse_line(line, &mut tokens);
+        assert!(matches!(state, LineParseState::Finished(regions) if regions.len == 1));
+        assert_eq!(1, tokens.len());
+        assert_token("```code", TokenKind::Keyword, line, &tokens[0]);
+
+        let line = "fn /* comment";
+        let state = parse_line(line, &mut tokens);
+        assert!(matches!(
+            state,
+            LineParseState::Unfinished(regions, TokenKind::Comment, _) if regions.len == 1
+        ));
+        assert_eq!(2, tokens.len());
+        assert_token("fn", TokenKind::Keyword, line, &tokens[0]);
+        assert_token(" /* comment", TokenKind::Comment, line, &tokens[1]);
+
+        let line = "``` */ fn";
+        let state = parse_line(line, &mut tokens);
+        assert!(matches!(state, LineParseState::Finished(regions) if regions.len == 1));
+        assert_eq!(2, tokens.len());
+        assert_token("``` */", TokenKind::Comment, line, &tokens[0]);
+        assert_token(" fn", TokenKind::Keyword, line, &tokens[1]);
+
+        let line = "```";
+        let state = parse_line(line, &mut tokens);
+        assert_eq!(
+            LineParseState::Finished(SyntaxRegionStack::default()),
+            state
+        );
+        assert_eq!(1, tokens.len());
+        assert_token("```", TokenKind::Keyword, line, &tokens[0]);
+
+        let line = "fn";
+        let state = parse_line(line, &mut tokens);
+        assert_eq!(
+            LineParseState::Finished(SyntaxRegionStack::default()),
+            state
+        );
+        assert_eq!(1, tokens.len());
+        assert_token("fn", TokenKind::Text, line, &tokens[0]);
+    }
+
+    #[test]
+    fn highlight_lines_after_region_end_changed() {
+        let syntaxes = region_syntaxes();
+        let handle = syntaxes.find_handle_by_name("md").unwrap();
+
+        let mut buffer = BufferContent::new();
+        let mut highlighted = HighlightedBuffer::new();
+
+        let range = buffer.insert_text(BufferPosition::zero(), "```code\nfn\n```\nfn");
+        highlighted.insert_range(range);
+        highlighted.highlight_dirty_lines(&syntaxes, handle, &buffer);
+
+        {
+            let mut tokens = highlighted_tokens(&highlighted);
+            assert_next_token(&mut tokens, TokenKind::Keyword, 0..7);
+            assert_next_token(&mut tokens, TokenKind::Keyword, 0..2);
+            assert_next_token(&mut tokens, TokenKind::Keyword, 0..3);
+            assert_next_token(&mut tokens, TokenKind::Text, 0..2);
+            assert_eq!(None, tokens.next());
+        }
+
+        let range = BufferRange::between(
+            BufferPosition::line_col(2, 0),
+            BufferPosition::line_col(2, 3),
+        );
+        buffer.delete_range(range);
+        highlighted.delete_range(range);
+        highlighted.highlight_dirty_lines(&syntaxes, handle, &buffer);
+
+        {
+            let mut tokens = highlighted_tokens(&highlighted);
+            assert_next_token(&mut tokens, TokenKind::Keyword, 0..7);
+            assert_next_token(&mut tokens, TokenKind::Keyword, 0..2);
+            assert_next_token(&mut tokens, TokenKind::Keyword, 0..2);
+            assert_eq!(None, tokens.next());
+        }
+
+        let range = buffer.insert_text(BufferPosition::line_col(2, 0), "```");
+        highlighted.insert_range(range);
+        highlighted.highlight_dirty_lines(&syntaxes, handle, &buffer);
+
+        {
+            let mut tokens = highlighted_tokens(&highlighted);
+            assert_next_token(&mut tokens, TokenKind::Keyword, 0..7);
+            assert_next_token(&mut tokens, TokenKind::Keyword, 0..2);
+            assert_next_token(&mut tokens, TokenKind::Keyword, 0..3);
+            assert_next_token(&mut tokens, TokenKind::Text, 0..2);
+            assert_eq!(None, tokens.next());
+        }
+    }
+
+    #[test]
+    fn lua_fixture() {
+        let result = check_syntax_fixture(
+            crate::DEFAULT_SYNTAXES.content,
+            "lua",
+            include_str!("syntax/fixtures/lua.lua"),
+            include_str!("syntax/fixtures/lua.lua.tokens"),
+        );
+        if let Err(error) = result {
+            panic!("{}", error);
+        }
+    }
+
+    #[test]
+    fn custom_token_kinds_must_be_declared() {
+        let syntax = "syntax \"**/*.txt\"\nsyntax name txt\n";
+        let check = |source: &str| {
+            let source = [syntax, source].concat();
+            check_syntax_fixture(
+                &source,
+                "txt",
+                "a CONST",
+                "1 finished\n  texts 0..1 \"a\"\n  constants 1..7 \" CONST\"\n",
+            )
+        };
+
+        assert!(check("syntax constants \"%u{%u%d_}%b\"").is_err());
+        assert!(check("syntax kind keywords").is_err());
+        assert!(check("color token_constants\nsyntax kind constants").is_err());
+        for source in [
+            "syntax kind constants\nsyntax constants \"%u{%u%d_}%b\"",
+            "color token_constants d3869b\nsyntax constants \"%u{%u%d_}%b\"",
+        ] {
+            if let Err(error) = check(source) {
+                panic!("{}", error);
+            }
+        }
+    }
+
+    #[test]
+    fn indentation_patterns() {
+        let mut syntax = Syntax::new();
+        assert!(!syntax.indents("if a then"));
+        assert_eq!(None, syntax.dedent_match_end("end"));
+
+        syntax
+            .set_indent("{!(then$)!(do$).}|{!(function%b).}{(end%))!(%)$).}")
+            .unwrap();
+        syntax.set_dedent("end%b|else%b").unwrap();
+
+        assert!(syntax.indents("if a then"));
+        assert!(syntax.indents("    for i = 1, 3 do"));
+        assert!(syntax.indents("\tfunction f()"));
+        assert!(syntax.indents("local function f(a, b)"));
+        assert!(syntax.indents("local f = function()"));
+        assert!(syntax.indents("table.sort(t, function(a, b)"));
+        assert!(!syntax.indents("if a then return end"));
+        assert!(!syntax.indents("function f() return 1 end"));
+        assert!(!syntax.indents("pcall(function() return 1 end)"));
+        assert!(!syntax.indents("f()"));
+        assert!(!syntax.indents(""));
+
+        assert!(syntax.dedents("end"));
+        assert!(syntax.dedents("  else"));
+        assert!(!syntax.dedents("ending()"));
+        assert!(!syntax.dedents("x = end"));
+        assert_eq!(Some(7), syntax.dedent_match_end("    end"));
+        assert_eq!(Some(4), syntax.dedent_match_end("\tend)"));
+        assert_eq!(None, syntax.dedent_match_end("\tends"));
+    }
+
+    #[test]
+    fn modeline_syntax_name() {
+        assert_eq!(None, parse_modeline_syntax_name("fn main() {}"));
+        assert_eq!(
+            Some("python"),
+            parse_modeline_syntax_name("# vim: set ft=python:")
+        );
+        assert_eq!(
+            Some("lua"),
+            parse_modeline_syntax_name("-- vim: ts=4 filetype=lua")
+        );
+        assert_eq!(Some("sh"), parse_modeline_syntax_name("# vi:syntax=sh"));
+        assert_eq!(None, parse_modeline_syntax_name("# avim: ft=sh"));
+        assert_eq!(None, parse_modeline_syntax_name("# vim: ts=4"));
+        assert_eq!(Some("ruby"), parse_modeline_syntax_name("# -*- ruby -*-"));
+        assert_eq!(
+            Some("lisp"),
+            parse_modeline_syntax_name(";; -*- mode: lisp; coding: utf-8 -*-")
+        );
+        assert_eq!(None, parse_modeline_syntax_name("# -*- coding: utf-8 -*-"));
+    }
+
+    #[test]
+    fn find_syntax_handle() {
+        let mut syntaxes = SyntaxCollection::new();
+        syntaxes.add_from_glob("**/*.py").unwrap();
+        syntaxes.get_last().set_name("python");
+        syntaxes
+            .get_last()
+            .set_first_line("#%!{!(python).}")
+            .unwrap();
+        syntaxes.add_from_glob("**/*.sh").unwrap();
+        syntaxes.get_last().set_name("sh");
+        syntaxes
+            .get_last()
+            .set_first_line(
+                "#%!{!(/sh$)!(/sh )!(/bash$)!(/bash )!( sh$)!( sh )!( bash$)!( bash ).}",
+            )
+            .unwrap();
+
+        let python = syntaxes.find_handle_by_name("python");
+        let sh = syntaxes.find_handle_by_name("sh");
+        assert!(python.is_some());
+        assert!(sh.is_some());
+        assert!(python != sh);
+        assert_eq!(None, syntaxes.find_handle_by_name(""));
+        assert_eq!(None, syntaxes.find_handle_by_name("rust"));
+
+        let mut content = BufferContent::new();
+        assert_eq!(None, syntaxes.find_handle("bin/deploy", &content));
+        assert_eq!(python, syntaxes.find_handle("main.py", &content));
+
+        content.insert_text(BufferPosition::zero(), "#!/usr/bin/env python3\n");
+        assert_eq!(python, syntaxes.find_handle("bin/deploy", &content));
+        assert_eq!(sh, syntaxes.find_handle("run.sh", &content));
+
+        let position = BufferPosition::line_col(1, 0);
+        content.insert_text(position, "\n\n\n\n\n\n\n\n\n\n# vim: ft=sh");
+        assert_eq!(sh, syntaxes.find_handle("main.py", &content));
+        content.insert_text(position, "\n");
+        assert_eq!(sh, syntaxes.find_handle("main.py", &content));
+        let position = content.end();
+        content.insert_text(position, "\n\n\n\n\n");
+        assert_eq!(python, syntaxes.find_handle("main.py", &content));
+
+        for (first_line, handle) in [
+            ("#!/bin/sh", sh),
+            ("#!/bin/bash -e", sh),
+            ("#!/usr/bin/env sh", sh),
+            ("#!/usr/bin/env bash", sh),
+            ("#!/home/shaun/bin/perl", None),
+            ("#!/bin/shellcheck", None),
+        ] {
+            let mut content = BufferContent::new();
+            content.insert_text(BufferPosition::zero(), first_line);
+            assert_eq!(handle, syntaxes.find_handle("bin/deploy", &content));
+        }
+    }
 }
@@ -0,0 +1,13 @@
+-- computes a factorial
+local function factorial(n)
+    if n <= 1 then
+        return 1
+    end
+    return n * factorial(n - 1)
+end
+
+--[[ TODO: memoize
+the results ]]
+local text = [[multiline
+string]] .. "!"
+print(factorial(5), text)
@@ -1,3 +1,5 @@
+use crate::syntax::TokenKind;
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Color(pub u8, pub u8, pub u8);
 
@@ -24,6 +26,7 @@ macro_rules! theme_colors {
 
         pub struct Theme {
             $(pub $color: Color,)*
+            custom_token_colors: Vec<Option<Color>>,
         }
 
         impl Theme {
@@ -48,6 +51,14 @@ theme_colors! {
     inactive_cursor,
     statusbar_active_background,
     statusbar_inactive_background,
+    gutter_background,
+    gutter_line_number,
+    gutter_active_line_number,
+    gutter_breakpoint,
+    gutter_lint,
+    gutter_vcs_added,
+    gutter_vcs_modified,
+    gutter_vcs_deleted,
 
     token_whitespace,
     token_text,
@@ -59,6 +70,36 @@ theme_colors! {
     token_literal,
 }
 
+impl Theme {
+    // custom token kinds without a color fall back to `token_text`
+    pub fn token_color(&self, kind: TokenKind) -> Color {
+        match kind {
+            TokenKind::Keyword => self.token_keyword,
+            TokenKind::Type => self.token_type,
+            TokenKind::Symbol => self.token_symbol,
+            TokenKind::Literal => self.token_literal,
+            TokenKind::String => self.token_string,
+            TokenKind::Comment => self.token_comment,
+            TokenKind::Text => self.token_text,
+            TokenKind::Whitespace => self.token_whitespace,
+            TokenKind::Custom(index) => match self.custom_token_colors.get(index as usize) {
+                Some(&Some(color)) => color,
+                _ => self.token_text,
+            },
+        }
+    }
+
+    pub fn set_custom_token_color(&mut self, kind: TokenKind, color: Color) {
+        if let TokenKind::Custom(index) = kind {
+            let index = index as usize;
+            if self.custom_token_colors.len() <= index {
+                self.custom_token_colors.resize(index + 1, None);
+            }
+            self.custom_token_colors[index] = Some(color);
+        }
+    }
+}
+
 impl Default for Theme {
     fn default() -> Self {
         gruvbox_theme()
@@ -77,6 +118,14 @@ pub fn gruvbox_theme() -> Theme {
         inactive_cursor: Color::from_u32(0x504945),
         statusbar_active_background: Color::from_u32(0x504945),
         statusbar_inactive_background: Color::from_u32(0x282828),
+        gutter_background: Color::from_u32(0x1d2021),
+        gutter_line_number: Color::from_u32(0x665c54),
+        gutter_active_line_number: Color::from_u32(0xfabd2f),
+        gutter_breakpoint: Color::from_u32(0xfb4934),
+        gutter_lint: Color::from_u32(0xfe8019),
+        gutter_vcs_added: Color::from_u32(0xb8bb26),
+        gutter_vcs_modified: Color::from_u32(0x83a598),
+        gutter_vcs_deleted: Color::from_u32(0xfb4934),
 
         token_whitespace: Color::from_u32(0x504945),
         token_text: Color::from_u32(0xebdbb2),
@@ -86,5 +135,7 @@ pub fn gruvbox_theme() -> Theme {
         token_symbol: Color::from_u32(0xa89984),
         token_string: Color::from_u32(0xb8bb26),
         token_literal: Color::from_u32(0xd3869b),
+
+        custom_token_colors: Vec::new(),
     }
 }
@@ -1,15 +1,17 @@
-use std::{io, iter};
+use std::{io, iter, ops::Range};
 
 use crate::{
-    buffer::CharDisplayDistances,
+    buffer::{char_display_len, char_to_raw_byte, DisplayLen},
     buffer_position::{BufferPosition, BufferPositionIndex, BufferRange},
     buffer_view::{BufferViewHandle, CursorMovementKind},
+    config::{Config, LineNumbers, WrapMode},
     cursor::Cursor,
     editor::Editor,
     editor_utils::{LoggerStatusBarDisplay, REGISTER_READLINE_INPUT, REGISTER_READLINE_PROMPT},
     mode::ModeKind,
     syntax::{Token, TokenKind},
-    theme::Color,
+    theme::{Color, Theme},
+    vcs::VcsLineChange,
 };
 
 pub static ENTER_ALTERNATE_BUFFER_CODE: &[u8] = b"\x1b[?1049h";
@@ -65,12 +67,238 @@ pub fn set_not_underlined(buf: &mut Vec<u8>) {
 
 pub struct RenderContext<'a> {
     pub editor: &'a Editor,
+    pub config: &'a Config,
     pub status_bar_display: &'a LoggerStatusBarDisplay<'a, 'a>,
     pub viewport_size: (u16, u16),
     pub scroll: BufferPositionIndex,
+    pub horizontal_scroll: BufferPositionIndex,
     pub has_focus: bool,
 }
 
+// the gutter is made of a marker column, the line number and a space separating it from the text.
+// without line numbers, only the marker column and the space are drawn if vcs markers are enabled
+pub fn gutter_width(
+    line_numbers: LineNumbers,
+    vcs_markers: bool,
+    line_count: usize,
+    viewport_width: usize,
+) -> usize {
+    let width = if line_numbers != LineNumbers::None {
+        let mut digit_count = 1;
+        let mut n = line_count;
+        while n >= 10 {
+            n /= 10;
+            digit_count += 1;
+        }
+        1 + digit_count.max(2) + 1
+    } else if vcs_markers {
+        2
+    } else {
+        return 0;
+    };
+
+    if width * 2 < viewport_width {
+        width
+    } else {
+        0
+    }
+}
+
+fn gutter_line_number(
+    line_numbers: LineNumbers,
+    line_index: usize,
+    active_line_index: usize,
+) -> usize {
+    match line_numbers {
+        LineNumbers::Relative => line_index.abs_diff(active_line_index),
+        LineNumbers::Hybrid if line_index != active_line_index => {
+            line_index.abs_diff(active_line_index)
+        }
+        _ => line_index + 1,
+    }
+}
+
+// how buffer lines are split into screen rows.
+// it's shared between drawing and scrolling so that they always agree on line heights
+#[derive(Clone, Copy)]
+pub struct LineLayout {
+    pub wrap: WrapMode,
+    pub wrap_indentation: bool,
+    pub tab_size: u8,
+    pub gutter_width: usize,
+    pub text_width: usize,
+}
+impl LineLayout {
+    pub fn new(config: &Config, line_count: usize, viewport_width: usize) -> Self {
+        let gutter_width = gutter_width(
+            config.line_numbers,
+            config.vcs_markers,
+            line_count,
+            viewport_width,
+        );
+        Self {
+            wrap: config.wrap,
+            wrap_indentation: config.wrap_indentation,
+            tab_size: config.tab_size.max(1),
+            gutter_width,
+            text_width: (viewport_width - gutter_width).max(1),
+        }
+    }
+
+    pub fn rows<'a>(&self, line: &'a str) -> LineRows<'a> {
+        let mut indicator_len = (self.wrap == WrapMode::Word) as usize;
+        let mut indentation_len = 0;
+        if self.wrap_indentation && self.wrap != WrapMode::None {
+            for c in line.chars() {
+                match c {
+                    ' ' => indentation_len += 1,
+                    '\t' => indentation_len += self.tab_size as usize,
+                    _ => break,
+                }
+            }
+        }
+
+        // continuation rows always keep at least half of their width for text
+        if (indentation_len + indicator_len) * 2 > self.text_width {
+            indentation_len = 0;
+        }
+        if indicator_len * 2 > self.text_width {
+            indicator_len = 0;
+        }
+
+        LineRows {
+            layout: *self,
+            line,
+            row_start: 0,
+            continuation_indentation_len: indentation_len,
+            continuation_indicator: indicator_len > 0,
+            done: false,
+        }
+    }
+
+    pub fn line_height(&self, line: &str, display_len: DisplayLen) -> usize {
+        if self.wrap == WrapMode::None || display_len.total_len(self.tab_size) < self.text_width {
+            1
+        } else {
+            self.rows(line).count()
+        }
+    }
+}
+
+// yields the byte range of each screen row of a line.
+// the end of the line takes a column (where a cursor past the last char is drawn)
+// so a line that exactly fills its last row gets an extra empty row
+pub struct LineRows<'a> {
+    layout: LineLayout,
+    line: &'a str,
+    row_start: usize,
+    pub continuation_indentation_len: usize,
+    pub continuation_indicator: bool,
+    done: bool,
+}
+impl<'a> LineRows<'a> {
+    pub fn continuation_prefix_len(&self) -> usize {
+        self.continuation_indentation_len + self.continuation_indicator as usize
+    }
+}
+impl<'a> Iterator for LineRows<'a> {
+    type Item = Range<usize>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let start = self.row_start;
+        if self.layout.wrap == WrapMode::None {
+            self.done = true;
+            return Some(start..self.line.len());
+        }
+
+        let width = if start == 0 {
+            self.layout.text_width
+        } else {
+            self.layout.text_width - self.continuation_prefix_len()
+        };
+
+        let mut x = 0;
+        let mut word_start_index = start;
+        for (i, c) in self.line[start..].char_indices() {
+            let i = start + i;
+            let len = match c {
+                '\t' => self.layout.tab_size as usize,
+                c => char_display_len(c) as usize,
+            };
+
+            if x + len > width && i > start {
+                let end = match self.layout.wrap {
+                    WrapMode::Word if c != ' ' && c != '\t' && word_start_index > start => {
+                        word_start_index
+                    }
+                    _ => i,
+                };
+                self.row_start = end;
+                return Some(start..end);
+            }
+
+            x += len;
+            if c == ' ' || c == '\t' {
+                word_start_index = i + 1;
+            }
+        }
+
+        let end = self.line.len();
+        if x + 1 > width && end > start {
+            self.row_start = end;
+        } else {
+            self.done = true;
+        }
+        Some(start..end)
+    }
+}
+
+struct GutterLine {
+    number: usize,
+    is_active: bool,
+    marker: Option<(u8, Color)>,
+}
+
+fn draw_gutter(theme: &Theme, buf: &mut Vec<u8>, width: usize, line: Option<GutterLine>) {
+    set_not_underlined(buf);
+    set_background_color(buf, theme.gutter_background);
+
+    match line {
+        Some(line) => {
+            use io::Write;
+
+            match line.marker {
+                Some((marker, color)) => {
+                    set_foreground_color(buf, color);
+                    buf.push(marker);
+                }
+                None => buf.push(b' '),
+            }
+
+            if width <= 2 {
+                buf.push(b' ');
+                return;
+            }
+
+            let number_color = if line.is_active {
+                theme.gutter_active_line_number
+            } else {
+                theme.gutter_line_number
+            };
+            set_foreground_color(buf, number_color);
+            let _ = write!(buf, "{:>width$} ", line.number, width = width - 2);
+        }
+        None => {
+            for _ in 0..width {
+                buf.push(b' ');
+            }
+        }
+    }
+}
+
 pub fn draw(ctx: &RenderContext, buffer_view_handle: Option<BufferViewHandle>, buf: &mut Vec<u8>) {
     draw_buffer_view(ctx, buffer_view_handle, buf);
     draw_picker(ctx, buf);
@@ -104,7 +332,7 @@ fn draw_empty_view(ctx: &RenderContext, buf: &mut Vec<u8>) {
             .editor
             .picker
             .len()
-            .min(ctx.editor.config.picker_max_height as _);
+            .min(ctx.config.picker_max_height as _);
         margin_bottom.saturating_sub(picker_height)
     } else {
         margin_bottom
@@ -112,7 +340,6 @@ fn draw_empty_view(ctx: &RenderContext, buf: &mut Vec<u8>) {
 
     let mut visual_empty = [0; 4];
     let visual_empty = ctx
-        .editor
         .config
         .visual_empty
         .encode_utf8(&mut visual_empty)
@@ -142,6 +369,22 @@ fn draw_empty_view(ctx: &RenderContext, buf: &mut Vec<u8>) {
     }
 }
 
+fn draw_wrap_prefix(
+    theme: &Theme,
+    buf: &mut Vec<u8>,
+    rows: &LineRows,
+    visual_wrap: &[u8],
+) -> usize {
+    for _ in 0..rows.continuation_indentation_len {
+        buf.push(b' ');
+    }
+    if rows.continuation_indicator {
+        set_foreground_color(buf, theme.token_whitespace);
+        buf.extend_from_slice(visual_wrap);
+    }
+    rows.continuation_prefix_len()
+}
+
 fn draw_buffer_view(
     ctx: &RenderContext,
     buffer_view_handle: Option<BufferViewHandle>,
@@ -160,8 +403,6 @@ fn draw_buffer_view(
     let cursors = &buffer_view.cursors[..];
     let active_line_index = buffer_view.cursors.main_cursor().position.line_index as usize;
 
-    let tab_size = ctx.editor.config.tab_size.max(1);
-
     let draw_width = ctx.viewport_size.0 as usize;
     let draw_height = ctx.viewport_size.1.saturating_sub(1);
     let draw_height = if ctx.has_focus {
@@ -169,7 +410,7 @@ fn draw_buffer_view(
             .editor
             .picker
             .len()
-            .min(ctx.editor.config.picker_max_height as _);
+            .min(ctx.config.picker_max_height as _);
         draw_height.saturating_sub(picker_height as _)
     } else {
         draw_height
@@ -190,6 +431,11 @@ fn draw_buffer_view(
     let cursors_end_index = cursors.len().saturating_sub(1);
 
     let buffer_content = buffer.content();
+    let layout = LineLayout::new(ctx.config, buffer_content.lines().len(), draw_width);
+    let tab_size = layout.tab_size;
+    let gutter_width = layout.gutter_width;
+    let text_width = layout.text_width;
+    let horizontal_scroll = ctx.horizontal_scroll as usize;
     let highlighted_buffer = buffer.highlighted();
     let search_ranges = buffer.search_ranges();
     let search_ranges_end_index = search_ranges.len().saturating_sub(1);
@@ -202,27 +448,22 @@ fn draw_buffer_view(
 
     let mut scroll_offset = BufferPosition::zero();
     let mut scroll_padding_top = ctx.scroll as usize;
-    for (line_index, display_len) in buffer_content.line_display_lens().iter().enumerate() {
+    for (line_index, display_len) in buffer_content.lines().display_lens(..).enumerate() {
         scroll_offset.line_index = line_index as _;
 
         if scroll_padding_top == 0 {
             break;
         }
 
-        let line_height = 1 + display_len.total_len(tab_size) / draw_width;
+        let line = buffer_content.lines()[line_index].as_str();
+        let line_height = layout.line_height(line, *display_len);
         if line_height <= scroll_padding_top {
             scroll_padding_top -= line_height;
             continue;
         }
 
-        let line = buffer_content.lines()[line_index].as_str();
-        let target_display_len = (scroll_padding_top * draw_width) as _;
-        for d in CharDisplayDistances::new(line, tab_size) {
-            if d.distance >= target_display_len {
-                let index = d.char_index as usize + d.char.len_utf8();
-                scroll_offset.column_byte_index = index as _;
-                break;
-            }
+        if let Some(row) = layout.rows(line).nth(scroll_padding_top) {
+            scroll_offset.column_byte_index = row.start as _;
         }
 
         break;
@@ -279,7 +520,6 @@ fn draw_buffer_view(
 
     let mut visual_empty = [0; 4];
     let visual_empty = ctx
-        .editor
         .config
         .visual_empty
         .encode_utf8(&mut visual_empty)
@@ -287,7 +527,6 @@ fn draw_buffer_view(
 
     let mut visual_space = [0; 4];
     let visual_space = ctx
-        .editor
         .config
         .visual_space
         .encode_utf8(&mut visual_space)
@@ -295,7 +534,6 @@ fn draw_buffer_view(
 
     let mut visual_tab_first = [0; 4];
     let visual_tab_first = ctx
-        .editor
         .config
         .visual_tab_first
         .encode_utf8(&mut visual_tab_first)
@@ -303,12 +541,18 @@ fn draw_buffer_view(
 
     let mut visual_tab_repeat = [0; 4];
     let visual_tab_repeat = ctx
-        .editor
         .config
         .visual_tab_repeat
         .encode_utf8(&mut visual_tab_repeat)
         .as_bytes();
 
+    let mut visual_wrap = [0; 4];
+    let visual_wrap = ctx
+        .config
+        .visual_wrap
+        .encode_utf8(&mut visual_wrap)
+        .as_bytes();
+
     let mut lines_drawn_count = 0;
     for (line_index, line) in buffer_content
         .lines()
@@ -316,23 +560,26 @@ fn draw_buffer_view(
         .enumerate()
         .skip(scroll_offset.line_index as _)
     {
-        #[derive(Clone, Copy, PartialEq, Eq)]
-        enum DrawState {
-            Token(TokenKind),
-            Selection(TokenKind),
-            Highlight,
-            Cursor,
-        }
-
         if lines_drawn_count == draw_height {
             break;
         }
         lines_drawn_count += 1;
 
-        let line = &line.as_str()[scroll_offset.column_byte_index as usize..];
-        let mut draw_state = DrawState::Token(TokenKind::Text);
+        let line = line.as_str();
+        let mut rows = layout.rows(line);
+        let mut row = rows.next().unwrap_or(0..0);
+        while row.start < scroll_offset.column_byte_index as usize {
+            match rows.next() {
+                Some(next_row) => row = next_row,
+                None => break,
+            }
+        }
+        let first_row_start = row.start;
+        scroll_offset.column_byte_index = 0;
+
         let mut was_inside_lint_range = false;
         let mut x = 0;
+        let mut column = 0;
         let mut last_line_token = Token::default();
         let mut line_tokens = highlighted_buffer.line_tokens(line_index).iter();
 
@@ -352,14 +599,87 @@ fn draw_buffer_view(
             ctx.editor.theme.normal_background
         };
 
+        if gutter_width > 0 {
+            let gutter_line = if first_row_start > 0 {
+                None
+            } else {
+                let number =
+                    gutter_line_number(ctx.config.line_numbers, line_index, active_line_index);
+                let marker = if inside_breakpoint_line {
+                    Some((b'*', ctx.editor.theme.gutter_breakpoint))
+                } else if lints.iter().any(|l| {
+                    l.range.from.line_index as usize <= line_index
+                        && line_index <= l.range.to.line_index as usize
+                }) {
+                    Some((b'!', ctx.editor.theme.gutter_lint))
+                } else {
+                    match buffer.vcs_diff().line_change(line_index) {
+                        Some(VcsLineChange::Added) => {
+                            Some((b'+', ctx.editor.theme.gutter_vcs_added))
+                        }
+                        Some(VcsLineChange::Modified) => {
+                            Some((b'~', ctx.editor.theme.gutter_vcs_modified))
+                        }
+                        Some(VcsLineChange::Deleted) => {
+                            Some((b'_', ctx.editor.theme.gutter_vcs_deleted))
+                        }
+                        None => None,
+                    }
+                };
+                Some(GutterLine {
+                    number,
+                    is_active: line_index == active_line_index,
+                    marker,
+                })
+            };
+            draw_gutter(&ctx.editor.theme, buf, gutter_width, gutter_line);
+        }
+
         set_background_color(buf, background_color);
+        if first_row_start > 0 {
+            x = draw_wrap_prefix(&ctx.editor.theme, buf, &rows, visual_wrap);
+        }
         set_foreground_color(buf, ctx.editor.theme.token_text);
+        let mut current_colors = (background_color, ctx.editor.theme.token_text);
+
+        let chars = line[first_row_start..].char_indices();
+        'chars: for (char_index, c) in chars.chain(iter::once((line.len() - first_row_start, '\n')))
+        {
+            let char_index = char_index + first_row_start;
+            if char_index >= row.end {
+                if let Some(next_row) = rows.next() {
+                    row = next_row;
+
+                    if x < text_width {
+                        set_background_color(buf, background_color);
+                        clear_until_new_line(buf);
+                    }
+
+                    lines_drawn_count += 1;
+                    if lines_drawn_count > draw_height {
+                        lines_drawn_count = draw_height;
+                        break 'chars;
+                    }
+
+                    move_cursor_to_next_line(buf);
+                    if gutter_width > 0 {
+                        draw_gutter(&ctx.editor.theme, buf, gutter_width, None);
+                    } else {
+                        set_not_underlined(buf);
+                    }
+                    set_background_color(buf, background_color);
+                    x = draw_wrap_prefix(&ctx.editor.theme, buf, &rows, visual_wrap);
+                    current_colors = (background_color, ctx.editor.theme.token_whitespace);
+                    if was_inside_lint_range {
+                        set_underlined(buf);
+                    }
+                }
+            }
 
-        for (char_index, c) in line.char_indices().chain(iter::once((line.len(), '\n'))) {
-            let char_index = char_index + scroll_offset.column_byte_index as usize;
             let char_position = BufferPosition::line_col(line_index as _, char_index as _);
 
-            let token_kind = if c.is_ascii_whitespace() {
+            let raw_byte = char_to_raw_byte(c);
+            let token_kind = if c.is_ascii_whitespace() || raw_byte.is_some() {
                 TokenKind::Whitespace
             } else {
                 if !last_line_token.contains(char_index as _) {
@@ -373,16 +693,7 @@ fn draw_buffer_view(
                 last_line_token.kind
             };
 
-            let text_color = match token_kind {
-                TokenKind::Keyword => ctx.editor.theme.token_keyword,
-                TokenKind::Type => ctx.editor.theme.token_type,
-                TokenKind::Symbol => ctx.editor.theme.token_symbol,
-                TokenKind::Literal => ctx.editor.theme.token_literal,
-                TokenKind::String => ctx.editor.theme.token_string,
-                TokenKind::Comment => ctx.editor.theme.token_comment,
-                TokenKind::Text => ctx.editor.theme.token_text,
-                TokenKind::Whitespace => ctx.editor.theme.token_whitespace,
-            };
+            let text_color = ctx.editor.theme.token_color(token_kind);
 
             if current_cursor_index < cursors_end_index && current_cursor_range.to < char_position {
                 current_cursor_index += 1;
@@ -418,72 +729,70 @@ fn draw_buffer_view(
                 }
             }
 
-            if char_position == current_cursor_position {
-                if draw_state != DrawState::Cursor {
-                    draw_state = DrawState::Cursor;
-                    set_background_color(buf, cursor_color);
-                    set_foreground_color(buf, text_color);
-                }
+            let colors = if char_position == current_cursor_position {
+                (cursor_color, text_color)
             } else if inside_cursor_range {
-                if draw_state != DrawState::Selection(token_kind) {
-                    draw_state = DrawState::Selection(token_kind);
-                    set_background_color(buf, text_color);
-                    set_foreground_color(buf, background_color);
-                }
+                (text_color, background_color)
             } else if inside_search_range {
-                if draw_state != DrawState::Highlight {
-                    draw_state = DrawState::Highlight;
-                    set_background_color(buf, ctx.editor.theme.highlight);
-                    set_foreground_color(buf, background_color);
-                }
-            } else if draw_state != DrawState::Token(token_kind) {
-                draw_state = DrawState::Token(token_kind);
-                set_background_color(buf, background_color);
-                set_foreground_color(buf, text_color);
-            }
-
-            let previous_x = x;
-            let previous_buf_len = buf.len();
+                (ctx.editor.theme.highlight, background_color)
+            } else {
+                (background_color, text_color)
+            };
 
-            match c {
-                '\n' => {
-                    x += 1;
-                    buf.push(b' ');
+            // tabs are drawn column by column so they can be partially visible
+            let (column_count, column_len) = match c {
+                '\t' => (tab_size as usize, 1),
+                '\n' | ' ' => (1, 1),
+                c => (1, char_display_len(c) as usize),
+            };
+            for column_index in 0..column_count {
+                let char_column = column;
+                column += column_len;
+                if char_column < horizontal_scroll && column <= horizontal_scroll {
+                    continue;
+                }
+                if x + column_len > text_width {
+                    if layout.wrap == WrapMode::None {
+                        break 'chars;
+                    }
+                    continue;
                 }
-                ' ' => {
-                    x += 1;
-                    buf.extend_from_slice(visual_space);
+
+                if current_colors != colors {
+                    current_colors = colors;
+                    set_background_color(buf, colors.0);
+                    set_foreground_color(buf, colors.1);
                 }
-                '\t' => {
-                    x += tab_size as usize;
 
-                    buf.extend_from_slice(visual_tab_first);
-                    for _ in 0..tab_size - 1 {
-                        buf.extend_from_slice(visual_tab_repeat);
+                // a wide char that is only partially scrolled into view
+                if char_column < horizontal_scroll {
+                    for _ in horizontal_scroll..column {
+                        buf.push(b' ');
                     }
+                    x += column - horizontal_scroll;
+                    continue;
                 }
-                _ => {
-                    x += 1;
-                    buf.extend_from_slice(c.encode_utf8(&mut char_buf).as_bytes());
-                }
-            }
 
-            if x > ctx.viewport_size.0 as _ {
-                x -= ctx.viewport_size.0 as usize;
-                lines_drawn_count += 1;
-                if lines_drawn_count > draw_height {
-                    lines_drawn_count = draw_height;
-                    buf.truncate(previous_buf_len);
-                    x = previous_x;
-                    break;
+                x += column_len;
+                match c {
+                    '\n' => buf.push(b' '),
+                    ' ' => buf.extend_from_slice(visual_space),
+                    '\t' if column_index == 0 => buf.extend_from_slice(visual_tab_first),
+                    '\t' => buf.extend_from_slice(visual_tab_repeat),
+                    _ => match raw_byte {
+                        Some(byte) => {
+                            use io::Write;
+                            let _ = write!(buf, "\\x{:02x}", byte);
+                        }
+                        None => buf.extend_from_slice(c.encode_utf8(&mut char_buf).as_bytes()),
+                    },
                 }
             }
         }
 
-        scroll_offset.column_byte_index = 0;
         set_background_color(buf, background_color);
 
-        if x < ctx.viewport_size.0 as _ {
+        if x < text_width {
             clear_until_new_line(buf);
         }
 
@@ -514,7 +823,7 @@ fn draw_picker(ctx: &RenderContext, buf: &mut Vec<u8>) {
         .editor
         .picker
         .len()
-        .min(ctx.editor.config.picker_max_height as _);
+        .min(ctx.config.picker_max_height as _);
 
     let background_normal_color = ctx.editor.theme.statusbar_inactive_background;
     let background_selected_color = ctx.editor.theme.statusbar_active_background;
@@ -542,23 +851,30 @@ fn draw_picker(ctx: &RenderContext, buf: &mut Vec<u8>) {
         fn print_char(buf: &mut Vec<u8>, x: &mut usize, c: char) {
             let mut char_buf = [0; std::mem::size_of::<char>()];
 
-            *x += 1;
+            *x += char_display_len(c) as usize;
             match c {
                 '\t' => buf.push(b' '),
                 c => buf.extend_from_slice(c.encode_utf8(&mut char_buf).as_bytes()),
             }
         }
 
-        let name_char_count = entry.chars().count();
-        if name_char_count < width {
+        let name_display_len: usize = entry.chars().map(|c| char_display_len(c) as usize).sum();
+        if name_display_len < width {
             for c in entry.chars() {
                 print_char(buf, &mut x, c);
             }
         } else {
             buf.extend_from_slice(b"...");
             x += 3;
-            let name_char_count = name_char_count + 3;
-            for c in entry.chars().skip(name_char_count.saturating_sub(width)) {
+            let mut skip_len = (name_display_len + 3).saturating_sub(width);
+            let mut chars = entry.chars();
+            while skip_len > 0 {
+                match chars.next() {
+                    Some(c) => skip_len = skip_len.saturating_sub(char_display_len(c) as _),
+                    None => break,
+                }
+            }
+            for c in chars {
                 print_char(buf, &mut x, c);
             }
         }
@@ -581,6 +897,9 @@ fn draw_statusbar(
 ) {
     let view_name;
     let needs_save;
+    let read_only;
+    let large_file;
+    let read_progress;
     let main_cursor;
     let cursor_count;
     let search_ranges;
@@ -592,6 +911,9 @@ fn draw_statusbar(
 
             view_name = buffer.path.to_str().unwrap_or("");
             needs_save = buffer.needs_save();
+            read_only = buffer.properties.read_only;
+            large_file = buffer.is_large_file();
+            read_progress = buffer.read_progress();
             main_cursor = *buffer_view.cursors.main_cursor();
             cursor_count = buffer_view.cursors[..].len();
             search_ranges = buffer.search_ranges();
@@ -599,6 +921,9 @@ fn draw_statusbar(
         None => {
             view_name = "";
             needs_save = false;
+            read_only = false;
+            large_file = false;
+            read_progress = None;
             main_cursor = Cursor::zero();
             cursor_count = 1;
             search_ranges = &[];
@@ -700,11 +1025,18 @@ fn draw_statusbar(
     };
 
     if let Some(x) = x {
-        fn take_chars(s: &str, char_count: usize) -> (usize, &str) {
-            match s.char_indices().rev().enumerate().take(char_count).last() {
-                Some((char_index, (byte_index, _))) => (char_index + 1, &s[byte_index..]),
-                None => (0, s),
+        fn take_display_len(s: &str, display_len: usize) -> (usize, &str) {
+            let mut taken_len = 0;
+            let mut taken_index = s.len();
+            for (i, c) in s.char_indices().rev() {
+                let c_len = char_display_len(c) as usize;
+                if taken_len + c_len > display_len {
+                    break;
+                }
+                taken_len += c_len;
+                taken_index = i;
             }
+            (taken_len, &s[taken_index..])
         }
 
         let available_width = ctx.viewport_size.0 as usize - x;
@@ -727,11 +1059,11 @@ fn draw_statusbar(
             buf.push(b'*');
         }
 
-        let (char_count, view_name) = take_chars(view_name, half_available_width);
-        if char_count == half_available_width {
+        let (_, view_name_tail) = take_display_len(view_name, half_available_width);
+        if view_name_tail.len() < view_name.len() {
             buf.extend_from_slice(TOO_LONG_PREFIX);
         }
-        buf.extend_from_slice(view_name.as_bytes());
+        buf.extend_from_slice(view_name_tail.as_bytes());
 
         if !view_name.is_empty() {
             if main_cursor.anchor != main_cursor.position {
@@ -743,6 +1075,14 @@ fn draw_statusbar(
         if cursor_count > 1 {
             let _ = write!(buf, " [{}]", cursor_count);
         }
+        if let Some(progress) = read_progress {
+            let _ = write!(buf, " [reading {}%]", progress);
+        } else if read_only {
+            buf.extend_from_slice(b" [read-only]");
+        }
+        if large_file {
+            buf.extend_from_slice(b" [large file]");
+        }
         buf.push(b' ');
 
         let status = match std::str::from_utf8(&buf[status_start_index..]) {
@@ -754,10 +1094,11 @@ fn draw_statusbar(
         };
 
         let available_width_minus_prefix = available_width.saturating_sub(TOO_LONG_PREFIX.len());
-        let (char_count, status) = take_chars(status, available_width_minus_prefix);
+        let full_status_len = status.len();
+        let (status_display_len, status) = take_display_len(status, available_width_minus_prefix);
 
         let status_len = status.len();
-        let buf_len = status_start_index + status_len + available_width - char_count;
+        let buf_len = status_start_index + status_len + available_width - status_display_len;
         buf.resize(buf_len, 0);
         buf.copy_within(
             status_start_index..status_start_index + status_len,
@@ -766,7 +1107,7 @@ fn draw_statusbar(
         for b in &mut buf[status_start_index..buf_len - status_len] {
             *b = b' ';
         }
-        if char_count == available_width_minus_prefix {
+        if status_len < full_status_len {
             let start_index = buf_len - status_len - TOO_LONG_PREFIX.len();
             let (prefix, rest) = buf[start_index..].split_at_mut(TOO_LONG_PREFIX.len());
             prefix.copy_from_slice(TOO_LONG_PREFIX);
@@ -789,3 +1130,148 @@ fn draw_statusbar(
 
     clear_until_new_line(buf);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(wrap: WrapMode, wrap_indentation: bool, text_width: usize) -> LineLayout {
+        LineLayout {
+            wrap,
+            wrap_indentation,
+            tab_size: 4,
+            gutter_width: 0,
+            text_width,
+        }
+    }
+
+    fn rows<'a>(layout: &LineLayout, line: &'a str) -> Vec<&'a str> {
+        layout.rows(line).map(|r| &line[r]).collect()
+    }
+
+    #[test]
+    fn gutter_widths() {
+        assert_eq!(0, gutter_width(LineNumbers::None, false, 100, 80));
+        assert_eq!(2, gutter_width(LineNumbers::None, true, 100, 80));
+
+        assert_eq!(4, gutter_width(LineNumbers::Absolute, false, 1, 80));
+        assert_eq!(4, gutter_width(LineNumbers::Absolute, false, 9, 80));
+        assert_eq!(4, gutter_width(LineNumbers::Absolute, false, 99, 80));
+        assert_eq!(5, gutter_width(LineNumbers::Absolute, false, 100, 80));
+        assert_eq!(7, gutter_width(LineNumbers::Absolute, true, 12345, 80));
+        assert_eq!(6, gutter_width(LineNumbers::Relative, false, 1000, 80));
+        assert_eq!(6, gutter_width(LineNumbers::Hybrid, false, 1000, 80));
+
+        assert_eq!(0, gutter_width(LineNumbers::Absolute, false, 100, 10));
+        assert_eq!(5, gutter_width(LineNumbers::Absolute, false, 100, 11));
+        assert_eq!(0, gutter_width(LineNumbers::None, true, 100, 4));
+    }
+
+    #[test]
+    fn gutter_line_numbers() {
+        assert_eq!(4, gutter_line_number(LineNumbers::Absolute, 3, 5));
+        assert_eq!(6, gutter_line_number(LineNumbers::Absolute, 5, 5));
+        assert_eq!(2, gutter_line_number(LineNumbers::Relative, 3, 5));
+        assert_eq!(2, gutter_line_number(LineNumbers::Relative, 7, 5));
+        assert_eq!(0, gutter_line_number(LineNumbers::Relative, 5, 5));
+        assert_eq!(2, gutter_line_number(LineNumbers::Hybrid, 3, 5));
+        assert_eq!(6, gutter_line_number(LineNumbers::Hybrid, 5, 5));
+    }
+
+    #[test]
+    fn line_rows() {
+        let char_layout = layout(WrapMode::Char, false, 8);
+        assert_eq!(vec![""], rows(&char_layout, ""));
+        assert_eq!(vec!["abc"], rows(&char_layout, "abc"));
+        assert_eq!(vec!["abcdefg"], rows(&char_layout, "abcdefg"));
+        assert_eq!(vec!["abcdefgh", ""], rows(&char_layout, "abcdefgh"));
+        assert_eq!(vec!["abc defg", "h ij"], rows(&char_layout, "abc defgh ij"));
+        assert_eq!(vec!["a\tbcd", "e"], rows(&char_layout, "a\tbcde"));
+        assert_eq!(vec!["abc中文", "中"], rows(&char_layout, "abc中文中"));
+
+        let none_layout = layout(WrapMode::None, false, 8);
+        assert_eq!(vec!["abc defgh ij"], rows(&none_layout, "abc defgh ij"));
+
+        let word_layout = layout(WrapMode::Word, false, 8);
+        assert_eq!(
+            vec!["abc ", "defgh ", "ij"],
+            rows(&word_layout, "abc defgh ij")
+        );
+        assert_eq!(vec!["abcdefgh", "ijkl"], rows(&word_layout, "abcdefghijkl"));
+        assert_eq!(vec!["abc defg", " hi"], rows(&word_layout, "abc defg hi"));
+
+        let indentation_layout = layout(WrapMode::Word, true, 10);
+        let mut line_rows = indentation_layout.rows("  abc def ghi");
+        assert_eq!(Some(0..10), line_rows.next());
+        assert_eq!(3, line_rows.continuation_prefix_len());
+        assert_eq!(Some(10..13), line_rows.next());
+        assert_eq!(None, line_rows.next());
+
+        let indentation_layout = layout(WrapMode::Char, true, 6);
+        let line_rows = indentation_layout.rows("    abcdef");
+        assert_eq!(0, line_rows.continuation_prefix_len());
+    }
+
+    #[test]
+    fn line_rows_without_wrap() {
+        let none_layout = layout(WrapMode::None, true, 8);
+        assert_eq!(vec![""], rows(&none_layout, ""));
+        assert_eq!(vec!["abcdefghijkl"], rows(&none_layout, "abcdefghijkl"));
+        assert_eq!(vec!["中文中文中文"], rows(&none_layout, "中文中文中文"));
+        assert_eq!(vec!["\t\t\tx"], rows(&none_layout, "\t\t\tx"));
+        assert_eq!(
+            0,
+            none_layout
+                .rows("    abcdefghijkl")
+                .continuation_prefix_len()
+        );
+    }
+
+    #[test]
+    fn line_rows_wide_chars_and_tabs() {
+        let char_layout = layout(WrapMode::Char, false, 8);
+        assert_eq!(vec!["中文中文", "中"], rows(&char_layout, "中文中文中"));
+        assert_eq!(vec!["abcdefg", "中"], rows(&char_layout, "abcdefg中"));
+        assert_eq!(vec!["\t\t", "\tx"], rows(&char_layout, "\t\t\tx"));
+        assert_eq!(vec!["abcdef", "\tg"], rows(&char_layout, "abcdef\tg"));
+
+        let word_layout = layout(WrapMode::Word, false, 8);
+        assert_eq!(
+            vec!["中文 ", "中文中", "文"],
+            rows(&word_layout, "中文 中文中文")
+        );
+        assert_eq!(vec!["ab\t", "cdefgh"], rows(&word_layout, "ab\tcdefgh"));
+    }
+
+    #[test]
+    fn line_rows_words_longer_than_width() {
+        let word_layout = layout(WrapMode::Word, false, 8);
+        assert_eq!(
+            vec!["abcdefgh", "ijklmno", "pqrst"],
+            rows(&word_layout, "abcdefghijklmnopqrst")
+        );
+        assert_eq!(
+            vec!["ab ", "cdefghi", "jklm"],
+            rows(&word_layout, "ab cdefghijklm")
+        );
+
+        let char_layout = layout(WrapMode::Char, false, 8);
+        assert_eq!(
+            vec!["ab cdefg", "hijklm"],
+            rows(&char_layout, "ab cdefghijklm")
+        );
+    }
+
+    #[test]
+    fn line_height() {
+        let word_layout = layout(WrapMode::Word, false, 8);
+        let line = "abc defgh ij";
+        assert_eq!(3, word_layout.line_height(line, DisplayLen::from(line)));
+        let line = "abc";
+        assert_eq!(1, word_layout.line_height(line, DisplayLen::from(line)));
+
+        let none_layout = layout(WrapMode::None, false, 8);
+        let line = "abcdefghijklmnop";
+        assert_eq!(1, none_layout.line_height(line, DisplayLen::from(line)));
+    }
+}
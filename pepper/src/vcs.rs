@@ -0,0 +1,351 @@
+use std::{
+    fmt::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::{
+    buffer::{BufferContent, BufferHandle},
+    buffer_position::BufferRange,
+    diff::{diff, DiffHunk},
+    editor_utils::hash_bytes,
+    platform::{Platform, PlatformProcessHandle, PlatformRequest, ProcessTag},
+};
+
+// lines that were touched by an edit are rehashed only when the diff is updated
+const DIRTY_LINE_HASH: u64 = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VcsLineChange {
+    Added,
+    Modified,
+    // some lines were deleted right after this one
+    Deleted,
+}
+
+// tracks how a buffer's content differs from its file's version at the vcs `HEAD`.
+// that version is read from the output of `git cat-file --batch` given `HEAD:<path>` as input
+pub struct BufferVcsDiff {
+    process_id: u32,
+    process_input: String,
+    process_output: Vec<u8>,
+    has_base: bool,
+    base: BufferContent,
+    base_line_hashes: Vec<u64>,
+    line_hashes: Vec<u64>,
+    needs_update: bool,
+    hunks: Vec<DiffHunk>,
+}
+impl BufferVcsDiff {
+    pub(crate) fn new() -> Self {
+        Self {
+            process_id: 0,
+            process_input: String::new(),
+            process_output: Vec::new(),
+            has_base: false,
+            base: BufferContent::new(),
+            base_line_hashes: Vec::new(),
+            line_hashes: Vec::new(),
+            needs_update: false,
+            hunks: Vec::new(),
+        }
+    }
+
+    pub fn hunks(&self) -> &[DiffHunk] {
+        &self.hunks
+    }
+
+    pub fn base(&self) -> &BufferContent {
+        &self.base
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.process_id = self.process_id.wrapping_add(1);
+        self.process_output.clear();
+        self.has_base = false;
+        self.base_line_hashes.clear();
+        self.line_hashes.clear();
+        self.needs_update = false;
+        self.hunks.clear();
+    }
+
+    // the previous markers are kept until the new `HEAD` version arrives
+    pub(crate) fn spawn_base_process(
+        &mut self,
+        platform: &mut Platform,
+        buffer_handle: BufferHandle,
+        path: &Path,
+    ) {
+        let (directory, file_name) = match (path.parent(), path.file_name()) {
+            (Some(directory), Some(file_name)) => (directory, file_name),
+            _ => return,
+        };
+        let file_name = match file_name.to_str() {
+            Some(file_name) => file_name,
+            None => return,
+        };
+
+        self.process_id = self.process_id.wrapping_add(1);
+        self.process_input.clear();
+        let _ = writeln!(self.process_input, "HEAD:./{}", file_name);
+        self.process_output.clear();
+
+        let mut command = Command::new("git");
+        command.args(["cat-file", "--batch"]);
+        if !directory.as_os_str().is_empty() {
+            command.current_dir(directory);
+        }
+        command.stdin(Stdio::piped());
+        command.stdout(Stdio::piped());
+        command.stderr(Stdio::null());
+
+        platform.requests.enqueue(PlatformRequest::SpawnProcess {
+            tag: ProcessTag::VcsBase {
+                buffer_handle,
+                id: self.process_id,
+            },
+            command,
+            buf_len: 16 * 1024,
+        });
+    }
+
+    pub(crate) fn on_process_spawned(
+        &mut self,
+        platform: &mut Platform,
+        id: u32,
+        handle: PlatformProcessHandle,
+    ) {
+        if id == self.process_id {
+            let mut buf = platform.buf_pool.acquire();
+            buf.write().extend_from_slice(self.process_input.as_bytes());
+            platform
+                .requests
+                .enqueue(PlatformRequest::WriteToProcess { handle, buf });
+        }
+        platform
+            .requests
+            .enqueue(PlatformRequest::CloseProcessInput { handle });
+    }
+
+    pub(crate) fn on_process_output(&mut self, id: u32, bytes: &[u8]) {
+        if id == self.process_id {
+            self.process_output.extend_from_slice(bytes);
+        }
+    }
+
+    // a file that is not tracked (or not inside a repository) gets no markers
+    pub(crate) fn on_process_exit(&mut self, id: u32, content: &BufferContent) {
+        if id != self.process_id {
+            return;
+        }
+
+        let mut base_bytes = match parse_cat_file_blob(&self.process_output) {
+            Some(bytes) => bytes,
+            None => {
+                self.clear();
+                return;
+            }
+        };
+        let base_is_empty = base_bytes.is_empty();
+        if self.base.read(&mut base_bytes).is_err() {
+            self.clear();
+            return;
+        }
+        self.process_output.clear();
+
+        // a file that is empty at `HEAD` has no lines, so all of its current lines are added
+        self.base_line_hashes.clear();
+        if !base_is_empty {
+            for line in self.base.lines() {
+                self.base_line_hashes
+                    .push(hash_bytes(line.as_str().as_bytes()));
+            }
+        }
+
+        self.has_base = true;
+        self.line_hashes.clear();
+        self.needs_update = true;
+        self.update(content);
+    }
+
+    pub(crate) fn insert_range(&mut self, range: BufferRange) {
+        if !self.has_base {
+            return;
+        }
+
+        let from = range.from.line_index as usize;
+        let to = range.to.line_index as usize;
+        if from < self.line_hashes.len() {
+            self.line_hashes[from] = DIRTY_LINE_HASH;
+            self.line_hashes
+                .splice(from + 1..from + 1, (from..to).map(|_| DIRTY_LINE_HASH));
+        }
+        self.needs_update = true;
+    }
+
+    pub(crate) fn delete_range(&mut self, range: BufferRange) {
+        if !self.has_base {
+            return;
+        }
+
+        let from = range.from.line_index as usize;
+        let to = range.to.line_index as usize;
+        if to < self.line_hashes.len() {
+            self.line_hashes[from] = DIRTY_LINE_HASH;
+            self.line_hashes.drain(from + 1..to + 1);
+        }
+        self.needs_update = true;
+    }
+
+    pub(crate) fn update(&mut self, content: &BufferContent) {
+        if !self.has_base || !self.needs_update {
+            return;
+        }
+        self.needs_update = false;
+
+        let lines = content.lines();
+        if self.line_hashes.len() == lines.len() {
+            for (hash, line) in self.line_hashes.iter_mut().zip(lines) {
+                if *hash == DIRTY_LINE_HASH {
+                    *hash = hash_bytes(line.as_str().as_bytes());
+                }
+            }
+        } else {
+            // edits keep `line_hashes` in sync, so this only happens when the base has just arrived
+            self.line_hashes.clear();
+            for line in lines {
+                self.line_hashes.push(hash_bytes(line.as_str().as_bytes()));
+            }
+        }
+
+        diff(&self.base_line_hashes, &self.line_hashes, &mut self.hunks);
+    }
+
+    // the lines where a hunk's marker is drawn
+    pub fn hunk_marker_lines(hunk: &DiffHunk) -> std::ops::Range<usize> {
+        if hunk.new_range.is_empty() {
+            let line_index = hunk.new_range.start.saturating_sub(1);
+            line_index..line_index + 1
+        } else {
+            hunk.new_range.clone()
+        }
+    }
+
+    pub fn hunk_at(&self, line_index: usize) -> Option<&DiffHunk> {
+        let index = self
+            .hunks
+            .partition_point(|h| Self::hunk_marker_lines(h).end <= line_index);
+        let hunk = self.hunks.get(index)?;
+        if Self::hunk_marker_lines(hunk).start <= line_index {
+            Some(hunk)
+        } else {
+            None
+        }
+    }
+
+    pub fn line_change(&self, line_index: usize) -> Option<VcsLineChange> {
+        let hunk = self.hunk_at(line_index)?;
+        if hunk.new_range.is_empty() {
+            Some(VcsLineChange::Deleted)
+        } else if hunk.old_range.is_empty() {
+            Some(VcsLineChange::Added)
+        } else {
+            Some(VcsLineChange::Modified)
+        }
+    }
+}
+
+// `git cat-file --batch` outputs `<object> blob <size>\n<content>\n` for a file tracked at `HEAD`
+// and `<object> missing\n` for one that is not. outside of a repository, nothing is output.
+// this way, a file that is empty at `HEAD` is told apart from an untracked one
+fn parse_cat_file_blob(output: &[u8]) -> Option<&[u8]> {
+    let header_len = output.iter().position(|&b| b == b'\n')?;
+    let header = std::str::from_utf8(&output[..header_len]).ok()?;
+    let mut header = header.split(' ');
+    let _object = header.next()?;
+    if header.next()? != "blob" {
+        return None;
+    }
+    let len: usize = header.next()?.parse().ok()?;
+
+    let start = header_len + 1;
+    output.get(start..start + len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::buffer_position::BufferPosition;
+
+    fn content(text: &str) -> BufferContent {
+        let mut content = BufferContent::new();
+        content.insert_text(BufferPosition::zero(), text);
+        content
+    }
+
+    #[test]
+    fn vcs_diff_line_changes() {
+        let mut content = content("a\nb\nc\nd");
+        let mut vcs_diff = BufferVcsDiff::new();
+        vcs_diff.on_process_output(0, b"0123abcd blob 7\na\nb\nc\nd\n");
+        vcs_diff.on_process_exit(0, &content);
+        assert!(vcs_diff.hunks().is_empty());
+
+        let range = content.insert_text(BufferPosition::line_col(1, 1), "x\ny");
+        vcs_diff.insert_range(range);
+        vcs_diff.update(&content);
+        assert_eq!(None, vcs_diff.line_change(0));
+        assert_eq!(Some(VcsLineChange::Modified), vcs_diff.line_change(1));
+        assert_eq!(Some(VcsLineChange::Modified), vcs_diff.line_change(2));
+        assert_eq!(None, vcs_diff.line_change(3));
+
+        let range = BufferRange::between(
+            BufferPosition::line_col(1, 1),
+            BufferPosition::line_col(2, 1),
+        );
+        content.delete_range(range);
+        vcs_diff.delete_range(range);
+        vcs_diff.update(&content);
+        assert!(vcs_diff.hunks().is_empty());
+
+        let range = BufferRange::between(
+            BufferPosition::line_col(2, 0),
+            BufferPosition::line_col(3, 0),
+        );
+        content.delete_range(range);
+        vcs_diff.delete_range(range);
+        let range = content.insert_text(BufferPosition::line_col(0, 0), "new\n");
+        vcs_diff.insert_range(range);
+        vcs_diff.update(&content);
+        assert_eq!(Some(VcsLineChange::Added), vcs_diff.line_change(0));
+        assert_eq!(None, vcs_diff.line_change(1));
+        assert_eq!(Some(VcsLineChange::Deleted), vcs_diff.line_change(2));
+        assert_eq!(None, vcs_diff.line_change(3));
+    }
+
+    #[test]
+    fn vcs_diff_ignores_stale_processes() {
+        let content = content("a");
+        let mut vcs_diff = BufferVcsDiff::new();
+        vcs_diff.on_process_output(7, b"0123abcd blob 1\nb\n");
+        vcs_diff.on_process_exit(7, &content);
+        assert!(vcs_diff.hunks().is_empty());
+
+        vcs_diff.on_process_exit(0, &content);
+        assert!(vcs_diff.hunks().is_empty());
+    }
+
+    #[test]
+    fn vcs_diff_tells_empty_files_from_untracked_ones() {
+        let content = content("a\nb");
+        let mut vcs_diff = BufferVcsDiff::new();
+        vcs_diff.on_process_output(0, b"0123abcd blob 0\n\n");
+        vcs_diff.on_process_exit(0, &content);
+        assert_eq!(Some(VcsLineChange::Added), vcs_diff.line_change(1));
+
+        vcs_diff.on_process_output(0, b"HEAD:./file missing\n");
+        vcs_diff.on_process_exit(0, &content);
+        assert!(vcs_diff.hunks().is_empty());
+    }
+}
@@ -1020,15 +1020,16 @@ impl Client {
 
         let buffer_path = &editor.buffers.get(buffer_handle).path;
         let text_document = util::text_document_with_id(&self.root, buffer_path, &mut self.json);
+        let config = editor.buffer_config(buffer_handle);
         let mut options = JsonObject::default();
         options.set(
             "tabSize".into(),
-            JsonValue::Integer(editor.config.tab_size as _),
+            JsonValue::Integer(config.tab_size as _),
             &mut self.json,
         );
         options.set(
             "insertSpaces".into(),
-            (!editor.config.indent_with_tabs).into(),
+            (!config.indent_with_tabs).into(),
             &mut self.json,
         );
         options.set("trimTrailingWhitespace".into(), true.into(), &mut self.json);
@@ -651,7 +651,7 @@ pub(crate) fn on_response(
                         .min((location.range.start.line as usize).saturating_sub(context_len - 1));
                     let end = line_count.min(location.range.end.line as usize + context_len);
 
-                    for line in &context_buffer.lines()[start..end] {
+                    for line in context_buffer.lines().range(start..end) {
                         let line = line.as_str();
                         if line.is_empty() {
                             text.push('~');
@@ -387,6 +387,13 @@ impl TextEdit {
         json: &Json,
     ) {
         let buffer = editor.buffers.get_mut(buffer_handle);
+        if buffer.properties.read_only {
+            editor.logger.write(LogKind::Error).fmt(format_args!(
+                "could not apply edits to buffer {:?} because it is read-only",
+                &buffer.path
+            ));
+            return;
+        }
 
         buffer.commit_edits();
         temp_edits.clear();
@@ -637,10 +644,13 @@ impl WorkspaceEdit {
             TextEdit::apply_edits(editor, result.buffer_handle, temp_edits, text_edits, json);
 
             if result.is_new {
-                let _ = editor
-                    .buffers
-                    .get_mut(result.buffer_handle)
-                    .write_to_file(None, editor.events.writer());
+                let save_backup = editor.buffer_config(result.buffer_handle).save_backup;
+                let _ = editor.buffers.get_mut(result.buffer_handle).write_to_file(
+                    None,
+                    false,
+                    save_backup,
+                    editor.events.writer(),
+                );
 
                 editor
                     .buffers
@@ -673,10 +683,13 @@ impl WorkspaceEdit {
                     );
 
                     if result.is_new {
-                        let _ = editor
-                            .buffers
-                            .get_mut(result.buffer_handle)
-                            .write_to_file(None, editor.events.writer());
+                        let save_backup = editor.buffer_config(result.buffer_handle).save_backup;
+                        let _ = editor.buffers.get_mut(result.buffer_handle).write_to_file(
+                            None,
+                            false,
+                            save_backup,
+                            editor.events.writer(),
+                        );
 
                         editor
                             .buffers